@@ -1,4 +1,5 @@
 pub mod General {
+    use crate::Bounds3D::Bounds::{Aabb, BoundingSphere};
 
     fn protate_X(p: [f32; 3], a: f32) -> [f32; 3] {
         return [p[0], p[1]*a.cos() - p[2]*a.sin(), p[1]*a.sin() + p[2]*a.cos()];
@@ -24,6 +25,10 @@ pub mod General {
         return (p[0]*p[0] + p[1]*p[1] + p[2]*p[2]).sqrt();
     }
 
+    fn pdot(p0: [f32; 3], p1: [f32; 3]) -> f32 {
+        return p0[0]*p1[0] + p0[1]*p1[1] + p0[2]*p1[2];
+    }
+
     fn vcross(v0: [f32; 3], v1: [f32; 3]) -> [f32; 3] {
         return [
             v0[1] * v1[2] - v0[2] * v1[1],
@@ -95,6 +100,26 @@ pub mod General {
     }
     implement_vertex!(Normal, normal);
 
+    /**
+     * Tangent frame of a vertex for normal mapping. `tangent` points along +u of the texture, its `w` is the
+     * handedness of the texture mapping, so the bitangent along +v is `w * cross(normal, tangent.xyz)`
+     * with the normal of the vertex. Generated by `Shape3D::generate_tangents`.
+     */
+    #[derive(Copy, Clone)]
+    pub struct Tangent {
+        pub tangent: [f32; 4]
+    }
+    implement_vertex!(Tangent, tangent);
+
+    /*
+    Any unit vector perpendicular to a normal, for vertices without a usable texture mapping
+    */
+    fn perpendicular(n: [f32; 3]) -> [f32; 3] {
+        let axis = if n[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] };
+        let t = vcross(axis, n);
+        return pscale(t, 1.0 / plength(t));
+    }
+
     /*
      * General shapes 
      */
@@ -105,6 +130,75 @@ pub mod General {
         fn get_mut_vertices(&mut self) -> &mut Vec<Vertex>;
         fn get_mut_normals(&mut self) -> &mut Vec<Normal>;
 
+        /**
+         * Per vertex tangents following the MikkTSpace conventions. Every triangle contributes its texture
+         * space directions, weighted by its angle at the corner, to all corners sharing position, normal,
+         * texture coordinates and handedness, so tangents are smooth wherever normals are and split at mirrored
+         * texture seams. Tangents are orthogonalized against the normals, bitangents are left to the shader.
+         */
+        fn generate_tangents(&self) -> Vec<Tangent> {
+            let vertices = self.get_vertices();
+            let normals = self.get_normals();
+            let bits = |f: f32| -> u32 { if f == 0.0 { 0 } else { f.to_bits() } };
+
+            let mut groups: std::collections::HashMap<[u32; 9], usize> = std::collections::HashMap::new();
+            let mut corner_groups: Vec<usize> = Vec::with_capacity(vertices.len());
+            let mut sums: Vec<([f32; 3], [f32; 3])> = Vec::new();
+
+            for i in 0..(vertices.len() / 3) {
+                let p = [vertices[3*i].position, vertices[3*i + 1].position, vertices[3*i + 2].position];
+                let uv = [vertices[3*i].texture, vertices[3*i + 1].texture, vertices[3*i + 2].texture];
+                let e1 = ptranslate(p[1], pscale(p[0], -1.0));
+                let e2 = ptranslate(p[2], pscale(p[0], -1.0));
+                let (du1, dv1) = (uv[1][0] - uv[0][0], uv[1][1] - uv[0][1]);
+                let (du2, dv2) = (uv[2][0] - uv[0][0], uv[2][1] - uv[0][1]);
+                let det = du1 * dv2 - du2 * dv1;
+
+                // Directions of +u and +v on the triangle, nothing for degenerate texture mappings
+                let (t, b) = if det.abs() > 1e-12 {
+                    (pscale(ptranslate(pscale(e1, dv2), pscale(e2, -dv1)), 1.0 / det),
+                     pscale(ptranslate(pscale(e2, du1), pscale(e1, -du2)), 1.0 / det))
+                } else {
+                    ([0.0; 3], [0.0; 3])
+                };
+                let t = if plength(t) > 0.0 { pscale(t, 1.0 / plength(t)) } else { t };
+                let b = if plength(b) > 0.0 { pscale(b, 1.0 / plength(b)) } else { b };
+
+                for j in 0..3 {
+                    let vertex = vertices[3*i + j];
+                    let normal = normals[3*i + j].normal;
+                    let key = [
+                        bits(vertex.position[0]), bits(vertex.position[1]), bits(vertex.position[2]),
+                        bits(normal[0]), bits(normal[1]), bits(normal[2]),
+                        bits(vertex.texture[0]), bits(vertex.texture[1]),
+                        (det < 0.0) as u32,
+                    ];
+                    let group = *groups.entry(key).or_insert_with(|| {
+                        sums.push(([0.0; 3], [0.0; 3]));
+                        return sums.len() - 1;
+                    });
+                    corner_groups.push(group);
+
+                    let a = ptranslate(p[(j + 1) % 3], pscale(p[j], -1.0));
+                    let c = ptranslate(p[(j + 2) % 3], pscale(p[j], -1.0));
+                    let cos_angle = pdot(a, c) / (plength(a) * plength(c)).max(1e-20);
+                    let angle = cos_angle.clamp(-1.0, 1.0).acos();
+                    sums[group].0 = ptranslate(sums[group].0, pscale(t, angle));
+                    sums[group].1 = ptranslate(sums[group].1, pscale(b, angle));
+                }
+            }
+
+            return corner_groups.iter().zip(normals.iter()).map(|(group, normal)| {
+                let (t, b) = sums[*group];
+                let length = plength(normal.normal);
+                let n = if length > 0.0 { pscale(normal.normal, 1.0 / length) } else { [0.0, 1.0, 0.0] };
+                let t = ptranslate(t, pscale(n, -pdot(n, t)));
+                let t = if plength(t) > 1e-6 { pscale(t, 1.0 / plength(t)) } else { perpendicular(n) };
+                let w = if pdot(vcross(n, t), b) < 0.0 { -1.0 } else { 1.0 };
+                return Tangent { tangent: [t[0], t[1], t[2], w] };
+            }).collect();
+        }
+
         fn rotate(&self, angle_XYZ: [f32; 3]) -> AShape {
 
             let mut vertices: Vec<Vertex> = Vec::new();
@@ -257,6 +351,21 @@ pub mod General {
             return [sum[0]/(n as f32), sum[1]/(n as f32), sum[2]/(n as f32)];
         }
 
+        /**
+         * Axis aligned box around all vertices, use `Aabb::transform` to place it with a model matrix
+         */
+        fn bounding_box(&self) -> Aabb {
+            return Aabb::from_points(self.get_vertices().iter().map(|vertex| vertex.position));
+        }
+
+        /**
+         * Sphere around all vertices, use `BoundingSphere::transform` to place it with a model matrix
+         */
+        fn bounding_sphere(&self) -> BoundingSphere {
+            let positions: Vec<[f32; 3]> = self.get_vertices().iter().map(|vertex| vertex.position).collect();
+            return BoundingSphere::from_points(&positions);
+        }
+
         fn subdivide_mut(&mut self) {
 
             // Subdivide all triangles
@@ -362,6 +471,22 @@ pub mod General {
 
         }
 
+        /*
+        Gives every vertex the normal of its triangle, after the vertices were moved
+        */
+        fn flat_normals_mut(&mut self) {
+            for i in 0..(self.get_vertices().len()/3) {
+                let p: Vec<[f32; 3]> = (0..3).map(|j| self.get_vertices()[3*i + j].position).collect();
+                let norm = vcross(
+                    ptranslate(p[1], pscale(p[0], -1f32)),
+                    ptranslate(p[2], pscale(p[1], -1f32))
+                );
+                for j in 0..3 {
+                    self.get_mut_normals()[3*i + j].normal = norm;
+                }
+            }
+        }
+
     }
 
     #[derive(Clone)]
@@ -742,4 +867,69 @@ pub mod General {
         }
     }
 
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /*
+        Directions of +u and +v across a triangle, solved from its edges and texture coordinates
+        */
+        fn texture_directions(shape: &AShape, triangle: usize) -> ([f32; 3], [f32; 3]) {
+            let v = &shape.vertices[3*triangle .. 3*triangle + 3];
+            let e1 = ptranslate(v[1].position, pscale(v[0].position, -1.0));
+            let e2 = ptranslate(v[2].position, pscale(v[0].position, -1.0));
+            let (du1, dv1) = (v[1].texture[0] - v[0].texture[0], v[1].texture[1] - v[0].texture[1]);
+            let (du2, dv2) = (v[2].texture[0] - v[0].texture[0], v[2].texture[1] - v[0].texture[1]);
+            let det = du1 * dv2 - du2 * dv1;
+            return (pscale(ptranslate(pscale(e1, dv2), pscale(e2, -dv1)), 1.0 / det),
+                    pscale(ptranslate(pscale(e2, du1), pscale(e1, -du2)), 1.0 / det));
+        }
+
+        /*
+        Checks that every tangent is a unit vector orthogonal to its normal, pointing along +u,
+        with a handedness that turns it towards +v
+        */
+        fn assert_tangents_follow_the_texture(shape: &AShape) {
+            let tangents = shape.generate_tangents();
+            assert_eq!(tangents.len(), shape.vertices.len());
+            for (i, tangent) in tangents.iter().enumerate() {
+                let t = [tangent.tangent[0], tangent.tangent[1], tangent.tangent[2]];
+                let w = tangent.tangent[3];
+                let n = pscale(shape.normals[i].normal, 1.0 / plength(shape.normals[i].normal));
+                let (u, v) = texture_directions(shape, i / 3);
+                assert!((plength(t) - 1.0).abs() < 1e-5, "tangent {} has length {}", i, plength(t));
+                assert!(pdot(t, n).abs() < 1e-5, "tangent {} is not orthogonal to its normal", i);
+                assert!(w == 1.0 || w == -1.0, "tangent {} has handedness {}", i, w);
+                assert!(pdot(t, u) > 0.0, "tangent {} points away from +u", i);
+                assert!(pdot(pscale(vcross(n, t), w), v) > 0.0, "bitangent {} points away from +v", i);
+            }
+        }
+
+        #[test]
+        fn quad_tangents_point_along_u() {
+            let quad = Quad::new([0.0, 0.0, 0.0], [[2.0, 0.0, 0.0], [0.0, 0.0, 3.0]], 0);
+            assert_tangents_follow_the_texture(&quad);
+            for tangent in quad.generate_tangents().iter() {
+                assert!(pdot([tangent.tangent[0], tangent.tangent[1], tangent.tangent[2]], [1.0, 0.0, 0.0]) > 0.9999);
+            }
+        }
+
+        #[test]
+        fn mirrored_quad_flips_handedness() {
+            let quad = Quad::new([0.0, 0.0, 0.0], [[2.0, 0.0, 0.0], [0.0, 0.0, 3.0]], 0);
+            let mut mirrored = quad.clone();
+            for vertex in mirrored.vertices.iter_mut() {
+                vertex.texture[0] = 1.0 - vertex.texture[0];
+            }
+            assert_tangents_follow_the_texture(&mirrored);
+            for (tangent, mirrored) in quad.generate_tangents().iter().zip(mirrored.generate_tangents().iter()) {
+                assert_eq!(tangent.tangent[3], -mirrored.tangent[3]);
+            }
+        }
+
+        #[test]
+        fn cube_tangents_follow_every_face() {
+            assert_tangents_follow_the_texture(&Cube::new([0.5, -1.0, 2.0], 1.5, 0));
+        }
+    }
 }
\ No newline at end of file
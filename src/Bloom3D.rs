@@ -0,0 +1,113 @@
+pub mod Bloom {
+    use glium::framebuffer::SimpleFrameBuffer;
+    use glium::texture::{MipmapsOption, Texture2d, UncompressedFloatFormat};
+
+    use crate::Screen3D::Screen::*;
+    use crate::Shaders;
+
+    /*
+    Most blur levels, each half the size of the one before, the chain also ends once a level would get smaller than this
+    */
+    const MAX_LEVELS: usize = 6;
+    const MIN_LEVEL_SIZE: u32 = 8;
+
+    /**
+     * Bloom on an HDR image. Colors brighter than `threshold` are blurred over a chain of
+     * downsampled textures, which are added back up and blended onto the image scaled by `intensity`.
+     * `knee` softens the threshold and `radius` spreads the blur of every level, in texels.
+     */
+    pub struct BloomRenderer {
+        downsample_program: glium::Program,
+        upsample_program: glium::Program,
+        screen_quad: ScreenQuad,
+        levels: Vec<Texture2d>,
+        dimensions: (u32, u32),
+        pub enabled: bool,
+        pub threshold: f32,
+        pub knee: f32,
+        pub intensity: f32,
+        pub radius: f32,
+    }
+
+    impl BloomRenderer {
+
+        pub fn new(display: &glium::Display) -> BloomRenderer {
+            return BloomRenderer {
+                downsample_program: glium::Program::from_source(display, Shaders::SCREEN_VERTEX_SHADER, Shaders::BLOOM_DOWNSAMPLE_FRAGMENT_SHADER, None).unwrap(),
+                upsample_program: glium::Program::from_source(display, Shaders::SCREEN_VERTEX_SHADER, Shaders::BLOOM_UPSAMPLE_FRAGMENT_SHADER, None).unwrap(),
+                screen_quad: ScreenQuad::new(display),
+                levels: Vec::new(),
+                dimensions: (0, 0),
+                enabled: true,
+                threshold: 1.0,
+                knee: 0.5,
+                intensity: 0.08,
+                radius: 1.0,
+            }
+        }
+
+        /**
+         * Adds bloom to `hdr` in place
+         */
+        pub fn render(&mut self, display: &glium::Display, hdr: &Texture2d) {
+            if !self.enabled {
+                return;
+            }
+
+            let dimensions = (hdr.width(), hdr.height());
+            if self.dimensions != dimensions {
+                self.create_levels(display, dimensions);
+            }
+            if self.levels.is_empty() {
+                return;
+            }
+
+            // Downsample, keeping only the bright parts of the image in the first level
+            for i in 0..self.levels.len() {
+                let source = if i == 0 { hdr } else { &self.levels[i - 1] };
+                let uniforms = uniform! {
+                    source: get_linear_sampler(source),
+                    prefilter: i == 0,
+                    threshold: self.threshold,
+                    knee: self.knee.max(1e-4),
+                };
+                let mut framebuffer = SimpleFrameBuffer::new(display, &self.levels[i]).unwrap();
+                self.screen_quad.draw(&mut framebuffer, &self.downsample_program, &uniforms, &Default::default()).unwrap();
+            }
+
+            // Upsample, every level adds the blurred smaller level to its own, the largest one ends up on the image
+            let additive = glium::DrawParameters {
+                blend: glium::Blend {
+                    color: glium::BlendingFunction::Addition {
+                        source: glium::LinearBlendingFactor::One,
+                        destination: glium::LinearBlendingFactor::One,
+                    },
+                    alpha: glium::BlendingFunction::AlwaysReplace,
+                    constant_value: (0.0, 0.0, 0.0, 0.0),
+                },
+                .. Default::default()
+            };
+            for i in (0..self.levels.len()).rev() {
+                let destination = if i == 0 { hdr } else { &self.levels[i - 1] };
+                let uniforms = uniform! {
+                    source: get_linear_sampler(&self.levels[i]),
+                    radius: self.radius,
+                    intensity: if i == 0 { self.intensity } else { 1.0 },
+                };
+                let mut framebuffer = SimpleFrameBuffer::new(display, destination).unwrap();
+                self.screen_quad.draw(&mut framebuffer, &self.upsample_program, &uniforms, &additive).unwrap();
+            }
+        }
+
+        fn create_levels(&mut self, display: &glium::Display, dimensions: (u32, u32)) {
+            self.levels.clear();
+            let (mut width, mut height) = (dimensions.0 / 2, dimensions.1 / 2);
+            while self.levels.len() < MAX_LEVELS && width >= MIN_LEVEL_SIZE && height >= MIN_LEVEL_SIZE {
+                self.levels.push(Texture2d::empty_with_format(display, UncompressedFloatFormat::F16F16F16F16, MipmapsOption::NoMipmap, width, height).unwrap());
+                width /= 2;
+                height /= 2;
+            }
+            self.dimensions = dimensions;
+        }
+    }
+}
@@ -0,0 +1,431 @@
+pub mod Bounds {
+    use crate::Math3D::Math::*;
+
+    /**
+     * Axis aligned bounding box. The empty box has `min` above `max` and grows to the first point added to it.
+     */
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub struct Aabb {
+        pub min: [f32; 3],
+        pub max: [f32; 3],
+    }
+
+    impl Aabb {
+
+        pub fn new(min: [f32; 3], max: [f32; 3]) -> Aabb {
+            return Aabb {
+                min: min,
+                max: max,
+            }
+        }
+
+        pub fn empty() -> Aabb {
+            return Aabb::new([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]);
+        }
+
+        pub fn from_points<I: IntoIterator<Item = [f32; 3]>>(points: I) -> Aabb {
+            let mut aabb = Aabb::empty();
+            for point in points {
+                aabb.add_point(point);
+            }
+            return aabb;
+        }
+
+        pub fn is_empty(&self) -> bool {
+            return (0..3).any(|i| self.min[i] > self.max[i]);
+        }
+
+        pub fn add_point(&mut self, point: [f32; 3]) {
+            for i in 0..3 {
+                self.min[i] = self.min[i].min(point[i]);
+                self.max[i] = self.max[i].max(point[i]);
+            }
+        }
+
+        pub fn union(&self, other: &Aabb) -> Aabb {
+            let mut aabb = *self;
+            for i in 0..3 {
+                aabb.min[i] = aabb.min[i].min(other.min[i]);
+                aabb.max[i] = aabb.max[i].max(other.max[i]);
+            }
+            return aabb;
+        }
+
+        /**
+         * The box grown by `margin` on every side
+         */
+        pub fn expand(&self, margin: f32) -> Aabb {
+            if self.is_empty() {
+                return *self;
+            }
+            return Aabb::new(vsub(self.min, [margin; 3]), vadd(self.max, [margin; 3]));
+        }
+
+        pub fn get_center(&self) -> [f32; 3] {
+            if self.is_empty() {
+                return [0.0, 0.0, 0.0];
+            }
+            return vscale(vadd(self.min, self.max), 0.5);
+        }
+
+        /**
+         * Half the size of the box along every axis
+         */
+        pub fn get_extents(&self) -> [f32; 3] {
+            if self.is_empty() {
+                return [0.0, 0.0, 0.0];
+            }
+            return vscale(vsub(self.max, self.min), 0.5);
+        }
+
+        pub fn get_surface_area(&self) -> f32 {
+            let size = vscale(self.get_extents(), 2.0);
+            return 2.0 * (size[0] * size[1] + size[1] * size[2] + size[2] * size[0]);
+        }
+
+        pub fn intersects(&self, other: &Aabb) -> bool {
+            return (0..3).all(|i| self.min[i] <= other.max[i] && other.min[i] <= self.max[i]);
+        }
+
+        /**
+         * Point of the box closest to `point`, `point` itself when it is inside
+         */
+        pub fn closest_point(&self, point: [f32; 3]) -> [f32; 3] {
+            return [
+                point[0].clamp(self.min[0], self.max[0]),
+                point[1].clamp(self.min[1], self.max[1]),
+                point[2].clamp(self.min[2], self.max[2]),
+            ];
+        }
+
+        /**
+         * Box around the transformed box, which is larger than the box around the transformed contents
+         * whenever the transform rotates
+         */
+        pub fn transform(&self, m: &[[f32; 4]; 4]) -> Aabb {
+            if self.is_empty() {
+                return *self;
+            }
+            // Every axis of the matrix moves the bounds by the smaller and larger end of its contribution
+            let mut min = [m[3][0], m[3][1], m[3][2]];
+            let mut max = min;
+            for column in 0..3 {
+                for row in 0..3 {
+                    let a = m[column][row] * self.min[column];
+                    let b = m[column][row] * self.max[column];
+                    min[row] += a.min(b);
+                    max[row] += a.max(b);
+                }
+            }
+            return Aabb::new(min, max);
+        }
+    }
+
+    /**
+     * Bounding sphere, a negative radius marks the empty sphere
+     */
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub struct BoundingSphere {
+        pub center: [f32; 3],
+        pub radius: f32,
+    }
+
+    impl BoundingSphere {
+
+        pub fn new(center: [f32; 3], radius: f32) -> BoundingSphere {
+            return BoundingSphere {
+                center: center,
+                radius: radius,
+            }
+        }
+
+        pub fn empty() -> BoundingSphere {
+            return BoundingSphere::new([0.0, 0.0, 0.0], -1.0);
+        }
+
+        /**
+         * Approximate sphere around points after Ritter, usually 5 to 20 percent larger than the smallest one
+         */
+        pub fn from_points(points: &[[f32; 3]]) -> BoundingSphere {
+            if points.is_empty() {
+                return BoundingSphere::empty();
+            }
+            // Start from two points far apart, the farthest from any point and the farthest from that one
+            let farthest = |from: [f32; 3]| -> [f32; 3] {
+                return *points.iter().max_by(|a, b| vlength(vsub(**a, from)).total_cmp(&vlength(vsub(**b, from)))).unwrap();
+            };
+            let a = farthest(points[0]);
+            let b = farthest(a);
+            let mut sphere = BoundingSphere::new(vscale(vadd(a, b), 0.5), vlength(vsub(b, a)) * 0.5);
+
+            // Grow the sphere just enough to cover every point outside of it
+            for point in points.iter() {
+                let distance = vlength(vsub(*point, sphere.center));
+                if distance > sphere.radius {
+                    let radius = (sphere.radius + distance) * 0.5;
+                    sphere.center = vadd(sphere.center, vscale(vsub(*point, sphere.center), (radius - sphere.radius) / distance));
+                    sphere.radius = radius;
+                }
+            }
+            return sphere;
+        }
+
+        pub fn from_aabb(aabb: &Aabb) -> BoundingSphere {
+            if aabb.is_empty() {
+                return BoundingSphere::empty();
+            }
+            return BoundingSphere::new(aabb.get_center(), vlength(aabb.get_extents()));
+        }
+
+        pub fn is_empty(&self) -> bool {
+            return self.radius < 0.0;
+        }
+
+        /**
+         * The sphere moved by a transform, its radius scaled by the largest scale of the transform
+         */
+        pub fn transform(&self, m: &[[f32; 4]; 4]) -> BoundingSphere {
+            if self.is_empty() {
+                return *self;
+            }
+            return BoundingSphere::new(transform_point(m, self.center), self.radius * get_max_scale(m));
+        }
+    }
+
+    /**
+     * Largest factor a transform scales lengths by, the length of its longest axis
+     */
+    pub fn get_max_scale(m: &[[f32; 4]; 4]) -> f32 {
+        let axis_length = |column: usize| -> f32 { return vlength([m[column][0], m[column][1], m[column][2]]); };
+        return axis_length(0).max(axis_length(1)).max(axis_length(2));
+    }
+
+    /**
+     * Plane of the points `p` with `dot(normal, p) + distance == 0`, the normal points to the positive side
+     */
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub struct Plane {
+        pub normal: [f32; 3],
+        pub distance: f32,
+    }
+
+    impl Plane {
+
+        /**
+         * Plane from the coefficients of `ax + by + cz + d`, normalized so distances are in world units
+         */
+        pub fn from_coefficients(coefficients: [f32; 4]) -> Plane {
+            let normal = [coefficients[0], coefficients[1], coefficients[2]];
+            let length = vlength(normal);
+            return Plane {
+                normal: vscale(normal, 1.0 / length),
+                distance: coefficients[3] / length,
+            }
+        }
+
+        pub fn get_signed_distance(&self, point: [f32; 3]) -> f32 {
+            return vdot(self.normal, point) + self.distance;
+        }
+    }
+
+    /**
+     * Volume seen by a camera, bounded by six planes facing inwards in the order
+     * left, right, bottom, top, near, far
+     */
+    #[derive(Copy, Clone, Debug)]
+    pub struct Frustum {
+        pub planes: [Plane; 6],
+    }
+
+    impl Frustum {
+
+        /**
+         * Frustum of a `perspective * view` matrix, or of `perspective * view * model` for one in model space.
+         * Planes are the sums and differences of the rows of the matrix after Gribb and Hartmann,
+         * for a clip space with z from -w to w.
+         */
+        pub fn from_matrix(m: &[[f32; 4]; 4]) -> Frustum {
+            let row = |i: usize| -> [f32; 4] { return [m[0][i], m[1][i], m[2][i], m[3][i]]; };
+            let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+            let add = |a: [f32; 4], b: [f32; 4]| -> [f32; 4] { return [a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]]; };
+            let sub = |a: [f32; 4], b: [f32; 4]| -> [f32; 4] { return [a[0] - b[0], a[1] - b[1], a[2] - b[2], a[3] - b[3]]; };
+            return Frustum {
+                planes: [
+                    Plane::from_coefficients(add(w, x)),
+                    Plane::from_coefficients(sub(w, x)),
+                    Plane::from_coefficients(add(w, y)),
+                    Plane::from_coefficients(sub(w, y)),
+                    Plane::from_coefficients(add(w, z)),
+                    Plane::from_coefficients(sub(w, z)),
+                ],
+            }
+        }
+
+        pub fn from_view_perspective(view: &[[f32; 4]; 4], perspective: &[[f32; 4]; 4]) -> Frustum {
+            return Frustum::from_matrix(&mat4_mul(perspective, view));
+        }
+
+        /**
+         * Whether a sphere is at least partly inside. Spheres near the corners may be reported inside while they are not.
+         */
+        pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+            if sphere.is_empty() {
+                return false;
+            }
+            return self.planes.iter().all(|plane| plane.get_signed_distance(sphere.center) >= -sphere.radius);
+        }
+
+        /**
+         * Whether a box is at least partly inside, tested with the corner of the box farthest along every plane normal.
+         * Boxes near the corners may be reported inside while they are not.
+         */
+        pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+            if aabb.is_empty() {
+                return false;
+            }
+            return self.planes.iter().all(|plane| {
+                let corner = [
+                    if plane.normal[0] >= 0.0 { aabb.max[0] } else { aabb.min[0] },
+                    if plane.normal[1] >= 0.0 { aabb.max[1] } else { aabb.min[1] },
+                    if plane.normal[2] >= 0.0 { aabb.max[2] } else { aabb.min[2] },
+                ];
+                return plane.get_signed_distance(corner) >= 0.0;
+            });
+        }
+    }
+
+    /**
+     * Deterministic random numbers from 0 to 1 for the tests of the bounding volumes and the BVH
+     */
+    #[cfg(test)]
+    pub struct Random {
+        seed: u32,
+    }
+
+    #[cfg(test)]
+    impl Random {
+        pub fn new(seed: u32) -> Random {
+            return Random { seed: seed };
+        }
+
+        pub fn next(&mut self) -> f32 {
+            self.seed = self.seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            return (self.seed >> 8) as f32 / (1 << 24) as f32;
+        }
+
+        /**
+         * Point in a cube of the given size around the origin
+         */
+        pub fn point(&mut self, size: f32) -> [f32; 3] {
+            return [(self.next() - 0.5) * size, (self.next() - 0.5) * size, (self.next() - 0.5) * size];
+        }
+
+        pub fn aabb(&mut self) -> Aabb {
+            let min = self.point(20.0);
+            return Aabb::new(min, vadd(min, [self.next() * 1.5, self.next() * 1.5, self.next() * 1.5]));
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn assert_close(a: f32, b: f32) {
+            assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+        }
+
+        fn assert_plane(plane: &Plane, normal: [f32; 3], distance: f32) {
+            for i in 0..3 {
+                assert_close(plane.normal[i], normal[i]);
+            }
+            assert_close(plane.distance, distance);
+        }
+
+        /*
+        Scales by 2, 3 and 0.5, rotates a quarter turn around z and moves by (1, 2, 3)
+        */
+        const SCALE_ROTATE_TRANSLATE: [[f32; 4]; 4] = [
+            [0.0, 2.0, 0.0, 0.0],
+            [-3.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 0.5, 0.0],
+            [1.0, 2.0, 3.0, 1.0],
+        ];
+
+        #[test]
+        fn extracts_perspective_planes() {
+            let frustum = Frustum::from_matrix(&perspective_matrix(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0));
+            let h = std::f32::consts::FRAC_1_SQRT_2;
+            assert_plane(&frustum.planes[0], [h, 0.0, h], 0.0);
+            assert_plane(&frustum.planes[1], [-h, 0.0, h], 0.0);
+            assert_plane(&frustum.planes[2], [0.0, h, h], 0.0);
+            assert_plane(&frustum.planes[3], [0.0, -h, h], 0.0);
+            assert_plane(&frustum.planes[4], [0.0, 0.0, 1.0], -0.1);
+            assert_plane(&frustum.planes[5], [0.0, 0.0, -1.0], 100.0);
+        }
+
+        #[test]
+        fn extracts_planes_of_a_moved_camera() {
+            // Looking along +x from (2, 1, -5), so the near and far planes are at x = 2.1 and x = 102
+            let view = look_at_matrix([2.0, 1.0, -5.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]);
+            let perspective = perspective_matrix(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+            let frustum = Frustum::from_view_perspective(&view, &perspective);
+            let h = std::f32::consts::FRAC_1_SQRT_2;
+            assert_plane(&frustum.planes[2], [h, h, 0.0], -h * 3.0);
+            assert_plane(&frustum.planes[3], [h, -h, 0.0], -h * 1.0);
+            assert_plane(&frustum.planes[4], [1.0, 0.0, 0.0], -2.1);
+            assert_plane(&frustum.planes[5], [-1.0, 0.0, 0.0], 102.0);
+
+            assert!(frustum.intersects_sphere(&BoundingSphere::new([12.0, 1.0, -5.0], 0.5)));
+            assert!(!frustum.intersects_sphere(&BoundingSphere::new([-8.0, 1.0, -5.0], 0.5)));
+            assert!(!frustum.intersects_sphere(&BoundingSphere::new([12.0, 1.0, 10.0], 0.5)));
+            assert!(frustum.intersects_aabb(&Aabb::new([101.0, 0.0, -6.0], [103.0, 2.0, -4.0])));
+            assert!(!frustum.intersects_aabb(&Aabb::new([103.0, 0.0, -6.0], [104.0, 2.0, -4.0])));
+            assert!(!frustum.intersects_aabb(&Aabb::empty()));
+        }
+
+        #[test]
+        fn transforms_aabb_with_non_uniform_scale() {
+            let aabb = Aabb::new([-1.0, 0.0, 2.0], [1.0, 1.0, 4.0]);
+            let transformed = aabb.transform(&SCALE_ROTATE_TRANSLATE);
+            // Quarter turns keep the box tight, so it is the box around the transformed corners
+            let corners = (0..8).map(|i| transform_point(&SCALE_ROTATE_TRANSLATE, [
+                if i & 1 == 0 { aabb.min[0] } else { aabb.max[0] },
+                if i & 2 == 0 { aabb.min[1] } else { aabb.max[1] },
+                if i & 4 == 0 { aabb.min[2] } else { aabb.max[2] },
+            ]));
+            let expected = Aabb::from_points(corners);
+            assert_eq!(transformed, expected);
+            assert_eq!(transformed, Aabb::new([-2.0, 0.0, 4.0], [1.0, 4.0, 5.0]));
+            assert!(Aabb::empty().transform(&SCALE_ROTATE_TRANSLATE).is_empty());
+        }
+
+        #[test]
+        fn transforms_sphere_with_non_uniform_scale() {
+            let sphere = BoundingSphere::new([1.0, 0.0, 0.0], 1.0);
+            let transformed = sphere.transform(&SCALE_ROTATE_TRANSLATE);
+            assert_eq!(get_max_scale(&SCALE_ROTATE_TRANSLATE), 3.0);
+            assert_eq!(transformed, BoundingSphere::new([1.0, 4.0, 3.0], 3.0));
+
+            // Every point of the sphere stays inside after the transform
+            for i in 0..64 {
+                let (a, b) = (i as f32 * 0.7, i as f32 * 1.3);
+                let point = vadd(sphere.center, [a.cos() * b.sin(), a.sin() * b.sin(), b.cos()]);
+                let distance = vlength(vsub(transform_point(&SCALE_ROTATE_TRANSLATE, point), transformed.center));
+                assert!(distance <= transformed.radius + 1e-5);
+            }
+        }
+
+        #[test]
+        fn ritter_sphere_covers_all_points() {
+            let mut random = Random::new(1);
+            let points: Vec<[f32; 3]> = (0..500).map(|_| [random.next() * 4.0 - 1.0, random.next() * 0.5, random.next() * 2.0 + 3.0]).collect();
+            let sphere = BoundingSphere::from_points(&points);
+            for point in points.iter() {
+                assert!(vlength(vsub(*point, sphere.center)) <= sphere.radius * (1.0 + 1e-5));
+            }
+            // Not larger than the sphere around the bounding box
+            assert!(sphere.radius <= BoundingSphere::from_aabb(&Aabb::from_points(points.iter().cloned())).radius);
+            assert!(BoundingSphere::from_points(&[]).is_empty());
+        }
+    }
+}
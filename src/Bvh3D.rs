@@ -0,0 +1,695 @@
+pub mod Bvh {
+    use crate::Base3D::General::*;
+    use crate::Bounds3D::Bounds::*;
+    use crate::Math3D::Math::*;
+
+    /*
+    Build parameters. Costs are relative to testing one item, splits are searched among the bin borders
+    of the item centroids along every axis.
+    */
+    const SAH_BINS: usize = 12;
+    const TRAVERSAL_COST: f32 = 1.0;
+    const MAX_LEAF_SIZE: usize = 8;
+
+    /**
+     * Half line from `origin` along the unit vector `direction`
+     */
+    #[derive(Copy, Clone, Debug)]
+    pub struct Ray {
+        pub origin: [f32; 3],
+        pub direction: [f32; 3],
+    }
+
+    impl Ray {
+
+        pub fn new(origin: [f32; 3], direction: [f32; 3]) -> Ray {
+            return Ray {
+                origin: origin,
+                direction: vnormalize(direction),
+            }
+        }
+
+        /**
+         * Ray from the eye through a point on the screen given in normalized device coordinates,
+         * -1 to 1 from the left to the right and from the bottom to the top, for picking
+         */
+        pub fn from_screen(x: f32, y: f32, view: &[[f32; 4]; 4], perspective: &[[f32; 4]; 4]) -> Ray {
+            let inverse = mat4_inverse(&mat4_mul(perspective, view));
+            let unproject = |z: f32| -> [f32; 3] {
+                let p = mat4_mul_vec4(&inverse, [x, y, z, 1.0]);
+                return vscale([p[0], p[1], p[2]], 1.0 / p[3]);
+            };
+            let near = unproject(-1.0);
+            return Ray::new(near, vsub(unproject(1.0), near));
+        }
+
+        pub fn get_point(&self, distance: f32) -> [f32; 3] {
+            return vadd(self.origin, vscale(self.direction, distance));
+        }
+
+        /**
+         * Distance at which the ray enters a box, 0 when it starts inside, with the slab method
+         */
+        pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+            let mut near = 0.0f32;
+            let mut far = f32::INFINITY;
+            for i in 0..3 {
+                // Rays parallel to a slab stay inside or outside of it, dividing by zero would give NaN on its planes
+                if self.direction[i] == 0.0 {
+                    if self.origin[i] < aabb.min[i] || self.origin[i] > aabb.max[i] {
+                        return None;
+                    }
+                    continue;
+                }
+                let inverse = 1.0 / self.direction[i];
+                let t0 = (aabb.min[i] - self.origin[i]) * inverse;
+                let t1 = (aabb.max[i] - self.origin[i]) * inverse;
+                near = near.max(t0.min(t1));
+                far = far.min(t0.max(t1));
+            }
+            if near > far {
+                return None;
+            }
+            return Some(near);
+        }
+
+        /**
+         * Distance at which the ray hits a triangle from either side, after Möller and Trumbore
+         */
+        pub fn intersect_triangle(&self, triangle: &[[f32; 3]; 3]) -> Option<f32> {
+            let e1 = vsub(triangle[1], triangle[0]);
+            let e2 = vsub(triangle[2], triangle[0]);
+            let p = vcross(self.direction, e2);
+            let determinant = vdot(e1, p);
+            if determinant.abs() < 1e-12 {
+                return None;
+            }
+            let inverse = 1.0 / determinant;
+            let s = vsub(self.origin, triangle[0]);
+            let u = vdot(s, p) * inverse;
+            if u < 0.0 || u > 1.0 {
+                return None;
+            }
+            let q = vcross(s, e1);
+            let v = vdot(self.direction, q) * inverse;
+            if v < 0.0 || u + v > 1.0 {
+                return None;
+            }
+            let distance = vdot(e2, q) * inverse;
+            if distance < 0.0 {
+                return None;
+            }
+            return Some(distance);
+        }
+    }
+
+    /**
+     * Closest hit of a ray cast, the index of the item and the distance along the ray
+     */
+    #[derive(Copy, Clone, Debug)]
+    pub struct RayHit {
+        pub item: usize,
+        pub distance: f32,
+    }
+
+    /**
+     * Result of a nearest point query, the index of the closest item, its point closest to the query and the distance between them
+     */
+    #[derive(Copy, Clone, Debug)]
+    pub struct NearestHit {
+        pub item: usize,
+        pub point: [f32; 3],
+        pub distance: f32,
+    }
+
+    /**
+     * Point of a triangle closest to `p`, after Ericson's Real-Time Collision Detection
+     */
+    pub fn closest_point_on_triangle(p: [f32; 3], triangle: &[[f32; 3]; 3]) -> [f32; 3] {
+        let [a, b, c] = *triangle;
+        let ab = vsub(b, a);
+        let ac = vsub(c, a);
+
+        // Vertex regions
+        let ap = vsub(p, a);
+        let d1 = vdot(ab, ap);
+        let d2 = vdot(ac, ap);
+        if d1 <= 0.0 && d2 <= 0.0 {
+            return a;
+        }
+        let bp = vsub(p, b);
+        let d3 = vdot(ab, bp);
+        let d4 = vdot(ac, bp);
+        if d3 >= 0.0 && d4 <= d3 {
+            return b;
+        }
+        let cp = vsub(p, c);
+        let d5 = vdot(ab, cp);
+        let d6 = vdot(ac, cp);
+        if d6 >= 0.0 && d5 <= d6 {
+            return c;
+        }
+
+        // Edge regions
+        let vc = d1 * d4 - d3 * d2;
+        if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+            return vadd(a, vscale(ab, d1 / (d1 - d3)));
+        }
+        let vb = d5 * d2 - d1 * d6;
+        if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+            return vadd(a, vscale(ac, d2 / (d2 - d6)));
+        }
+        let va = d3 * d6 - d5 * d4;
+        if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+            return vadd(b, vscale(vsub(c, b), (d4 - d3) / ((d4 - d3) + (d5 - d6))));
+        }
+
+        // Face region
+        let denominator = 1.0 / (va + vb + vc);
+        return vadd(a, vadd(vscale(ab, vb * denominator), vscale(ac, vc * denominator)));
+    }
+
+    /*
+    Node of the flattened tree. Leaves hold `count` items starting at `first` in the item order,
+    inner nodes have a count of 0 and their two children at `first` and `first + 1`.
+    */
+    #[derive(Copy, Clone, Debug)]
+    struct BvhNode {
+        bounds: Aabb,
+        first: usize,
+        count: usize,
+    }
+
+    /**
+     * Bounding volume hierarchy over items given by their bounding boxes, such as the draw calls or nodes of a
+     * scene or the triangles of a shape. Items are referred to by their index in the slice the tree was built from.
+     * The tree is built with the surface area heuristic, moving items only need a `refit` as long as they stay
+     * close to where they were, a new build is worth it once queries slow down.
+     * Queries only test the boxes, ray casts and nearest point queries ask a closure for the exact answer per item.
+     */
+    pub struct Bvh {
+        nodes: Vec<BvhNode>,
+        items: Vec<usize>,
+        item_bounds: Vec<Aabb>,
+    }
+
+    impl Bvh {
+
+        pub fn build(bounds: &[Aabb]) -> Bvh {
+            let mut bvh = Bvh {
+                nodes: Vec::with_capacity(2 * bounds.len()),
+                items: (0..bounds.len()).collect(),
+                item_bounds: bounds.to_vec(),
+            };
+            if !bounds.is_empty() {
+                bvh.nodes.push(BvhNode { bounds: Aabb::empty(), first: 0, count: bounds.len() });
+                bvh.subdivide(0);
+            }
+            return bvh;
+        }
+
+        pub fn len(&self) -> usize {
+            return self.item_bounds.len();
+        }
+
+        pub fn is_empty(&self) -> bool {
+            return self.item_bounds.is_empty();
+        }
+
+        /**
+         * Box around all items
+         */
+        pub fn get_bounds(&self) -> Aabb {
+            return self.nodes.first().map_or(Aabb::empty(), |root| root.bounds);
+        }
+
+        /*
+        Splits a leaf at the cheapest bin border by the surface area heuristic, or keeps it
+        when no split is cheaper than testing all of its items
+        */
+        fn subdivide(&mut self, index: usize) {
+            let node = self.nodes[index];
+            let items = node.first .. node.first + node.count;
+            let bounds = self.items[items.clone()].iter().fold(Aabb::empty(), |bounds, item| bounds.union(&self.item_bounds[*item]));
+            self.nodes[index].bounds = bounds;
+            if node.count <= 1 {
+                return;
+            }
+
+            let centroids = Aabb::from_points(self.items[items.clone()].iter().map(|item| self.item_bounds[*item].get_center()));
+            let mut best: Option<(usize, f32, f32)> = None;
+            for axis in 0..3 {
+                let extent = centroids.max[axis] - centroids.min[axis];
+                if extent <= 0.0 {
+                    continue;
+                }
+                let scale = SAH_BINS as f32 / extent;
+                let mut bin_bounds = [Aabb::empty(); SAH_BINS];
+                let mut bin_counts = [0usize; SAH_BINS];
+                for item in self.items[items.clone()].iter() {
+                    let bin = (((self.item_bounds[*item].get_center()[axis] - centroids.min[axis]) * scale) as usize).min(SAH_BINS - 1);
+                    bin_bounds[bin] = bin_bounds[bin].union(&self.item_bounds[*item]);
+                    bin_counts[bin] += 1;
+                }
+
+                // Areas and counts left of every border from a sweep from the left, then the same from the right
+                let mut left_costs = [0.0f32; SAH_BINS - 1];
+                let mut left = Aabb::empty();
+                let mut left_count = 0;
+                for i in 0..(SAH_BINS - 1) {
+                    left = left.union(&bin_bounds[i]);
+                    left_count += bin_counts[i];
+                    left_costs[i] = if left_count > 0 { left.get_surface_area() * left_count as f32 } else { 0.0 };
+                }
+                let mut right = Aabb::empty();
+                let mut right_count = 0;
+                for i in (1..SAH_BINS).rev() {
+                    right = right.union(&bin_bounds[i]);
+                    right_count += bin_counts[i];
+                    if right_count == 0 || right_count == node.count {
+                        continue;
+                    }
+                    let cost = left_costs[i - 1] + right.get_surface_area() * right_count as f32;
+                    if best.map_or(true, |(_, _, best_cost)| cost < best_cost) {
+                        best = Some((axis, centroids.min[axis] + i as f32 / scale, cost));
+                    }
+                }
+            }
+
+            let area = bounds.get_surface_area().max(f32::MIN_POSITIVE);
+            let leaf_cost = node.count as f32;
+            let (axis, position) = match best {
+                Some((axis, position, cost)) if TRAVERSAL_COST + cost / area < leaf_cost || node.count > MAX_LEAF_SIZE => (axis, position),
+                // Items with the same centroid can not be told apart by their position, split them in halves
+                None if node.count > MAX_LEAF_SIZE => (3, 0.0),
+                _ => return,
+            };
+
+            // Partition the items of the leaf into the ones left and right of the split
+            let mut middle = node.first + node.count / 2;
+            if axis < 3 {
+                middle = node.first;
+                for i in items.clone() {
+                    if self.item_bounds[self.items[i]].get_center()[axis] < position {
+                        self.items.swap(i, middle);
+                        middle += 1;
+                    }
+                }
+                // Rounding may put every centroid next to a bin border on one side
+                if middle == node.first || middle == node.first + node.count {
+                    if node.count <= MAX_LEAF_SIZE {
+                        return;
+                    }
+                    middle = node.first + node.count / 2;
+                }
+            }
+
+            let first_child = self.nodes.len();
+            self.nodes.push(BvhNode { bounds: Aabb::empty(), first: node.first, count: middle - node.first });
+            self.nodes.push(BvhNode { bounds: Aabb::empty(), first: middle, count: node.first + node.count - middle });
+            self.nodes[index].first = first_child;
+            self.nodes[index].count = 0;
+            self.subdivide(first_child);
+            self.subdivide(first_child + 1);
+        }
+
+        /**
+         * Updates the boxes of all nodes for items that moved, keeping the structure of the tree.
+         * Fails when `bounds` does not hold as many items as the tree was built from, which needs a new build.
+         */
+        pub fn refit(&mut self, bounds: &[Aabb]) -> Result<(), String> {
+            if bounds.len() != self.item_bounds.len() {
+                return Err(format!("A BVH built from {} items can not be refit with {} items", self.item_bounds.len(), bounds.len()));
+            }
+            self.item_bounds.copy_from_slice(bounds);
+            // Children are always stored after their parents
+            for index in (0..self.nodes.len()).rev() {
+                let node = self.nodes[index];
+                self.nodes[index].bounds = if node.count > 0 {
+                    self.items[node.first .. node.first + node.count].iter().fold(Aabb::empty(), |bounds, item| bounds.union(&self.item_bounds[*item]))
+                } else {
+                    self.nodes[node.first].bounds.union(&self.nodes[node.first + 1].bounds)
+                };
+            }
+            return Ok(());
+        }
+
+        /*
+        Items of every leaf whose box passes `test`, skipping the subtrees of nodes that do not
+        */
+        fn collect<F: Fn(&Aabb) -> bool>(&self, test: F) -> Vec<usize> {
+            let mut found: Vec<usize> = Vec::new();
+            if self.nodes.is_empty() {
+                return found;
+            }
+            let mut stack: Vec<usize> = vec![0];
+            while let Some(index) = stack.pop() {
+                let node = &self.nodes[index];
+                if !test(&node.bounds) {
+                    continue;
+                }
+                if node.count > 0 {
+                    found.extend(self.items[node.first .. node.first + node.count].iter().filter(|item| test(&self.item_bounds[**item])));
+                } else {
+                    stack.push(node.first);
+                    stack.push(node.first + 1);
+                }
+            }
+            return found;
+        }
+
+        /**
+         * Items whose boxes overlap `aabb`
+         */
+        pub fn query_aabb(&self, aabb: &Aabb) -> Vec<usize> {
+            return self.collect(|bounds| bounds.intersects(aabb));
+        }
+
+        /**
+         * Items whose boxes are at least partly inside `frustum`
+         */
+        pub fn query_frustum(&self, frustum: &Frustum) -> Vec<usize> {
+            return self.collect(|bounds| frustum.intersects_aabb(bounds));
+        }
+
+        /**
+         * Closest item hit by a ray within `max_distance`. `intersect` returns the distance at which the ray hits an
+         * item, if it does, and is only asked for items whose boxes the ray enters before the closest hit so far.
+         */
+        pub fn cast_ray<F: FnMut(usize, &Ray) -> Option<f32>>(&self, ray: &Ray, max_distance: f32, mut intersect: F) -> Option<RayHit> {
+            let mut closest: Option<RayHit> = None;
+            let mut limit = max_distance;
+            if self.nodes.is_empty() {
+                return None;
+            }
+            let mut stack: Vec<(usize, f32)> = match ray.intersect_aabb(&self.nodes[0].bounds) {
+                Some(distance) => vec![(0, distance)],
+                None => return None,
+            };
+            while let Some((index, entry)) = stack.pop() {
+                if entry > limit {
+                    continue;
+                }
+                let node = &self.nodes[index];
+                if node.count > 0 {
+                    for item in self.items[node.first .. node.first + node.count].iter() {
+                        if let Some(distance) = intersect(*item, ray) {
+                            if distance <= limit {
+                                limit = distance;
+                                closest = Some(RayHit { item: *item, distance: distance });
+                            }
+                        }
+                    }
+                    continue;
+                }
+                // Visit the nearer child first so the farther one can be skipped once something closer was hit
+                let children = [node.first, node.first + 1].map(|child| (child, ray.intersect_aabb(&self.nodes[child].bounds)));
+                let mut children: Vec<(usize, f32)> = children.iter().filter_map(|(child, distance)| distance.map(|distance| (*child, distance))).collect();
+                children.sort_by(|a, b| b.1.total_cmp(&a.1));
+                stack.extend(children);
+            }
+            return closest;
+        }
+
+        /**
+         * Item closest to `point` within `max_distance`. `closest_point` returns the point of an item closest to
+         * `point` and is only asked for items whose boxes are closer than the closest point so far.
+         */
+        pub fn find_nearest<F: FnMut(usize, [f32; 3]) -> [f32; 3]>(&self, point: [f32; 3], max_distance: f32, mut closest_point: F) -> Option<NearestHit> {
+            let mut nearest: Option<NearestHit> = None;
+            let mut limit = max_distance;
+            if self.nodes.is_empty() {
+                return None;
+            }
+            let box_distance = |bounds: &Aabb| -> f32 { return vlength(vsub(bounds.closest_point(point), point)); };
+            let mut stack: Vec<(usize, f32)> = vec![(0, box_distance(&self.nodes[0].bounds))];
+            while let Some((index, distance)) = stack.pop() {
+                if distance > limit {
+                    continue;
+                }
+                let node = &self.nodes[index];
+                if node.count > 0 {
+                    for item in self.items[node.first .. node.first + node.count].iter() {
+                        if box_distance(&self.item_bounds[*item]) > limit {
+                            continue;
+                        }
+                        let candidate = closest_point(*item, point);
+                        let distance = vlength(vsub(candidate, point));
+                        if distance <= limit {
+                            limit = distance;
+                            nearest = Some(NearestHit { item: *item, point: candidate, distance: distance });
+                        }
+                    }
+                    continue;
+                }
+                let mut children = [node.first, node.first + 1].map(|child| (child, box_distance(&self.nodes[child].bounds)));
+                children.sort_by(|a, b| b.1.total_cmp(&a.1));
+                stack.extend(children);
+            }
+            return nearest;
+        }
+    }
+
+    /**
+     * BVH over the triangles of a shape, in the space of its vertices. Items are triangle indices,
+     * triangle `i` is made of the vertices `3i` to `3i + 2` of the shape.
+     */
+    pub struct TriangleBvh {
+        bvh: Bvh,
+        triangles: Vec<[[f32; 3]; 3]>,
+    }
+
+    impl TriangleBvh {
+
+        pub fn from_shape<T: Shape3D>(shape: &T) -> TriangleBvh {
+            let triangles = get_triangles(shape);
+            let bounds: Vec<Aabb> = triangles.iter().map(|triangle| Aabb::from_points(triangle.iter().cloned())).collect();
+            return TriangleBvh {
+                bvh: Bvh::build(&bounds),
+                triangles: triangles,
+            }
+        }
+
+        /**
+         * Refits the tree to the moved vertices of the shape it was built from,
+         * fails and keeps the tree as it was when the shape has a different number of triangles
+         */
+        pub fn refit<T: Shape3D>(&mut self, shape: &T) -> Result<(), String> {
+            let triangles = get_triangles(shape);
+            let bounds: Vec<Aabb> = triangles.iter().map(|triangle| Aabb::from_points(triangle.iter().cloned())).collect();
+            self.bvh.refit(&bounds)?;
+            self.triangles = triangles;
+            return Ok(());
+        }
+
+        pub fn get_bvh(&self) -> &Bvh {
+            return &self.bvh;
+        }
+
+        pub fn get_triangles(&self) -> &Vec<[[f32; 3]; 3]> {
+            return &self.triangles;
+        }
+
+        /**
+         * Closest triangle hit by a ray within `max_distance`
+         */
+        pub fn cast_ray(&self, ray: &Ray, max_distance: f32) -> Option<RayHit> {
+            return self.bvh.cast_ray(ray, max_distance, |item, ray| ray.intersect_triangle(&self.triangles[item]));
+        }
+
+        /**
+         * Triangles whose boxes overlap `aabb`
+         */
+        pub fn query_aabb(&self, aabb: &Aabb) -> Vec<usize> {
+            return self.bvh.query_aabb(aabb);
+        }
+
+        /**
+         * Triangles whose boxes are at least partly inside `frustum`
+         */
+        pub fn query_frustum(&self, frustum: &Frustum) -> Vec<usize> {
+            return self.bvh.query_frustum(frustum);
+        }
+
+        /**
+         * Point of the shape closest to `point` within `max_distance`
+         */
+        pub fn find_nearest(&self, point: [f32; 3], max_distance: f32) -> Option<NearestHit> {
+            return self.bvh.find_nearest(point, max_distance, |item, point| closest_point_on_triangle(point, &self.triangles[item]));
+        }
+    }
+
+    /*
+    Corner positions of every triangle of a shape
+    */
+    fn get_triangles<T: Shape3D>(shape: &T) -> Vec<[[f32; 3]; 3]> {
+        return shape.get_vertices().chunks_exact(3).map(|triangle| [triangle[0].position, triangle[1].position, triangle[2].position]).collect();
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn random_triangle(random: &mut Random) -> [[f32; 3]; 3] {
+            let a = random.point(20.0);
+            return [a, vadd(a, random.point(2.0)), vadd(a, random.point(2.0))];
+        }
+
+        fn random_ray(random: &mut Random) -> Ray {
+            return Ray::new(random.point(30.0), random.point(2.0));
+        }
+
+        fn shape_of(triangles: &[[[f32; 3]; 3]]) -> AShape {
+            let vertices: Vec<Vertex> = triangles.iter().flatten().map(|p| Vertex { position: *p, texture: [0.0, 0.0], material_id: 0 }).collect();
+            return AShape {
+                normals: vec![Normal { normal: [0.0, 1.0, 0.0] }; vertices.len()],
+                vertices: vertices,
+            };
+        }
+
+        fn sorted(mut items: Vec<usize>) -> Vec<usize> {
+            items.sort_unstable();
+            return items;
+        }
+
+        /*
+        Compares every query of a tree over boxes with testing all boxes
+        */
+        fn check_against_brute_force(bvh: &Bvh, boxes: &[Aabb], random: &mut Random) {
+            for _ in 0..50 {
+                let query = random.aabb().expand(random.next() * 3.0);
+                let expected: Vec<usize> = (0..boxes.len()).filter(|i| boxes[*i].intersects(&query)).collect();
+                assert_eq!(sorted(bvh.query_aabb(&query)), expected);
+            }
+
+            for _ in 0..20 {
+                let view = look_at_matrix(random.point(30.0), random.point(2.0), [0.0, 1.0, 0.0]);
+                let frustum = Frustum::from_view_perspective(&view, &perspective_matrix(1.0, 0.75, 0.1, 20.0));
+                let expected: Vec<usize> = (0..boxes.len()).filter(|i| frustum.intersects_aabb(&boxes[*i])).collect();
+                assert_eq!(sorted(bvh.query_frustum(&frustum)), expected);
+            }
+
+            for _ in 0..100 {
+                let ray = random_ray(random);
+                let expected = (0..boxes.len()).filter_map(|i| ray.intersect_aabb(&boxes[i])).filter(|d| *d <= 40.0).fold(None, |a: Option<f32>, d| Some(a.map_or(d, |a| a.min(d))));
+                let hit = bvh.cast_ray(&ray, 40.0, |item, ray| ray.intersect_aabb(&boxes[item]));
+                assert_eq!(hit.map(|hit| hit.distance), expected);
+                if let Some(hit) = hit {
+                    assert_eq!(ray.intersect_aabb(&boxes[hit.item]), Some(hit.distance));
+                }
+            }
+
+            for _ in 0..100 {
+                let point = random.point(30.0);
+                let expected = boxes.iter().map(|b| vlength(vsub(b.closest_point(point), point))).fold(f32::INFINITY, f32::min);
+                let hit = bvh.find_nearest(point, f32::INFINITY, |item, point| boxes[item].closest_point(point)).unwrap();
+                assert_eq!(hit.distance, expected);
+                assert_eq!(hit.point, boxes[hit.item].closest_point(point));
+            }
+        }
+
+        #[test]
+        fn box_queries_match_brute_force() {
+            let mut random = Random::new(7);
+            let boxes: Vec<Aabb> = (0..300).map(|_| random.aabb()).collect();
+            let bvh = Bvh::build(&boxes);
+            assert_eq!(bvh.len(), 300);
+            assert_eq!(bvh.get_bounds(), boxes.iter().fold(Aabb::empty(), |bounds, b| bounds.union(b)));
+            check_against_brute_force(&bvh, &boxes, &mut random);
+        }
+
+        #[test]
+        fn refit_matches_brute_force_after_moving_items() {
+            let mut random = Random::new(11);
+            let mut boxes: Vec<Aabb> = (0..300).map(|_| random.aabb()).collect();
+            let mut bvh = Bvh::build(&boxes);
+            for b in boxes.iter_mut() {
+                let offset = random.point(4.0);
+                *b = Aabb::new(vadd(b.min, offset), vadd(b.max, offset));
+            }
+            bvh.refit(&boxes).unwrap();
+            assert_eq!(bvh.get_bounds(), boxes.iter().fold(Aabb::empty(), |bounds, b| bounds.union(b)));
+            check_against_brute_force(&bvh, &boxes, &mut random);
+
+            assert!(bvh.refit(&boxes[1..]).is_err());
+            check_against_brute_force(&bvh, &boxes, &mut random);
+        }
+
+        #[test]
+        fn triangle_queries_match_brute_force() {
+            let mut random = Random::new(13);
+            let mut triangles: Vec<[[f32; 3]; 3]> = (0..300).map(|_| random_triangle(&mut random)).collect();
+            let mut bvh = TriangleBvh::from_shape(&shape_of(&triangles));
+
+            for pass in 0..2 {
+                for _ in 0..200 {
+                    let ray = random_ray(&mut random);
+                    let expected = triangles.iter().filter_map(|t| ray.intersect_triangle(t)).filter(|d| *d <= 40.0).fold(None, |a: Option<f32>, d| Some(a.map_or(d, |a| a.min(d))));
+                    assert_eq!(bvh.cast_ray(&ray, 40.0).map(|hit| hit.distance), expected);
+                }
+                for _ in 0..100 {
+                    let point = random.point(30.0);
+                    let expected = triangles.iter().map(|t| vlength(vsub(closest_point_on_triangle(point, t), point))).fold(f32::INFINITY, f32::min);
+                    assert_eq!(bvh.find_nearest(point, f32::INFINITY).unwrap().distance, expected);
+                }
+                let query = random.aabb().expand(2.0);
+                let expected: Vec<usize> = (0..triangles.len()).filter(|i| Aabb::from_points(triangles[*i].iter().cloned()).intersects(&query)).collect();
+                assert_eq!(sorted(bvh.query_aabb(&query)), expected);
+
+                if pass == 0 {
+                    for triangle in triangles.iter_mut() {
+                        let offset = random.point(3.0);
+                        *triangle = triangle.map(|p| vadd(p, offset));
+                    }
+                    bvh.refit(&shape_of(&triangles)).unwrap();
+                    assert_eq!(bvh.get_triangles(), &triangles);
+                }
+            }
+
+            assert!(bvh.refit(&shape_of(&triangles[..10])).is_err());
+            assert_eq!(bvh.get_triangles(), &triangles);
+        }
+
+        #[test]
+        fn splits_items_with_equal_centroids() {
+            let boxes = vec![Aabb::new([1.0, 1.0, 1.0], [2.0, 2.0, 2.0]); 50];
+            let bvh = Bvh::build(&boxes);
+            assert!(bvh.nodes.iter().all(|node| node.count <= MAX_LEAF_SIZE));
+            assert_eq!(sorted(bvh.items.clone()), (0..50).collect::<Vec<usize>>());
+            assert_eq!(sorted(bvh.query_aabb(&Aabb::new([0.0; 3], [1.0; 3]))), (0..50).collect::<Vec<usize>>());
+            let hit = bvh.cast_ray(&Ray::new([1.5, 1.5, -5.0], [0.0, 0.0, 1.0]), 100.0, |item, ray| ray.intersect_aabb(&boxes[item])).unwrap();
+            assert_eq!(hit.distance, 6.0);
+        }
+
+        #[test]
+        fn empty_tree_finds_nothing() {
+            let mut bvh = Bvh::build(&[]);
+            assert!(bvh.is_empty());
+            assert!(bvh.get_bounds().is_empty());
+            assert!(bvh.query_aabb(&Aabb::new([-1.0; 3], [1.0; 3])).is_empty());
+            let frustum = Frustum::from_matrix(&perspective_matrix(1.0, 1.0, 0.1, 10.0));
+            assert!(bvh.query_frustum(&frustum).is_empty());
+            assert!(bvh.cast_ray(&Ray::new([0.0; 3], [0.0, 0.0, 1.0]), 100.0, |_, _| Some(1.0)).is_none());
+            assert!(bvh.find_nearest([0.0; 3], 100.0, |_, point| point).is_none());
+            assert!(bvh.refit(&[]).is_ok());
+        }
+
+        #[test]
+        fn axis_parallel_ray_on_slab_plane() {
+            let aabb = Aabb::new([0.0, 1.0, 2.0], [1.0, 2.0, 3.0]);
+            // Along the bottom face and along an edge of the box
+            assert_eq!(Ray::new([0.5, 1.0, -5.0], [0.0, 0.0, 1.0]).intersect_aabb(&aabb), Some(7.0));
+            assert_eq!(Ray::new([0.0, 2.0, -5.0], [0.0, 0.0, 1.0]).intersect_aabb(&aabb), Some(7.0));
+            // Starting on the plane of a face, inside of the box
+            assert_eq!(Ray::new([0.5, 1.5, 2.0], [1.0, 0.0, 0.0]).intersect_aabb(&aabb), Some(0.0));
+            // Parallel just outside of a slab
+            assert_eq!(Ray::new([0.5, 2.001, -5.0], [0.0, 0.0, 1.0]).intersect_aabb(&aabb), None);
+            // Pointing away from the box
+            assert_eq!(Ray::new([0.5, 1.0, -5.0], [0.0, 0.0, -1.0]).intersect_aabb(&aabb), None);
+
+            let bvh = Bvh::build(&[aabb]);
+            let hit = bvh.cast_ray(&Ray::new([1.0, 1.5, -5.0], [0.0, 0.0, 1.0]), 100.0, |_, ray| ray.intersect_aabb(&aabb)).unwrap();
+            assert_eq!(hit.distance, 7.0);
+        }
+    }
+}
@@ -41,6 +41,10 @@ impl Camera {
         return self.position;
     }
 
+    pub fn set_position(&mut self, position: [f32; 3]) {
+        self.position = position;
+    }
+
     pub fn get_direction(&self) -> [f32; 3] {
         return self.direction;
     }
@@ -0,0 +1,266 @@
+pub mod Cluster {
+    use glium::texture::buffer_texture::{BufferTexture, BufferTextureType};
+
+    use crate::Lights3D::Lights::*;
+    use crate::Math3D::Math::*;
+
+    /**
+     * Number of clusters along the screen width, the screen height and the view depth
+     */
+    pub const CLUSTERS_X: u32 = 16;
+    pub const CLUSTERS_Y: u32 = 9;
+    pub const CLUSTERS_Z: u32 = 24;
+
+    /*
+    Depth slices are spaced exponentially between these view distances.
+    Everything closer falls into the first slice and everything further into the last one.
+    */
+    const CLUSTER_ZNEAR: f32 = 0.1;
+    const CLUSTER_ZFAR: f32 = 100.0;
+
+    /**
+     * Point light culling for forward+ shading. The view frustum is split into clusters and
+     * every point light is listed in the clusters its attenuation sphere touches, so the
+     * fragment shader only has to look at the lights of its own cluster.
+     *
+     * `grid` holds (offset, count) into `light_indices` for every cluster, x fastest then y then z,
+     * and `light_indices` the indices of the point lights in the light buffer.
+     */
+    pub struct ClusterGrid {
+        grid: BufferTexture<[u32; 2]>,
+        light_indices: BufferTexture<u32>,
+        cluster_lights: Vec<Vec<u32>>,
+        grid_data: Vec<[u32; 2]>,
+        index_data: Vec<u32>,
+    }
+
+    impl ClusterGrid {
+
+        pub fn new(display: &glium::Display) -> ClusterGrid {
+            let cluster_count = (CLUSTERS_X * CLUSTERS_Y * CLUSTERS_Z) as usize;
+            return ClusterGrid {
+                grid: BufferTexture::empty_dynamic(display, cluster_count, BufferTextureType::Unsigned).unwrap(),
+                light_indices: BufferTexture::empty_dynamic(display, 1024, BufferTextureType::Unsigned).unwrap(),
+                cluster_lights: vec![Vec::new(); cluster_count],
+                grid_data: Vec::with_capacity(cluster_count),
+                index_data: Vec::new(),
+            }
+        }
+
+        /**
+         * Assigns the point lights to the clusters of the camera described by `view` and `perspective`
+         */
+        pub fn update(&mut self, display: &glium::Display, point_lights: &[PointLight], view: &[[f32; 4]; 4], perspective: &[[f32; 4]; 4]) {
+            assign_lights(&mut self.cluster_lights, point_lights, view, perspective);
+
+            self.grid_data.clear();
+            self.index_data.clear();
+            for lights in self.cluster_lights.iter() {
+                self.grid_data.push([self.index_data.len() as u32, lights.len() as u32]);
+                self.index_data.extend(lights.iter());
+            }
+
+            if self.index_data.len() > self.light_indices.len() {
+                let capacity = self.index_data.len().next_power_of_two();
+                self.light_indices = BufferTexture::empty_dynamic(display, capacity, BufferTextureType::Unsigned).unwrap();
+            }
+            self.grid.write(&self.grid_data);
+            if !self.index_data.is_empty() {
+                self.light_indices.slice(0 .. self.index_data.len()).unwrap().write(&self.index_data);
+            }
+        }
+
+        pub fn get_grid(&self) -> &BufferTexture<[u32; 2]> {
+            return &self.grid;
+        }
+
+        pub fn get_light_indices(&self) -> &BufferTexture<u32> {
+            return &self.light_indices;
+        }
+
+        pub fn get_dimensions(&self) -> [i32; 3] {
+            return [CLUSTERS_X as i32, CLUSTERS_Y as i32, CLUSTERS_Z as i32];
+        }
+
+        pub fn get_depth_range(&self) -> [f32; 2] {
+            return [CLUSTER_ZNEAR, CLUSTER_ZFAR];
+        }
+    }
+
+    /*
+    Lists every point light in the clusters its attenuation sphere touches
+    */
+    fn assign_lights(cluster_lights: &mut [Vec<u32>], point_lights: &[PointLight], view: &[[f32; 4]; 4], perspective: &[[f32; 4]; 4]) {
+        for lights in cluster_lights.iter_mut() {
+            lights.clear();
+        }
+
+        for (index, light) in point_lights.iter().enumerate() {
+            let center = transform_point(view, light.position);
+            let radius = light.get_range();
+            if center[2] + radius <= 0.0 {
+                continue;
+            }
+
+            let first_slice = get_slice(center[2] - radius);
+            let last_slice = get_slice(center[2] + radius);
+            for slice in first_slice..=last_slice {
+                // Part of the sphere's bounding box inside this slice
+                let (slice_near, slice_far) = get_slice_bounds(slice);
+                let near = (center[2] - radius).max(slice_near);
+                let far = (center[2] + radius).min(slice_far);
+
+                let (x_range, y_range) = if near <= 1e-4 {
+                    // Reaches around the camera, so it can cover any part of the screen
+                    (Some((0, CLUSTERS_X - 1)), Some((0, CLUSTERS_Y - 1)))
+                } else {
+                    (
+                        get_tile_range(perspective[0][0], center[0] - radius, center[0] + radius, near, far, CLUSTERS_X),
+                        get_tile_range(perspective[1][1], center[1] - radius, center[1] + radius, near, far, CLUSTERS_Y),
+                    )
+                };
+                let x_range = match x_range { Some(range) => range, None => continue };
+                let y_range = match y_range { Some(range) => range, None => continue };
+
+                for y in y_range.0..=y_range.1 {
+                    for x in x_range.0..=x_range.1 {
+                        cluster_lights[get_cluster_index(x, y, slice)].push(index as u32);
+                    }
+                }
+            }
+        }
+    }
+
+    fn get_cluster_index(x: u32, y: u32, slice: u32) -> usize {
+        return ((slice * CLUSTERS_Y + y) * CLUSTERS_X + x) as usize;
+    }
+
+    /*
+    Depth slice of a view distance, the same mapping is used in the fragment shader
+    */
+    fn get_slice(depth: f32) -> u32 {
+        if depth <= CLUSTER_ZNEAR {
+            return 0;
+        }
+        let slice = ((depth / CLUSTER_ZNEAR).ln() / (CLUSTER_ZFAR / CLUSTER_ZNEAR).ln() * CLUSTERS_Z as f32).floor();
+        return (slice as u32).min(CLUSTERS_Z - 1);
+    }
+
+    fn get_slice_bounds(slice: u32) -> (f32, f32) {
+        let ratio = CLUSTER_ZFAR / CLUSTER_ZNEAR;
+        let near = if slice == 0 { 0.0 } else { CLUSTER_ZNEAR * ratio.powf(slice as f32 / CLUSTERS_Z as f32) };
+        let far = if slice == CLUSTERS_Z - 1 { f32::INFINITY } else { CLUSTER_ZNEAR * ratio.powf((slice + 1) as f32 / CLUSTERS_Z as f32) };
+        return (near, far);
+    }
+
+    /*
+    Tiles covered along one screen axis by the box [min, max] x [near, far] in view space,
+    where `scale` is the projection's scale on that axis. None if the box is off screen.
+    */
+    fn get_tile_range(scale: f32, min: f32, max: f32, near: f32, far: f32, tiles: u32) -> Option<(u32, u32)> {
+        // x / z is monotonic in both x and z for z > 0, so the extremes lie on the corners
+        let low = (scale * min / near).min(scale * min / far);
+        let high = (scale * max / near).max(scale * max / far);
+        if low > 1.0 || high < -1.0 {
+            return None;
+        }
+        let to_tile = |ndc: f32| -> u32 { (((ndc.clamp(-1.0, 1.0) * 0.5 + 0.5) * tiles as f32) as u32).min(tiles - 1) };
+        return Some((to_tile(low), to_tile(high)));
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /*
+        Cluster of a view space position, as `get_cluster` in the fragment shader finds it
+        */
+        fn get_fragment_cluster(position: [f32; 3], perspective: &[[f32; 4]; 4]) -> usize {
+            let clip = mat4_mul_vec4(perspective, [position[0], position[1], position[2], 1.0]);
+            let tile = |ndc: f32, tiles: u32| -> u32 { (((ndc * 0.5 + 0.5) * tiles as f32).floor().max(0.0) as u32).min(tiles - 1) };
+            return get_cluster_index(tile(clip[0] / clip[3], CLUSTERS_X), tile(clip[1] / clip[3], CLUSTERS_Y), get_slice(position[2]));
+        }
+
+        fn light_with_range(position: [f32; 3], range: f32) -> PointLight {
+            // 1 + quadratic * range^2 = 256 at the range
+            let light = PointLight::new_with_parameters(position, [1.0, 1.0, 1.0], 0.0, 255.0 / (range * range));
+            assert!((light.get_range() - range).abs() < 1e-3);
+            return light;
+        }
+
+        fn assign(point_lights: &[PointLight], view: &[[f32; 4]; 4], perspective: &[[f32; 4]; 4]) -> Vec<Vec<u32>> {
+            let mut cluster_lights: Vec<Vec<u32>> = vec![Vec::new(); (CLUSTERS_X * CLUSTERS_Y * CLUSTERS_Z) as usize];
+            assign_lights(&mut cluster_lights, point_lights, view, perspective);
+            return cluster_lights;
+        }
+
+        #[test]
+        fn slices_match_their_bounds() {
+            for slice in 0..CLUSTERS_Z {
+                let (near, far) = get_slice_bounds(slice);
+                assert_eq!(get_slice(near * 1.001 + 1e-4), slice);
+                if far.is_finite() {
+                    assert_eq!(get_slice(far * 0.999), slice);
+                }
+            }
+            assert_eq!(get_slice(-1.0), 0);
+            assert_eq!(get_slice(1000.0), CLUSTERS_Z - 1);
+        }
+
+        #[test]
+        fn lists_lights_in_every_cluster_they_reach() {
+            let view = look_at_matrix([0.0, 1.0, -5.0], [0.2, -0.1, 1.0], [0.0, 1.0, 0.0]);
+            let perspective = perspective_matrix(1.2, 9.0 / 16.0, 0.1, 100.0);
+            let point_lights = vec![
+                light_with_range([0.0, 0.5, 0.0], 1.0),
+                light_with_range([2.0, 1.0, 3.0], 3.0),
+                // Around the camera, partly off screen and far away
+                light_with_range([0.0, 1.0, -4.8], 0.5),
+                light_with_range([-4.0, 0.0, 2.0], 2.0),
+                light_with_range([10.0, 3.0, 60.0], 8.0),
+            ];
+            let cluster_lights = assign(&point_lights, &view, &perspective);
+
+            for (index, light) in point_lights.iter().enumerate() {
+                let center = transform_point(&view, light.position);
+                let radius = light.get_range();
+                let steps = 12;
+                for i in 0..=steps {
+                    for j in 0..=steps {
+                        for k in 0..=steps {
+                            let offset = [i, j, k].map(|n| (n as f32 / steps as f32 * 2.0 - 1.0) * radius);
+                            if vlength(offset) > radius {
+                                continue;
+                            }
+                            // Only visible fragments look up their cluster
+                            let position = vadd(center, offset);
+                            let clip = mat4_mul_vec4(&perspective, [position[0], position[1], position[2], 1.0]);
+                            if position[2] < 0.1 || clip[0].abs() > clip[3] || clip[1].abs() > clip[3] {
+                                continue;
+                            }
+                            let cluster = get_fragment_cluster(position, &perspective);
+                            assert!(cluster_lights[cluster].contains(&(index as u32)), "light {} misses cluster {} at {:?}", index, cluster, position);
+                        }
+                    }
+                }
+            }
+
+            // Small lights stay in a small part of the grid
+            let small_light_clusters = cluster_lights.iter().filter(|lights| lights.contains(&0)).count();
+            assert!(small_light_clusters > 0 && small_light_clusters < cluster_lights.len() / 50, "{}", small_light_clusters);
+        }
+
+        #[test]
+        fn skips_lights_out_of_view() {
+            let view = look_at_matrix([0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]);
+            let perspective = perspective_matrix(1.0, 1.0, 0.1, 100.0);
+            let point_lights = vec![
+                light_with_range([0.0, 0.0, -3.0], 1.0),
+                light_with_range([20.0, 0.0, 5.0], 1.0),
+                light_with_range([0.0, -20.0, 5.0], 1.0),
+            ];
+            let cluster_lights = assign(&point_lights, &view, &perspective);
+            assert!(cluster_lights.iter().all(|lights| lights.is_empty()));
+        }
+    }
+}
@@ -0,0 +1,70 @@
+pub mod Culling {
+    use crate::Bounds3D::Bounds::*;
+    use crate::Mesh3D::Mesh::*;
+    use crate::Scene3D::Scene::*;
+
+    /**
+     * Draw calls kept and skipped since the last `reset_stats`
+     */
+    #[derive(Copy, Clone, Debug, Default)]
+    pub struct CullingStats {
+        pub visible: usize,
+        pub culled: usize,
+    }
+
+    /**
+     * Skips draw calls outside of the view frustum. Draw calls are found by their bounding boxes in the BVH
+     * of the scene graph and then tested with their bounding spheres, instanced draw calls as a whole.
+     * Only culls draws seen by the camera, shadow maps need the objects outside of the view as well.
+     */
+    pub struct FrustumCuller {
+        stats: CullingStats,
+        pub enabled: bool,
+    }
+
+    impl FrustumCuller {
+
+        pub fn new() -> FrustumCuller {
+            return FrustumCuller {
+                stats: CullingStats::default(),
+                enabled: true,
+            }
+        }
+
+        /**
+         * The lit or unlit draw calls of the scene at least partly inside the frustum of `perspective * view`,
+         * in the order of `SceneGraph::collect_draw_calls`. Call `update_world_transforms` first.
+         */
+        pub fn cull<'a>(&mut self, scene_graph: &'a SceneGraph, unlit: bool, view: &[[f32; 4]; 4], perspective: &[[f32; 4]; 4]) -> Vec<DrawCall<'a>> {
+            let draw_calls = scene_graph.collect_draw_calls(unlit);
+            if !self.enabled {
+                self.stats.visible += draw_calls.len();
+                return draw_calls;
+            }
+
+            let frustum = Frustum::from_view_perspective(view, perspective);
+            let mut inside = scene_graph.get_bvh().query_frustum(&frustum);
+            inside.sort_unstable();
+            let visible: Vec<DrawCall> = inside.iter()
+                .filter_map(|item| scene_graph.get(scene_graph.get_mesh_nodes()[*item]))
+                .filter(|node| node.unlit == unlit)
+                .filter_map(|node| node.get_draw_call())
+                .filter(|draw_call| frustum.intersects_sphere(&draw_call.get_bounding_sphere()))
+                .collect();
+            self.stats.visible += visible.len();
+            self.stats.culled += draw_calls.len() - visible.len();
+            return visible;
+        }
+
+        /**
+         * Counts of all calls to `cull` since the last reset, usually the ones of a frame
+         */
+        pub fn get_stats(&self) -> CullingStats {
+            return self.stats;
+        }
+
+        pub fn reset_stats(&mut self) {
+            self.stats = CullingStats::default();
+        }
+    }
+}
@@ -0,0 +1,253 @@
+pub mod Deferred {
+    use glium::Surface;
+    use glium::framebuffer::{MultiOutputFrameBuffer, SimpleFrameBuffer};
+    use glium::texture::{DepthFormat, DepthTexture2d, MipmapsOption, Texture2d, UncompressedFloatFormat};
+    use glium::uniforms::UniformValue;
+
+    use crate::Base3D::General::*;
+    use crate::Lights3D::Lights::*;
+    use crate::Math3D::Math::*;
+    use crate::Mesh3D::Mesh::*;
+    use crate::Screen3D::Screen::*;
+    use crate::Ssao3D::Ssao::SsaoRenderer;
+    use crate::Uniform3D::Uniforms::StdUniform;
+    use crate::Shaders;
+
+    /**
+     * Render targets of the deferred path, all sized like the window.
+     * The geometry pass writes the surface seen in every pixel:
+     *
+     * position: (world position, 1), 0 where nothing was drawn
+     * normal:   (world normal, shininess)
+     * albedo:   (diffuse or base color, shading model)
+     * specular: (specular color, material index) for Phong,
+     *           (metallic, roughness, ambient occlusion, material index) for PBR materials
+     *
+     * The geometry pass also writes the emissive color into `lit`, the lighting passes add up on it.
+     * `lit` shares the depth texture so objects drawn after lighting are still hidden behind the scene.
+     */
+    pub struct GBuffer {
+        position: Texture2d,
+        normal: Texture2d,
+        albedo: Texture2d,
+        specular: Texture2d,
+        depth: DepthTexture2d,
+        lit: Texture2d,
+    }
+
+    impl GBuffer {
+
+        pub fn new(display: &glium::Display, width: u32, height: u32) -> GBuffer {
+            let color_target = |format: UncompressedFloatFormat| -> Texture2d {
+                return Texture2d::empty_with_format(display, format, MipmapsOption::NoMipmap, width, height).unwrap();
+            };
+            return GBuffer {
+                position: color_target(UncompressedFloatFormat::F32F32F32F32),
+                normal: color_target(UncompressedFloatFormat::F16F16F16F16),
+                albedo: color_target(UncompressedFloatFormat::F16F16F16F16),
+                specular: color_target(UncompressedFloatFormat::F16F16F16F16),
+                depth: DepthTexture2d::empty_with_format(display, DepthFormat::F32, MipmapsOption::NoMipmap, width, height).unwrap(),
+                lit: color_target(UncompressedFloatFormat::F16F16F16F16),
+            }
+        }
+
+        pub fn get_dimensions(&self) -> (u32, u32) {
+            return (self.position.width(), self.position.height());
+        }
+
+        pub fn get_depth(&self) -> &DepthTexture2d {
+            return &self.depth;
+        }
+
+        pub fn get_lit(&self) -> &Texture2d {
+            return &self.lit;
+        }
+    }
+
+    /*
+    Per instance attributes of a point light volume
+    */
+    #[derive(Copy, Clone)]
+    struct LightVolume {
+        light_position: [f32; 3],
+        light_radius: f32,
+        light_index: i32,
+    }
+
+    implement_vertex!(LightVolume, light_position, light_radius, light_index);
+
+    /*
+    Uniforms of the lighting passes, everything of the forward pass plus the G-buffer
+    */
+    struct DeferredUniform<'a, 'b> {
+        std: &'a StdUniform<'b>,
+        gbuffer: &'a GBuffer,
+    }
+
+    impl glium::uniforms::Uniforms for DeferredUniform<'_, '_> {
+        fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut f: F) {
+            self.std.visit_values(&mut f);
+            f("g_position", UniformValue::Texture2d(&self.gbuffer.position, None));
+            f("g_normal", UniformValue::Texture2d(&self.gbuffer.normal, None));
+            f("g_albedo", UniformValue::Texture2d(&self.gbuffer.albedo, None));
+            f("g_specular", UniformValue::Texture2d(&self.gbuffer.specular, None));
+        }
+    }
+
+    /**
+     * Deferred shading, an alternative to drawing every object with all lights applied.
+     * Objects are drawn once into the G-buffer, then directional and spot lights are applied
+     * over the whole screen and every point light only to the pixels inside its range,
+     * using the same light models as the forward shader.
+     */
+    pub struct DeferredRenderer {
+        geometry_program: glium::Program,
+        lighting_program: glium::Program,
+        volume_program: glium::Program,
+        screen_quad: ScreenQuad,
+        volume_vertices: glium::VertexBuffer<Vertex>,
+        volume_indices: glium::IndexBuffer<u32>,
+        volume_scale: f32,
+        volumes: glium::VertexBuffer<LightVolume>,
+        volume_data: Vec<LightVolume>,
+        gbuffer: Option<GBuffer>,
+    }
+
+    impl DeferredRenderer {
+
+        pub fn new(display: &glium::Display) -> DeferredRenderer {
+            let geometry_source = Shaders::combine(&[Shaders::SHADER_VERSION, Shaders::LIGHTING, Shaders::MATERIALS, Shaders::GBUFFER_FRAGMENT_SHADER]);
+            let lighting_source = Shaders::combine(&[Shaders::SHADER_VERSION, Shaders::LIGHTING, Shaders::GBUFFER, Shaders::DEFERRED_FRAGMENT_SHADER]);
+            let volume_source = Shaders::combine(&[Shaders::SHADER_VERSION, Shaders::LIGHTING, Shaders::GBUFFER, Shaders::LIGHT_VOLUME_FRAGMENT_SHADER]);
+            let sphere = IndexedShape::from_shape(&Sphere::new([0.0, 0.0, 0.0], 1.0, 2, 0));
+
+            return DeferredRenderer {
+                geometry_program: glium::Program::from_source(display, Shaders::VERTEX_SHADER, &geometry_source, None).unwrap(),
+                lighting_program: glium::Program::from_source(display, Shaders::SCREEN_VERTEX_SHADER, &lighting_source, None).unwrap(),
+                volume_program: glium::Program::from_source(display, Shaders::LIGHT_VOLUME_VERTEX_SHADER, &volume_source, None).unwrap(),
+                screen_quad: ScreenQuad::new(display),
+                volume_vertices: glium::VertexBuffer::immutable(display, sphere.get_vertices()).unwrap(),
+                volume_indices: glium::IndexBuffer::immutable(display, glium::index::PrimitiveType::TrianglesList, sphere.get_indices()).unwrap(),
+                volume_scale: 1.0 / get_inner_radius(&sphere),
+                volumes: glium::VertexBuffer::empty_dynamic(display, 256).unwrap(),
+                volume_data: Vec::new(),
+                gbuffer: None,
+            }
+        }
+
+        /**
+         * Draws a frame of the given size and returns the lit HDR image. `uniform` has to be set up
+         * like for the forward pass, with the light buffer and shadow maps updated for `point_lights`
+         * and the occlusion of `ssao` bound, which is computed from the G-buffer before lighting.
+         * Unlit draw calls are drawn with `unlit_program` after lighting.
+         */
+        pub fn render(&mut self, display: &glium::Display, dimensions: (u32, u32), uniform: &mut StdUniform, point_lights: &[PointLight], ssao: &SsaoRenderer,
+            draw_calls: &[DrawCall], unlit_draw_calls: &[DrawCall], unlit_program: &glium::Program, draw_parameters: &glium::DrawParameters) -> &Texture2d {
+
+            if self.gbuffer.as_ref().map_or(true, |gbuffer| gbuffer.get_dimensions() != dimensions) {
+                self.gbuffer = Some(GBuffer::new(display, dimensions.0, dimensions.1));
+            }
+            let gbuffer = self.gbuffer.as_ref().unwrap();
+
+            /*
+            Geometry pass
+            */
+            let outputs = [
+                ("g_position", &gbuffer.position),
+                ("g_normal", &gbuffer.normal),
+                ("g_albedo", &gbuffer.albedo),
+                ("g_specular", &gbuffer.specular),
+                ("g_emissive", &gbuffer.lit),
+            ];
+            let mut geometry_framebuffer = MultiOutputFrameBuffer::with_depth_buffer(display, outputs.iter().cloned(), &gbuffer.depth).unwrap();
+            geometry_framebuffer.clear_color_and_depth((0.0, 0.0, 0.0, 0.0), 1.0);
+            for draw_call in draw_calls.iter() {
+                uniform.model = draw_call.model;
+                uniform.normal_matrix = draw_call.normal_matrix;
+                draw_call.draw(&mut geometry_framebuffer, &self.geometry_program, uniform, draw_parameters).unwrap();
+            }
+
+            ssao.render(display, &gbuffer.position, &gbuffer.normal, &uniform.view, &uniform.perspective);
+
+            /*
+            Lighting passes
+            */
+            let mut lit_framebuffer = SimpleFrameBuffer::with_depth_buffer(display, &gbuffer.lit, &gbuffer.depth).unwrap();
+            let deferred_uniform = DeferredUniform { std: uniform, gbuffer: gbuffer };
+
+            // Lights add up on the emissive colors
+            let additive = glium::Blend {
+                color: glium::BlendingFunction::Addition {
+                    source: glium::LinearBlendingFactor::One,
+                    destination: glium::LinearBlendingFactor::One,
+                },
+                alpha: glium::BlendingFunction::AlwaysReplace,
+                constant_value: (0.0, 0.0, 0.0, 0.0),
+            };
+            let lighting_parameters = glium::DrawParameters {
+                blend: additive,
+                .. Default::default()
+            };
+            self.screen_quad.draw(&mut lit_framebuffer, &self.lighting_program, &deferred_uniform, &lighting_parameters).unwrap();
+
+            self.volume_data.clear();
+            for (i, light) in point_lights.iter().enumerate() {
+                self.volume_data.push(LightVolume {
+                    light_position: light.position,
+                    light_radius: light.get_range() * self.volume_scale,
+                    light_index: i as i32,
+                });
+            }
+            if !self.volume_data.is_empty() {
+                if self.volume_data.len() > self.volumes.len() {
+                    self.volumes = glium::VertexBuffer::empty_dynamic(display, self.volume_data.len().next_power_of_two()).unwrap();
+                }
+                let instances = self.volumes.slice(0 .. self.volume_data.len()).unwrap();
+                instances.write(&self.volume_data);
+
+                // Back faces of the volumes behind the scene cover every pixel within range of the light,
+                // also when the camera is inside a volume
+                let volume_parameters = glium::DrawParameters {
+                    depth: glium::Depth {
+                        test: glium::draw_parameters::DepthTest::IfMoreOrEqual,
+                        write: false,
+                        .. Default::default()
+                    },
+                    backface_culling: glium::BackfaceCullingMode::CullCounterClockwise,
+                    blend: additive,
+                    .. Default::default()
+                };
+                lit_framebuffer.draw((&self.volume_vertices, instances.per_instance().unwrap()), &self.volume_indices, &self.volume_program, &deferred_uniform, &volume_parameters).unwrap();
+            }
+
+            for draw_call in unlit_draw_calls.iter() {
+                uniform.model = draw_call.model;
+                uniform.normal_matrix = draw_call.normal_matrix;
+                draw_call.draw(&mut lit_framebuffer, unlit_program, uniform, draw_parameters).unwrap();
+            }
+
+            return &gbuffer.lit;
+        }
+
+        /**
+         * Targets of the last frame, `None` before the first one
+         */
+        pub fn get_gbuffer(&self) -> Option<&GBuffer> {
+            return self.gbuffer.as_ref();
+        }
+    }
+
+    /*
+    Distance from the center to the closest face of a mesh around the origin,
+    light volumes are scaled by its inverse so the faces stay outside the light's range
+    */
+    fn get_inner_radius(shape: &IndexedShape) -> f32 {
+        let vertices = shape.get_vertices();
+        return shape.get_indices().chunks(3).map(|triangle| {
+            let a = vertices[triangle[0] as usize].position;
+            let b = vertices[triangle[1] as usize].position;
+            let c = vertices[triangle[2] as usize].position;
+            return vdot(vnormalize(vcross(vsub(b, a), vsub(c, a))), a).abs();
+        }).fold(f32::INFINITY, f32::min);
+    }
+}
@@ -0,0 +1,90 @@
+pub mod Gizmo {
+    use crate::Base3D::General::*;
+    use crate::Lights3D::Lights::*;
+    use crate::Mesh3D::Mesh::*;
+    use crate::Shaders;
+
+    /*
+    Per instance attributes of a light marker
+    */
+    #[derive(Copy, Clone)]
+    struct GizmoInstance {
+        gizmo_position: [f32; 3],
+        gizmo_color: [f32; 3],
+    }
+
+    implement_vertex!(GizmoInstance, gizmo_position, gizmo_color);
+
+    /**
+     * Draws a small cube at every point and spot light in the diffuse color of the light, all in one instanced call.
+     * Lights with a black diffuse color are left out. Markers are unlit, their color is scaled by `brightness`
+     * so they bloom, and they are `size` wide.
+     */
+    pub struct LightGizmoRenderer {
+        program: glium::Program,
+        vertices: glium::VertexBuffer<Vertex>,
+        indices: glium::IndexBuffer<u32>,
+        instances: glium::VertexBuffer<GizmoInstance>,
+        instance_data: Vec<GizmoInstance>,
+        pub enabled: bool,
+        pub size: f32,
+        pub brightness: f32,
+    }
+
+    impl LightGizmoRenderer {
+
+        pub fn new(display: &glium::Display) -> LightGizmoRenderer {
+            let cube = IndexedShape::from_shape(&Cube::new([-0.5, -0.5, -0.5], 1.0, 0));
+            return LightGizmoRenderer {
+                program: glium::Program::from_source(display, Shaders::LIGHT_GIZMO_VERTEX_SHADER, Shaders::LIGHT_GIZMO_FRAGMENT_SHADER, None).unwrap(),
+                vertices: glium::VertexBuffer::immutable(display, cube.get_vertices()).unwrap(),
+                indices: glium::IndexBuffer::immutable(display, glium::index::PrimitiveType::TrianglesList, cube.get_indices()).unwrap(),
+                instances: glium::VertexBuffer::empty_dynamic(display, 64).unwrap(),
+                instance_data: Vec::new(),
+                enabled: true,
+                size: 0.04,
+                brightness: 4.0,
+            }
+        }
+
+        /**
+         * Draws the markers of `lights` into a target with the depth buffer of the scene
+         */
+        pub fn render<S: glium::Surface>(&mut self, display: &glium::Display, target: &mut S, lights: &LightList,
+            view: &[[f32; 4]; 4], perspective: &[[f32; 4]; 4], draw_parameters: &glium::DrawParameters) {
+
+            if !self.enabled {
+                return;
+            }
+
+            self.instance_data.clear();
+            let point_lights = lights.point_lights.iter().map(|light| (light.position, light.diffuse_color));
+            let spot_lights = lights.spot_lights.iter().map(|light| (light.position, light.diffuse_color));
+            for (position, color) in point_lights.chain(spot_lights) {
+                if color.iter().any(|channel| *channel > 0.0) {
+                    self.instance_data.push(GizmoInstance {
+                        gizmo_position: position,
+                        gizmo_color: color,
+                    });
+                }
+            }
+            if self.instance_data.is_empty() {
+                return;
+            }
+
+            if self.instance_data.len() > self.instances.len() {
+                self.instances = glium::VertexBuffer::empty_dynamic(display, self.instance_data.len().next_power_of_two()).unwrap();
+            }
+            let instances = self.instances.slice(0 .. self.instance_data.len()).unwrap();
+            instances.write(&self.instance_data);
+
+            let uniforms = uniform! {
+                view: *view,
+                perspective: *perspective,
+                size: self.size,
+                brightness: self.brightness,
+            };
+            target.draw((&self.vertices, instances.per_instance().unwrap()), &self.indices, &self.program, &uniforms, draw_parameters).unwrap();
+        }
+    }
+}
@@ -0,0 +1,1254 @@
+pub mod GltfLoader {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
+
+    use crate::Base3D::General::*;
+    use crate::Lights3D::Lights::*;
+    use crate::Material3D::Material::*;
+    use crate::GraphicsLoader2D::GraphicsLoader;
+    use crate::Math3D::Math::*;
+    use crate::Mesh3D::Mesh::*;
+    use crate::Scene3D::Scene::*;
+    use crate::Transform3D::Transform::*;
+
+    /*
+    Minimal JSON reader, sufficient for glTF documents
+    */
+
+    #[derive(Clone)]
+    enum Json {
+        Null,
+        Bool(bool),
+        Number(f64),
+        String(String),
+        Array(Vec<Json>),
+        Object(Vec<(String, Json)>),
+    }
+
+    const NULL: Json = Json::Null;
+
+    impl Json {
+        fn get(&self, key: &str) -> &Json {
+            if let Json::Object(members) = self {
+                for (name, value) in members.iter() {
+                    if name == key {
+                        return value;
+                    }
+                }
+            }
+            return &NULL;
+        }
+
+        fn at(&self, index: usize) -> &Json {
+            if let Json::Array(values) = self {
+                if let Some(value) = values.get(index) {
+                    return value;
+                }
+            }
+            return &NULL;
+        }
+
+        fn len(&self) -> usize {
+            return match self {
+                Json::Array(values) => values.len(),
+                _ => 0,
+            };
+        }
+
+        fn is_null(&self) -> bool {
+            return match self {
+                Json::Null => true,
+                _ => false,
+            };
+        }
+
+        fn as_f32(&self) -> Option<f32> {
+            return match self {
+                Json::Number(n) => Some(*n as f32),
+                _ => None,
+            };
+        }
+
+        fn as_usize(&self) -> Option<usize> {
+            return match self {
+                Json::Number(n) if *n >= 0.0 => Some(*n as usize),
+                _ => None,
+            };
+        }
+
+        fn as_str(&self) -> Option<&str> {
+            return match self {
+                Json::String(s) => Some(s),
+                _ => None,
+            };
+        }
+
+        fn as_floats(&self) -> Vec<f32> {
+            return match self {
+                Json::Array(values) => values.iter().filter_map(|v| v.as_f32()).collect(),
+                _ => Vec::new(),
+            };
+        }
+
+        fn as_indices(&self) -> Vec<usize> {
+            return match self {
+                Json::Array(values) => values.iter().filter_map(|v| v.as_usize()).collect(),
+                _ => Vec::new(),
+            };
+        }
+    }
+
+    struct JsonParser<'a> {
+        bytes: &'a [u8],
+        position: usize,
+    }
+
+    impl JsonParser<'_> {
+        fn parse(source: &str) -> Result<Json, String> {
+            let mut parser = JsonParser { bytes: source.as_bytes(), position: 0 };
+            let value = parser.parse_value()?;
+            parser.skip_whitespace();
+            if parser.position != parser.bytes.len() {
+                return Err(format!("Unexpected data after JSON document at byte {}", parser.position));
+            }
+            return Ok(value);
+        }
+
+        fn skip_whitespace(&mut self) {
+            while self.position < self.bytes.len() && (self.bytes[self.position] as char).is_ascii_whitespace() {
+                self.position += 1;
+            }
+        }
+
+        fn peek(&mut self) -> Option<u8> {
+            self.skip_whitespace();
+            return self.bytes.get(self.position).copied();
+        }
+
+        fn expect(&mut self, c: u8) -> Result<(), String> {
+            if self.peek() != Some(c) {
+                return Err(format!("Expected '{}' at byte {}", c as char, self.position));
+            }
+            self.position += 1;
+            return Ok(());
+        }
+
+        fn parse_value(&mut self) -> Result<Json, String> {
+            return match self.peek() {
+                Some(b'{') => self.parse_object(),
+                Some(b'[') => self.parse_array(),
+                Some(b'"') => Ok(Json::String(self.parse_string()?)),
+                Some(b't') => self.parse_literal("true", Json::Bool(true)),
+                Some(b'f') => self.parse_literal("false", Json::Bool(false)),
+                Some(b'n') => self.parse_literal("null", Json::Null),
+                Some(_) => self.parse_number(),
+                None => Err("Unexpected end of JSON document".to_string()),
+            };
+        }
+
+        fn parse_literal(&mut self, literal: &str, value: Json) -> Result<Json, String> {
+            if self.bytes[self.position..].starts_with(literal.as_bytes()) {
+                self.position += literal.len();
+                return Ok(value);
+            }
+            return Err(format!("Invalid literal at byte {}", self.position));
+        }
+
+        fn parse_number(&mut self) -> Result<Json, String> {
+            let start = self.position;
+            while self.position < self.bytes.len() && b"+-0123456789.eE".contains(&self.bytes[self.position]) {
+                self.position += 1;
+            }
+            let text = std::str::from_utf8(&self.bytes[start..self.position]).unwrap();
+            return match text.parse::<f64>() {
+                Ok(n) => Ok(Json::Number(n)),
+                Err(_) => Err(format!("Invalid number '{}' at byte {}", text, start)),
+            };
+        }
+
+        fn parse_string(&mut self) -> Result<String, String> {
+            self.expect(b'"')?;
+            let mut bytes: Vec<u8> = Vec::new();
+            loop {
+                let c = match self.bytes.get(self.position) {
+                    Some(c) => *c,
+                    None => return Err("Unterminated string".to_string()),
+                };
+                self.position += 1;
+                match c {
+                    b'"' => break,
+                    b'\\' => {
+                        let escaped = self.bytes.get(self.position).copied().unwrap_or(b'"');
+                        self.position += 1;
+                        match escaped {
+                            b'n' => bytes.push(b'\n'),
+                            b't' => bytes.push(b'\t'),
+                            b'r' => bytes.push(b'\r'),
+                            b'b' => bytes.push(8),
+                            b'f' => bytes.push(12),
+                            b'u' => {
+                                let mut code = self.parse_code_unit();
+                                // Characters outside the basic plane are escaped as a UTF-16 surrogate pair
+                                if (0xD800..0xDC00).contains(&code) && self.bytes.get(self.position..).map_or(false, |rest| rest.starts_with(b"\\u")) {
+                                    let position = self.position;
+                                    self.position += 2;
+                                    let low = self.parse_code_unit();
+                                    if (0xDC00..0xE000).contains(&low) {
+                                        code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                                    } else {
+                                        self.position = position;
+                                    }
+                                }
+                                let c = char::from_u32(code).unwrap_or('\u{FFFD}');
+                                let mut buffer = [0u8; 4];
+                                bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                            },
+                            other => bytes.push(other),
+                        }
+                    },
+                    other => bytes.push(other),
+                }
+            }
+            return Ok(String::from_utf8_lossy(&bytes).into_owned());
+        }
+
+        /*
+        Reads the four hex digits of a \u escape
+        */
+        fn parse_code_unit(&mut self) -> u32 {
+            let hex = self.bytes.get(self.position..self.position + 4).and_then(|hex| std::str::from_utf8(hex).ok()).unwrap_or("");
+            self.position += 4;
+            return u32::from_str_radix(hex, 16).unwrap_or(0xFFFD);
+        }
+
+        fn parse_array(&mut self) -> Result<Json, String> {
+            self.expect(b'[')?;
+            let mut values: Vec<Json> = Vec::new();
+            if self.peek() == Some(b']') {
+                self.position += 1;
+                return Ok(Json::Array(values));
+            }
+            loop {
+                values.push(self.parse_value()?);
+                match self.peek() {
+                    Some(b',') => self.position += 1,
+                    Some(b']') => { self.position += 1; break; },
+                    _ => return Err(format!("Expected ',' or ']' at byte {}", self.position)),
+                }
+            }
+            return Ok(Json::Array(values));
+        }
+
+        fn parse_object(&mut self) -> Result<Json, String> {
+            self.expect(b'{')?;
+            let mut members: Vec<(String, Json)> = Vec::new();
+            if self.peek() == Some(b'}') {
+                self.position += 1;
+                return Ok(Json::Object(members));
+            }
+            loop {
+                self.skip_whitespace();
+                let key = self.parse_string()?;
+                self.expect(b':')?;
+                members.push((key, self.parse_value()?));
+                match self.peek() {
+                    Some(b',') => self.position += 1,
+                    Some(b'}') => { self.position += 1; break; },
+                    _ => return Err(format!("Expected ',' or '}}' at byte {}", self.position)),
+                }
+            }
+            return Ok(Json::Object(members));
+        }
+    }
+
+    fn decode_base64(data: &str) -> Result<Vec<u8>, String> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(data.len() * 3 / 4);
+        let mut accumulator: u32 = 0;
+        let mut bits: u32 = 0;
+        for c in data.bytes() {
+            let value = match c {
+                b'A'..=b'Z' => c - b'A',
+                b'a'..=b'z' => c - b'a' + 26,
+                b'0'..=b'9' => c - b'0' + 52,
+                b'+' | b'-' => 62,
+                b'/' | b'_' => 63,
+                b'=' => break,
+                b' ' | b'\n' | b'\r' | b'\t' => continue,
+                _ => return Err("Invalid base64 data".to_string()),
+            };
+            accumulator = (accumulator << 6) | value as u32;
+            bits += 6;
+            if bits >= 8 {
+                bits -= 8;
+                bytes.push((accumulator >> bits) as u8);
+                accumulator &= (1 << bits) - 1;
+            }
+        }
+        return Ok(bytes);
+    }
+
+    /*
+    glTF is right-handed with +Y up and outward facing normals, while the engine is
+    left-handed (+Z forward) with normals pointing into surfaces. Everything is
+    mirrored along Z on import: points p become C*p and matrices M become C*M*C
+    with C = diag(1, 1, -1), and normals are flipped after mirroring.
+    */
+
+    fn convert_position(p: [f32; 3]) -> [f32; 3] {
+        return [p[0], p[1], -p[2]];
+    }
+
+    fn convert_normal(n: [f32; 3]) -> [f32; 3] {
+        return [-n[0], -n[1], n[2]];
+    }
+
+    fn convert_matrix(m: [[f32; 4]; 4]) -> [[f32; 4]; 4] {
+        let mut converted = m;
+        for column in 0..4 {
+            for row in 0..4 {
+                if (column == 2) != (row == 2) {
+                    converted[column][row] = -m[column][row];
+                }
+            }
+        }
+        return converted;
+    }
+
+    fn trs_matrix(translation: &[f32], rotation: &[f32], scale: &[f32]) -> [[f32; 4]; 4] {
+        let (x, y, z, w) = (rotation[0], rotation[1], rotation[2], rotation[3]);
+        return [
+            [(1.0 - 2.0*(y*y + z*z)) * scale[0], 2.0*(x*y + z*w) * scale[0], 2.0*(x*z - y*w) * scale[0], 0.0],
+            [2.0*(x*y - z*w) * scale[1], (1.0 - 2.0*(x*x + z*z)) * scale[1], 2.0*(y*z + x*w) * scale[1], 0.0],
+            [2.0*(x*z + y*w) * scale[2], 2.0*(y*z - x*w) * scale[2], (1.0 - 2.0*(x*x + y*y)) * scale[2], 0.0],
+            [translation[0], translation[1], translation[2], 1.0],
+        ];
+    }
+
+    /**
+     * A KHR_lights_punctual light in the local space of its node,
+     * placed at the origin and shining along +Z (the converted glTF -Z)
+     */
+    #[derive(Copy, Clone)]
+    pub enum GltfLight {
+        Directional(DirectionalLight),
+        Point(PointLight),
+        Spot(SpotLight),
+    }
+
+    pub struct GltfNode {
+        pub name: String,
+        pub parent: Option<usize>,
+        pub children: Vec<usize>,
+        pub local_transform: [[f32; 4]; 4],
+        pub mesh: Option<usize>,
+        pub light: Option<GltfLight>,
+    }
+
+    /**
+     * Result of importing a glTF scene.
+     * Meshes are kept in the local space of the nodes referencing them, use
+     * `add_to_scene_graph` to place them in a scene with the node hierarchy.
+     * As for OBJ models, material ids start at the given material offset and
+     * `images` must be appended to the texture array after the images already present,
+     * and `data_images`, the normal, metallic / roughness and occlusion maps, to the data texture array.
+     */
+    pub struct GltfScene {
+        pub meshes: Vec<AShape>,
+        pub nodes: Vec<GltfNode>,
+        pub roots: Vec<usize>,
+        pub materials: Vec<Material>,
+        pub images: Vec<glium::texture::RawImage2d<'static, u8>>,
+        pub data_images: Vec<glium::texture::RawImage2d<'static, u8>>,
+    }
+
+    impl GltfScene {
+
+        /**
+         * Adds the nodes of the scene below `parent` with their names, hierarchy, local transforms and lights,
+         * so they can be looked up with `SceneGraph::find` and moved like any other node.
+         * Every mesh is uploaded once and shared by all nodes referencing it. Returns the added root nodes.
+         */
+        pub fn add_to_scene_graph(&self, display: &glium::Display, scene_graph: &mut SceneGraph, parent: Option<NodeId>) -> Vec<NodeId> {
+            let meshes: Vec<Option<Rc<GpuMesh>>> = self.meshes.iter().map(|mesh| {
+                if mesh.get_vertices().is_empty() {
+                    return None;
+                }
+                return Some(Rc::new(GpuMesh::new_static(display, &IndexedShape::from_shape(mesh))));
+            }).collect();
+
+            let mut roots: Vec<NodeId> = Vec::new();
+            let mut visited: Vec<bool> = vec![false; self.nodes.len()];
+            let mut stack: Vec<(usize, Option<NodeId>)> = self.roots.iter().rev().map(|root| (*root, None)).collect();
+            while let Some((index, node_parent)) = stack.pop() {
+                if index >= self.nodes.len() || visited[index] {
+                    continue;
+                }
+                visited[index] = true;
+                let node = &self.nodes[index];
+                let id = scene_graph.add_node(&node.name, Transform::from_matrix(&node.local_transform), node_parent.or(parent));
+                let scene_node = scene_graph.get_mut(id).unwrap();
+                scene_node.mesh = node.mesh.and_then(|mesh| meshes[mesh].clone());
+                scene_node.light = node.light.map(|light| match light {
+                    GltfLight::Directional(light) => SceneLight::Directional(light),
+                    GltfLight::Point(light) => SceneLight::Point(light),
+                    GltfLight::Spot(light) => SceneLight::Spot(light),
+                });
+                if node_parent.is_none() {
+                    roots.push(id);
+                }
+                stack.extend(node.children.iter().rev().map(|child| (*child, Some(id))));
+            }
+            return roots;
+        }
+    }
+
+    struct Document {
+        json: Json,
+        buffers: Vec<Vec<u8>>,
+        directory: PathBuf,
+    }
+
+    impl Document {
+
+        fn buffer_view(&self, index: usize) -> Result<(&[u8], usize), String> {
+            let view = self.json.get("bufferViews").at(index);
+            let buffer = match view.get("buffer").as_usize().and_then(|b| self.buffers.get(b)) {
+                Some(buffer) => buffer,
+                None => return Err(format!("Buffer view {} references a missing buffer", index)),
+            };
+            let offset = view.get("byteOffset").as_usize().unwrap_or(0);
+            let length = view.get("byteLength").as_usize().unwrap_or(0);
+            if offset + length > buffer.len() {
+                return Err(format!("Buffer view {} is out of bounds", index));
+            }
+            let stride = view.get("byteStride").as_usize().unwrap_or(0);
+            return Ok((&buffer[offset..offset + length], stride));
+        }
+
+        /*
+        Reads an accessor as floats, returning the values and the number of components per element
+        */
+        fn read_accessor(&self, index: usize) -> Result<(Vec<f32>, usize), String> {
+            let accessor = self.json.get("accessors").at(index);
+            let count = accessor.get("count").as_usize().unwrap_or(0);
+            let components = match accessor.get("type").as_str() {
+                Some("SCALAR") => 1,
+                Some("VEC2") => 2,
+                Some("VEC3") => 3,
+                Some("VEC4") => 4,
+                Some("MAT4") => 16,
+                _ => return Err(format!("Accessor {} has an unsupported type", index)),
+            };
+            let component_type = accessor.get("componentType").as_usize().unwrap_or(0);
+            let component_size = match component_type {
+                5120 | 5121 => 1,
+                5122 | 5123 => 2,
+                5126 => 4,
+                _ => return Err(format!("Accessor {} has an unsupported component type", index)),
+            };
+            let normalized = match accessor.get("normalized") {
+                Json::Bool(b) => *b,
+                _ => false,
+            };
+
+            let view_index = match accessor.get("bufferView").as_usize() {
+                Some(view_index) => view_index,
+                None => return Ok((vec![0.0; count * components], components)),
+            };
+            let (data, stride) = self.buffer_view(view_index)?;
+            let offset = accessor.get("byteOffset").as_usize().unwrap_or(0);
+            let stride = if stride == 0 { components * component_size } else { stride };
+
+            let mut values: Vec<f32> = Vec::with_capacity(count * components);
+            for element in 0..count {
+                for component in 0..components {
+                    let start = offset + element * stride + component * component_size;
+                    if start + component_size > data.len() {
+                        return Err(format!("Accessor {} reads past the end of its buffer view", index));
+                    }
+                    let bytes = &data[start..start + component_size];
+                    let value = match component_type {
+                        5120 => { let v = bytes[0] as i8 as f32; if normalized { (v / 127.0).max(-1.0) } else { v } },
+                        5121 => { let v = bytes[0] as f32; if normalized { v / 255.0 } else { v } },
+                        5122 => { let v = i16::from_le_bytes([bytes[0], bytes[1]]) as f32; if normalized { (v / 32767.0).max(-1.0) } else { v } },
+                        5123 => { let v = u16::from_le_bytes([bytes[0], bytes[1]]) as f32; if normalized { v / 65535.0 } else { v } },
+                        _ => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+                    };
+                    values.push(value);
+                }
+            }
+            return Ok((values, components));
+        }
+
+        /*
+        Reads an index accessor as integers, 32 bit indices do not fit into floats
+        */
+        fn read_indices(&self, index: usize) -> Result<Vec<u32>, String> {
+            let accessor = self.json.get("accessors").at(index);
+            let count = accessor.get("count").as_usize().unwrap_or(0);
+            if accessor.get("type").as_str() != Some("SCALAR") {
+                return Err(format!("Index accessor {} is not scalar", index));
+            }
+            let component_type = accessor.get("componentType").as_usize().unwrap_or(0);
+            let component_size = match component_type {
+                5121 => 1,
+                5123 => 2,
+                5125 => 4,
+                _ => return Err(format!("Index accessor {} has an unsupported component type", index)),
+            };
+
+            let view_index = match accessor.get("bufferView").as_usize() {
+                Some(view_index) => view_index,
+                None => return Ok(vec![0; count]),
+            };
+            let (data, stride) = self.buffer_view(view_index)?;
+            let offset = accessor.get("byteOffset").as_usize().unwrap_or(0);
+            let stride = if stride == 0 { component_size } else { stride };
+
+            let mut indices: Vec<u32> = Vec::with_capacity(count);
+            for element in 0..count {
+                let start = offset + element * stride;
+                if start + component_size > data.len() {
+                    return Err(format!("Accessor {} reads past the end of its buffer view", index));
+                }
+                let bytes = &data[start..start + component_size];
+                indices.push(match component_type {
+                    5121 => bytes[0] as u32,
+                    5123 => u16::from_le_bytes([bytes[0], bytes[1]]) as u32,
+                    _ => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+                });
+            }
+            return Ok(indices);
+        }
+
+        fn load_image(&self, index: usize) -> Result<glium::texture::RawImage2d<'static, u8>, String> {
+            let image = self.json.get("images").at(index);
+            if let Some(uri) = image.get("uri").as_str() {
+                if uri.starts_with("data:") {
+                    let data = decode_base64(uri.splitn(2, ',').nth(1).unwrap_or(""))?;
+                    return GraphicsLoader::load_image_from_memory(&data);
+                }
+                return GraphicsLoader::load_image_from_path(&self.directory.join(uri));
+            }
+            return match image.get("bufferView").as_usize() {
+                Some(view) => GraphicsLoader::load_image_from_memory(self.buffer_view(view)?.0),
+                None => Err(format!("Image {} has no data", index)),
+            };
+        }
+    }
+
+    /*
+    Images of one texture array, every glTF image is added once however many materials use it.
+    Ids start at `offset`, the number of images already in the array.
+    */
+    struct ImageRegistry {
+        offset: i32,
+        images: Vec<glium::texture::RawImage2d<'static, u8>>,
+        ids: HashMap<usize, i32>,
+    }
+
+    impl ImageRegistry {
+        fn new(offset: i32) -> ImageRegistry {
+            return ImageRegistry { offset: offset, images: Vec::new(), ids: HashMap::new() };
+        }
+
+        /*
+        Id of the image of a texture info object, None if there is none
+        */
+        fn register(&mut self, document: &Document, texture: &Json) -> Result<Option<i32>, String> {
+            let json = &document.json;
+            let source = match texture.get("index").as_usize().and_then(|t| json.get("textures").at(t).get("source").as_usize()) {
+                Some(source) => source,
+                None => return Ok(None),
+            };
+            if let Some(id) = self.ids.get(&source) {
+                return Ok(Some(*id));
+            }
+            let id = self.offset + self.images.len() as i32;
+            self.images.push(document.load_image(source)?);
+            self.ids.insert(source, id);
+            return Ok(Some(id));
+        }
+
+        fn register_color(&mut self, color: [f32; 3]) -> i32 {
+            self.images.push(GraphicsLoader::load_image_from_color(color));
+            return self.offset + self.images.len() as i32 - 1;
+        }
+    }
+
+    fn load_buffers(json: &Json, directory: &Path, binary_chunk: Option<Vec<u8>>) -> Result<Vec<Vec<u8>>, String> {
+        let mut buffers: Vec<Vec<u8>> = Vec::new();
+        let mut binary_chunk = binary_chunk;
+        for i in 0..json.get("buffers").len() {
+            let buffer = json.get("buffers").at(i);
+            let data = match buffer.get("uri").as_str() {
+                Some(uri) if uri.starts_with("data:") => decode_base64(uri.splitn(2, ',').nth(1).unwrap_or(""))?,
+                Some(uri) => match fs::read(directory.join(uri)) {
+                    Ok(data) => data,
+                    Err(error) => return Err(format!("Could not read buffer {}: {}", uri, error)),
+                },
+                None => match binary_chunk.take() {
+                    Some(data) => data,
+                    None => return Err(format!("Buffer {} has no data", i)),
+                },
+            };
+            buffers.push(data);
+        }
+        return Ok(buffers);
+    }
+
+    /*
+    Splits a binary .glb container into its JSON and BIN chunks
+    */
+    fn parse_glb(data: &[u8]) -> Result<(String, Option<Vec<u8>>), String> {
+        let read_u32 = |offset: usize| -> u32 {
+            u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+        };
+        if data.len() < 12 || read_u32(0) != 0x46546C67 {
+            return Err("Not a binary glTF file".to_string());
+        }
+        if read_u32(4) != 2 {
+            return Err("Only glTF 2.0 is supported".to_string());
+        }
+
+        let mut json: Option<String> = None;
+        let mut binary: Option<Vec<u8>> = None;
+        let mut offset = 12;
+        while offset + 8 <= data.len() {
+            let length = read_u32(offset) as usize;
+            let chunk_type = read_u32(offset + 4);
+            let start = offset + 8;
+            if start + length > data.len() {
+                return Err("Truncated chunk in binary glTF file".to_string());
+            }
+            match chunk_type {
+                0x4E4F534A => json = Some(String::from_utf8_lossy(&data[start..start + length]).into_owned()),
+                0x004E4942 => binary = Some(data[start..start + length].to_vec()),
+                _ => (),
+            }
+            offset = start + length;
+        }
+
+        return match json {
+            Some(json) => Ok((json, binary)),
+            None => Err("Binary glTF file has no JSON chunk".to_string()),
+        };
+    }
+
+    /*
+    Maps a metallic/roughness description onto the Phong model used by the shaders.
+    The specular color follows the usual F0 approximation and roughness drives the exponent.
+    */
+    fn specular_color(base_color: [f32; 3], metallic: f32) -> [f32; 3] {
+        return [
+            0.04 + (base_color[0] - 0.04) * metallic,
+            0.04 + (base_color[1] - 0.04) * metallic,
+            0.04 + (base_color[2] - 0.04) * metallic,
+        ];
+    }
+
+    fn shininess_from_roughness(roughness: f32) -> f32 {
+        let alpha = roughness.max(0.05).powi(2);
+        return (2.0 / (alpha * alpha) - 2.0).max(1.0).min(256.0);
+    }
+
+    /*
+    Attenuation (linear, quadratic) of a point or spot light with distances in meters.
+    The quadratic term gives the inverse square falloff of a physical light, with a linear term
+    added when the glTF range ends the light before the falloff drops it to 1/256.
+    */
+    fn light_attenuation(range: Option<f32>) -> (f32, f32) {
+        return match range {
+            Some(range) if range > 0.0 && range * range < 255.0 => ((255.0 - range * range) / range, 1.0),
+            _ => (0.0, 1.0),
+        };
+    }
+
+    /*
+    KHR_lights_punctual gives directional intensities as illuminance in lux and point and spot
+    intensities as luminous intensity in candela. A light color of 1.0 stands for 1 lux: directional
+    lights are scaled by their illuminance, and point and spot lights by their intensity, which is
+    the illuminance they give at one meter under the inverse square falloff of `light_attenuation`.
+    */
+    fn parse_light(light: &Json) -> Result<GltfLight, String> {
+        let color = light.get("color").as_floats();
+        let color = if color.len() >= 3 { [color[0], color[1], color[2]] } else { [1.0, 1.0, 1.0] };
+        let intensity = light.get("intensity").as_f32().unwrap_or(1.0);
+        let color = [color[0] * intensity, color[1] * intensity, color[2] * intensity];
+        let (linear, quadratic) = light_attenuation(light.get("range").as_f32());
+
+        let direction = [0.0, 0.0, 1.0];
+        let origin = [0.0, 0.0, 0.0];
+
+        return match light.get("type").as_str() {
+            Some("directional") => Ok(GltfLight::Directional(DirectionalLight::new(direction, color))),
+            Some("point") => Ok(GltfLight::Point(PointLight::new_with_parameters(origin, color, linear, quadratic))),
+            Some("spot") => {
+                let spot = light.get("spot");
+                let inner = spot.get("innerConeAngle").as_f32().unwrap_or(0.0);
+                let outer = spot.get("outerConeAngle").as_f32().unwrap_or(std::f32::consts::FRAC_PI_4);
+                let mut spot_light = SpotLight::new(origin, direction, inner.to_degrees(), color);
+                spot_light.outer_cutoff = outer.cos();
+                spot_light.linear = linear;
+                spot_light.quadratic = quadratic;
+                Ok(GltfLight::Spot(spot_light))
+            },
+            _ => Err("Unsupported light type".to_string()),
+        };
+    }
+
+    pub struct GltfLoader {}
+    impl GltfLoader {
+
+        /**
+         * Loads a .gltf or .glb file.
+         * `material_offset` is the first free slot in the material array and
+         * `texture_offset` and `data_texture_offset` are the numbers of images already in the
+         * color and the data texture array.
+         */
+        pub fn load(path: &Path, material_offset: i32, texture_offset: i32, data_texture_offset: i32) -> Result<GltfScene, String> {
+            let data = match fs::read(path) {
+                Ok(data) => data,
+                Err(error) => return Err(format!("Could not read {}: {}", path.display(), error)),
+            };
+            let directory = path.parent().unwrap_or(Path::new("")).to_path_buf();
+
+            let (source, binary_chunk) = if data.starts_with(b"glTF") {
+                parse_glb(&data)?
+            } else {
+                (String::from_utf8_lossy(&data).into_owned(), None)
+            };
+            let json = JsonParser::parse(&source)?;
+            let buffers = load_buffers(&json, &directory, binary_chunk)?;
+            let document = Document { json: json, buffers: buffers, directory: directory };
+            let json = &document.json;
+
+            // Images are registered lazily so unused ones are never uploaded
+            let mut images = ImageRegistry::new(texture_offset);
+            let mut data_images = ImageRegistry::new(data_texture_offset);
+
+            // Materials
+            let mut materials: Vec<Material> = Vec::new();
+            for i in 0..json.get("materials").len() {
+                let material = json.get("materials").at(i);
+                let pbr = material.get("pbrMetallicRoughness");
+                let factor = pbr.get("baseColorFactor").as_floats();
+                let base_color = if factor.len() >= 3 { [factor[0], factor[1], factor[2]] } else { [1.0, 1.0, 1.0] };
+                let metallic = pbr.get("metallicFactor").as_f32().unwrap_or(1.0);
+                let roughness = pbr.get("roughnessFactor").as_f32().unwrap_or(1.0);
+
+                let diffuse = match images.register(&document, pbr.get("baseColorTexture"))? {
+                    Some(id) => id,
+                    None => images.register_color(base_color),
+                };
+
+                // KHR_materials_specular overrides the derived specular color
+                let specular_extension = material.get("extensions").get("KHR_materials_specular");
+                let specular = match images.register(&document, specular_extension.get("specularColorTexture"))? {
+                    Some(id) => id,
+                    None => {
+                        let factor = specular_extension.get("specularColorFactor").as_floats();
+                        let color = if factor.len() >= 3 { [factor[0], factor[1], factor[2]] } else { specular_color(base_color, metallic) };
+                        images.register_color(color)
+                    },
+                };
+
+                // Shaded with the metallic / roughness model, the Phong textures above stay as fallback
+                let mut pbr_material = Material::new(diffuse, specular, shininess_from_roughness(roughness));
+                pbr_material.shading = ShadingModel::Pbr;
+                pbr_material.base_color = images.register(&document, pbr.get("baseColorTexture"))?.unwrap_or(NO_TEXTURE);
+                pbr_material.base_color_factor = base_color;
+                pbr_material.metallic_factor = metallic;
+                pbr_material.roughness_factor = roughness;
+                if let Some(id) = data_images.register(&document, pbr.get("metallicRoughnessTexture"))? {
+                    pbr_material.metallic = id;
+                    pbr_material.roughness = id;
+                }
+                let occlusion = material.get("occlusionTexture");
+                if let Some(id) = data_images.register(&document, occlusion)? {
+                    pbr_material.occlusion = id;
+                    pbr_material.occlusion_strength = occlusion.get("strength").as_f32().unwrap_or(1.0);
+                }
+                let emissive_factor = material.get("emissiveFactor").as_floats();
+                if emissive_factor.len() >= 3 {
+                    pbr_material.emissive_factor = [emissive_factor[0], emissive_factor[1], emissive_factor[2]];
+                }
+                if let Some(id) = images.register(&document, material.get("emissiveTexture"))? {
+                    pbr_material.emissive = id;
+                }
+                let normal = material.get("normalTexture");
+                if let Some(id) = data_images.register(&document, normal)? {
+                    pbr_material.normal = id;
+                    pbr_material.normal_scale = normal.get("scale").as_f32().unwrap_or(1.0);
+                }
+                pbr_material.alpha_mode = match material.get("alphaMode").as_str() {
+                    Some("MASK") => AlphaMode::Mask,
+                    Some("BLEND") => AlphaMode::Blend,
+                    _ => AlphaMode::Opaque,
+                };
+                pbr_material.opacity = if factor.len() >= 4 { factor[3] } else { 1.0 };
+                pbr_material.alpha_cutoff = material.get("alphaCutoff").as_f32().unwrap_or(0.5);
+
+                materials.push(pbr_material);
+            }
+            let default_material = material_offset + materials.len() as i32;
+
+            // Meshes, one shape per glTF mesh combining all its primitives
+            let mut meshes: Vec<AShape> = Vec::new();
+            let mut uses_default_material = false;
+            for i in 0..json.get("meshes").len() {
+                let primitives = json.get("meshes").at(i).get("primitives");
+                let mut shapes: Vec<AShape> = Vec::new();
+                for j in 0..primitives.len() {
+                    let primitive = primitives.at(j);
+                    let material_id = match primitive.get("material").as_usize() {
+                        Some(material) => material_offset + material as i32,
+                        None => {
+                            uses_default_material = true;
+                            default_material
+                        },
+                    };
+                    shapes.push(GltfLoader::load_primitive(&document, primitive, material_id)?);
+                }
+                meshes.push(combine_shapes(shapes.iter().collect::<Vec<&AShape>>()));
+            }
+
+            if uses_default_material {
+                let diffuse = images.register_color([0.8, 0.8, 0.8]);
+                let specular = images.register_color([0.04, 0.04, 0.04]);
+                materials.push(Material::new(diffuse, specular, 16.0));
+            }
+
+            if material_offset + materials.len() as i32 > MAX_MATERIALS {
+                return Err(format!("{} needs {} materials, only {} slots are available", path.display(), materials.len(), MAX_MATERIALS - material_offset));
+            }
+
+            // Lights
+            let mut lights: Vec<GltfLight> = Vec::new();
+            let light_definitions = json.get("extensions").get("KHR_lights_punctual").get("lights");
+            for i in 0..light_definitions.len() {
+                lights.push(parse_light(light_definitions.at(i))?);
+            }
+
+            // Nodes
+            let mut nodes: Vec<GltfNode> = Vec::new();
+            for i in 0..json.get("nodes").len() {
+                let node = json.get("nodes").at(i);
+                let matrix = node.get("matrix").as_floats();
+                let local_transform = if matrix.len() == 16 {
+                    [
+                        [matrix[0], matrix[1], matrix[2], matrix[3]],
+                        [matrix[4], matrix[5], matrix[6], matrix[7]],
+                        [matrix[8], matrix[9], matrix[10], matrix[11]],
+                        [matrix[12], matrix[13], matrix[14], matrix[15]],
+                    ]
+                } else {
+                    let translation = node.get("translation").as_floats();
+                    let rotation = node.get("rotation").as_floats();
+                    let scale = node.get("scale").as_floats();
+                    trs_matrix(
+                        if translation.len() == 3 { &translation } else { &[0.0, 0.0, 0.0] },
+                        if rotation.len() == 4 { &rotation } else { &[0.0, 0.0, 0.0, 1.0] },
+                        if scale.len() == 3 { &scale } else { &[1.0, 1.0, 1.0] },
+                    )
+                };
+
+                nodes.push(GltfNode {
+                    name: node.get("name").as_str().unwrap_or("").to_string(),
+                    parent: None,
+                    children: node.get("children").as_indices(),
+                    local_transform: convert_matrix(local_transform),
+                    mesh: node.get("mesh").as_usize().filter(|mesh| *mesh < meshes.len()),
+                    light: node.get("extensions").get("KHR_lights_punctual").get("light").as_usize().and_then(|light| lights.get(light).copied()),
+                });
+            }
+
+            for i in 0..nodes.len() {
+                for child in nodes[i].children.clone() {
+                    match nodes.get_mut(child) {
+                        Some(node) => node.parent = Some(i),
+                        None => return Err(format!("Node {} has an invalid child {}", i, child)),
+                    }
+                }
+            }
+
+            // Roots are the nodes of the default scene, or every parentless node
+            let scene = json.get("scenes").at(json.get("scene").as_usize().unwrap_or(0));
+            let roots: Vec<usize> = if scene.is_null() {
+                (0..nodes.len()).filter(|i| nodes[*i].parent.is_none()).collect()
+            } else {
+                scene.get("nodes").as_indices()
+            };
+
+            // Find the nodes reachable from the roots
+            let mut stack: Vec<usize> = roots.clone();
+            let mut visited: Vec<bool> = vec![false; nodes.len()];
+            while let Some(index) = stack.pop() {
+                if index >= nodes.len() || visited[index] {
+                    continue;
+                }
+                visited[index] = true;
+                stack.extend(nodes[index].children.iter());
+            }
+
+            // Nodes outside the active scene are not part of the import
+            for i in 0..nodes.len() {
+                if !visited[i] {
+                    nodes[i].mesh = None;
+                    nodes[i].light = None;
+                }
+            }
+
+            return Ok(GltfScene {
+                meshes: meshes,
+                nodes: nodes,
+                roots: roots,
+                materials: materials,
+                images: images.images,
+                data_images: data_images.images,
+            });
+        }
+
+        fn load_primitive(document: &Document, primitive: &Json, material_id: i32) -> Result<AShape, String> {
+            let attributes = primitive.get("attributes");
+            let positions = match attributes.get("POSITION").as_usize() {
+                Some(accessor) => GltfLoader::read_attribute(document, accessor, "POSITION", 3, 0)?,
+                None => return Err("Primitive without positions".to_string()),
+            };
+            let vertex_count = positions.len() / 3;
+            let normals = match attributes.get("NORMAL").as_usize() {
+                Some(accessor) => Some(GltfLoader::read_attribute(document, accessor, "NORMAL", 3, vertex_count)?),
+                None => None,
+            };
+            let textures = match attributes.get("TEXCOORD_0").as_usize() {
+                Some(accessor) => Some(GltfLoader::read_attribute(document, accessor, "TEXCOORD_0", 2, vertex_count)?),
+                None => None,
+            };
+
+            let indices: Vec<usize> = match primitive.get("indices").as_usize() {
+                Some(accessor) => document.read_indices(accessor)?.iter().map(|i| *i as usize).collect(),
+                None => (0..vertex_count).collect(),
+            };
+            if indices.iter().any(|i| *i >= vertex_count) {
+                return Err("Primitive index out of range".to_string());
+            }
+
+            // Expand strips and fans into a triangle list
+            let mut triangles: Vec<[usize; 3]> = Vec::new();
+            match primitive.get("mode").as_usize().unwrap_or(4) {
+                4 => for t in indices.chunks_exact(3) { triangles.push([t[0], t[1], t[2]]) },
+                5 => for i in 2..indices.len() {
+                    if i % 2 == 0 {
+                        triangles.push([indices[i - 2], indices[i - 1], indices[i]]);
+                    } else {
+                        triangles.push([indices[i - 1], indices[i - 2], indices[i]]);
+                    }
+                },
+                6 => for i in 2..indices.len() { triangles.push([indices[0], indices[i - 1], indices[i]]) },
+                _ => return Ok(AShape { vertices: Vec::new(), normals: Vec::new() }),
+            }
+
+            let mut shape = AShape {
+                vertices: Vec::with_capacity(triangles.len() * 3),
+                normals: Vec::with_capacity(triangles.len() * 3),
+            };
+            for triangle in triangles.iter() {
+                let p: Vec<[f32; 3]> = triangle.iter().map(|i| convert_position([positions[3*i], positions[3*i + 1], positions[3*i + 2]])).collect();
+                let flat_normal = vnormalize(Normal::normal([
+                    [p[1][0] - p[0][0], p[1][1] - p[0][1], p[1][2] - p[0][2]],
+                    [p[2][0] - p[0][0], p[2][1] - p[0][1], p[2][2] - p[0][2]],
+                ]).normal);
+
+                for (j, i) in triangle.iter().enumerate() {
+                    shape.vertices.push(Vertex {
+                        position: p[j],
+                        // glTF places the texture origin in the top left corner
+                        texture: match &textures {
+                            Some(t) => [t[2*i], 1.0 - t[2*i + 1]],
+                            None => [0.0, 0.0],
+                        },
+                        material_id: material_id,
+                    });
+                    shape.normals.push(Normal {
+                        normal: match &normals {
+                            Some(n) => convert_normal(vnormalize([n[3*i], n[3*i + 1], n[3*i + 2]])),
+                            None => flat_normal,
+                        }
+                    });
+                }
+            }
+
+            return Ok(shape);
+        }
+
+        /*
+        Reads a vertex attribute, which needs `components` values per element and at least `vertex_count` elements
+        */
+        fn read_attribute(document: &Document, accessor: usize, name: &str, components: usize, vertex_count: usize) -> Result<Vec<f32>, String> {
+            let (values, accessor_components) = document.read_accessor(accessor)?;
+            if accessor_components != components {
+                return Err(format!("{} accessor {} has {} components instead of {}", name, accessor, accessor_components, components));
+            }
+            if values.len() / components < vertex_count {
+                return Err(format!("{} accessor {} is too short", name, accessor));
+            }
+            return Ok(values);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /*
+        The fixtures share one embedded buffer holding a triangle at (0, 0, 0), (1, 0, 0) and (0, 1, 0)
+        with normals along +Z and texture coordinates (0, 0), (1, 0) and (0, 1)
+        */
+        /*
+        Positions, normals and texture coordinates of three vertices
+        */
+        const TRIANGLE: &str = r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": 96, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/" }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 72, "byteLength": 24 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" },
+                { "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3" },
+                { "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC2" }
+            ],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2 } }] }],
+            "nodes": [{ "name": "triangle", "mesh": 0 }],
+            "scenes": [{ "nodes": [0] }]
+        }"#;
+
+        /*
+        Only two normals for three vertices
+        */
+        const SHORT_NORMALS: &str = r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": 96, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/" }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 72, "byteLength": 24 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" },
+                { "bufferView": 1, "componentType": 5126, "count": 2, "type": "VEC3" },
+                { "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC2" }
+            ],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2 } }] }],
+            "nodes": [{ "name": "triangle", "mesh": 0 }],
+            "scenes": [{ "nodes": [0] }]
+        }"#;
+
+        /*
+        Only two texture coordinates for three vertices
+        */
+        const SHORT_TEXCOORDS: &str = r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": 96, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/" }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 72, "byteLength": 24 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" },
+                { "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3" },
+                { "bufferView": 2, "componentType": 5126, "count": 2, "type": "VEC2" }
+            ],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2 } }] }],
+            "nodes": [{ "name": "triangle", "mesh": 0 }],
+            "scenes": [{ "nodes": [0] }]
+        }"#;
+
+        /*
+        Texture coordinates declared with three components
+        */
+        const VEC3_TEXCOORDS: &str = r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": 96, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/" }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 72, "byteLength": 24 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" },
+                { "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3" },
+                { "bufferView": 2, "componentType": 5126, "count": 2, "type": "VEC3" }
+            ],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2 } }] }],
+            "nodes": [{ "name": "triangle", "mesh": 0 }],
+            "scenes": [{ "nodes": [0] }]
+        }"#;
+
+        /*
+        Binary chunk of the GLB fixture: the triangle positions followed by 32 bit indices 0, 2, 1
+        */
+        fn indexed_triangle_chunk() -> Vec<u8> {
+            let mut chunk: Vec<u8> = Vec::new();
+            for value in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0].iter() {
+                chunk.extend_from_slice(&value.to_le_bytes());
+            }
+            for index in [0u32, 2, 1].iter() {
+                chunk.extend_from_slice(&index.to_le_bytes());
+            }
+            return chunk;
+        }
+
+        const INDEXED_TRIANGLE: &str = r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": 48 }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 12 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" },
+                { "bufferView": 1, "componentType": 5125, "count": 3, "type": "SCALAR" }
+            ],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 }, "indices": 1 }] }],
+            "nodes": [{ "name": "indexed 🔺", "mesh": 0 }]
+        }"#;
+
+        /* Packs a JSON and a binary chunk into a .glb container */
+        fn glb(json: &str, binary: &[u8]) -> Vec<u8> {
+            let mut json = json.as_bytes().to_vec();
+            json.resize((json.len() + 3) / 4 * 4, b' ');
+            let mut binary = binary.to_vec();
+            binary.resize((binary.len() + 3) / 4 * 4, 0);
+
+            let mut data: Vec<u8> = Vec::new();
+            data.extend_from_slice(&0x46546C67u32.to_le_bytes());
+            data.extend_from_slice(&2u32.to_le_bytes());
+            data.extend_from_slice(&((12 + 8 + json.len() + 8 + binary.len()) as u32).to_le_bytes());
+            data.extend_from_slice(&(json.len() as u32).to_le_bytes());
+            data.extend_from_slice(&0x4E4F534Au32.to_le_bytes());
+            data.extend_from_slice(&json);
+            data.extend_from_slice(&(binary.len() as u32).to_le_bytes());
+            data.extend_from_slice(&0x004E4942u32.to_le_bytes());
+            data.extend_from_slice(&binary);
+            return data;
+        }
+
+        fn load(name: &str, extension: &str, data: &[u8]) -> Result<GltfScene, String> {
+            let path = std::env::temp_dir().join(format!("gltf_loader_{}_{}.{}", name, std::process::id(), extension));
+            fs::write(&path, data).unwrap();
+            let result = GltfLoader::load(&path, 0, 0, 0);
+            fs::remove_file(&path).unwrap();
+            return result;
+        }
+
+        #[test]
+        fn loads_complete_attributes() {
+            let scene = load("complete", "gltf", TRIANGLE.as_bytes()).unwrap();
+            assert_eq!(scene.meshes.len(), 1);
+            assert_eq!(scene.nodes[0].name, "triangle");
+            let mesh = &scene.meshes[0];
+            assert_eq!(mesh.vertices.len(), 3);
+            assert_eq!(mesh.vertices[1].position, [1.0, 0.0, 0.0]);
+            // The texture origin moves to the bottom left, and normals are mirrored along Z and flipped
+            assert_eq!(mesh.vertices[2].texture, [0.0, 0.0]);
+            assert_eq!(mesh.normals[0].normal, [0.0, 0.0, 1.0]);
+        }
+
+        #[test]
+        fn rejects_short_normals() {
+            let error = load("short_normals", "gltf", SHORT_NORMALS.as_bytes()).err().unwrap();
+            assert!(error.contains("NORMAL accessor 1 is too short"), "{}", error);
+        }
+
+        #[test]
+        fn rejects_short_texture_coordinates() {
+            let error = load("short_texcoords", "gltf", SHORT_TEXCOORDS.as_bytes()).err().unwrap();
+            assert!(error.contains("TEXCOORD_0 accessor 2 is too short"), "{}", error);
+        }
+
+        #[test]
+        fn rejects_texture_coordinates_of_the_wrong_type() {
+            let error = load("texcoord_type", "gltf", VEC3_TEXCOORDS.as_bytes()).err().unwrap();
+            assert!(error.contains("TEXCOORD_0 accessor 2 has 3 components"), "{}", error);
+        }
+
+        #[test]
+        fn loads_binary_chunk_with_32_bit_indices() {
+            let scene = load("binary", "glb", &glb(INDEXED_TRIANGLE, &indexed_triangle_chunk())).unwrap();
+            assert_eq!(scene.nodes[0].name, "indexed \u{1F53A}");
+            let positions: Vec<[f32; 3]> = scene.meshes[0].vertices.iter().map(|v| v.position).collect();
+            assert_eq!(positions, vec![[0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]]);
+        }
+
+        #[test]
+        fn reads_32_bit_indices_exactly() {
+            // 2^24 + 1 is the first integer a float cannot represent
+            let json = JsonParser::parse(r#"{
+                "buffers": [{ "byteLength": 8 }],
+                "bufferViews": [{ "buffer": 0, "byteLength": 8 }],
+                "accessors": [{ "bufferView": 0, "componentType": 5125, "count": 2, "type": "SCALAR" }]
+            }"#).unwrap();
+            let mut buffer: Vec<u8> = Vec::new();
+            buffer.extend_from_slice(&16777217u32.to_le_bytes());
+            buffer.extend_from_slice(&u32::MAX.to_le_bytes());
+            let document = Document { json: json, buffers: vec![buffer], directory: PathBuf::new() };
+            assert_eq!(document.read_indices(0).unwrap(), vec![16777217, u32::MAX]);
+        }
+
+        #[test]
+        fn sorts_maps_into_color_and_data_images() {
+            // Every map of the material uses the same 1x1 image
+            let json = r#"{
+                "asset": { "version": "2.0" },
+                "images": [{ "uri": "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR4nGNoaPj/HwAGggL/s75RMwAAAABJRU5ErkJggg==" }],
+                "textures": [{ "source": 0 }],
+                "materials": [{
+                    "pbrMetallicRoughness": { "baseColorTexture": { "index": 0 }, "metallicRoughnessTexture": { "index": 0 } },
+                    "normalTexture": { "index": 0 },
+                    "occlusionTexture": { "index": 0 },
+                    "emissiveTexture": { "index": 0 }
+                }]
+            }"#;
+            let scene = load("maps", "gltf", json.as_bytes()).unwrap();
+
+            // The color images are the texture and the derived specular color, the data image is the texture again
+            assert_eq!((scene.images.len(), scene.data_images.len()), (2, 1));
+            let material = &scene.materials[0];
+            assert_eq!((material.diffuse, material.specular, material.base_color, material.emissive), (0, 1, 0, 0));
+            assert_eq!((material.metallic, material.roughness, material.occlusion, material.normal), (0, 0, 0, 0));
+        }
+
+        #[test]
+        fn combines_surrogate_pairs() {
+            let json = JsonParser::parse(r#"["😀", "é", "\ud83d x"]"#).unwrap();
+            assert_eq!(json.at(0).as_str(), Some("\u{1F600}"));
+            assert_eq!(json.at(1).as_str(), Some("\u{E9}"));
+            assert_eq!(json.at(2).as_str(), Some("\u{FFFD} x"));
+        }
+
+        #[test]
+        fn converts_light_intensities() {
+            let light = JsonParser::parse(r#"{ "type": "point", "color": [1.0, 0.5, 0.0], "intensity": 20.0 }"#).unwrap();
+            match parse_light(&light).unwrap() {
+                GltfLight::Point(light) => {
+                    assert_eq!(light.diffuse_color, [20.0, 10.0, 0.0]);
+                    // Without a range the light falls off with the inverse square of the distance
+                    assert_eq!((light.linear, light.quadratic), (0.0, 1.0));
+                },
+                _ => panic!("Expected a point light"),
+            }
+
+            let light = JsonParser::parse(r#"{ "type": "spot", "range": 5.0, "spot": {} }"#).unwrap();
+            match parse_light(&light).unwrap() {
+                GltfLight::Spot(light) => assert!((light.get_range() - 5.0).abs() < 1e-4),
+                _ => panic!("Expected a spot light"),
+            }
+        }
+    }
+}
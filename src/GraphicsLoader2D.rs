@@ -1,7 +1,36 @@
 use std::io::{Cursor, Read};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use glium;
+use glium::texture::{CubeLayer, Cubemap, MipmapsOption, RawImage2d, Texture2d, UncompressedFloatFormat};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, SamplerWrapFunction};
+
+use crate::Screen3D::Screen::ScreenQuad;
+use crate::Shaders;
+
+/**
+ * All layers of a texture array must share dimensions,
+ * so every loaded image is resized to this size
+ */
+pub const TEXTURE_SIZE: u32 = 512;
+
+/**
+ * Layers of a cube map in the order its face images are passed to `load_cubemap`
+ */
+pub const CUBEMAP_LAYERS: [CubeLayer; 6] = [
+    CubeLayer::PositiveX, CubeLayer::NegativeX,
+    CubeLayer::PositiveY, CubeLayer::NegativeY,
+    CubeLayer::PositiveZ, CubeLayer::NegativeZ,
+];
+
+/*
+File names, without extension, a face image of a cube map directory may have
+*/
+const CUBEMAP_FACE_NAMES: [&[&str]; 6] = [
+    &["px", "posx", "right"], &["nx", "negx", "left"],
+    &["py", "posy", "top", "up"], &["ny", "negy", "bottom", "down"],
+    &["pz", "posz", "front"], &["nz", "negz", "back"],
+];
 
 pub struct GraphicsLoader {}
 impl GraphicsLoader {
@@ -12,6 +41,41 @@ impl GraphicsLoader {
         return image;
     }
     
+    /**
+     * Tangent space normal map derived from the brightness of an image taken as height, brighter is higher.
+     * `strength` steepens the slopes. Normals are encoded like in glTF, +X along +u and +Y along +v.
+     */
+    pub fn load_normal_map_from_height(bytes: &'static [u8], format: image::ImageFormat, strength: f32) -> glium::texture::RawImage2d<'static, u8> {
+        let height = image::load(Cursor::new(bytes), format).unwrap().to_luma32f();
+        let (width, rows) = height.dimensions();
+        let at = |x: i64, y: i64| -> f32 {
+            // Textures repeat, so the slopes wrap around the edges
+            return height.get_pixel(x.rem_euclid(width as i64) as u32, y.rem_euclid(rows as i64) as u32)[0];
+        };
+
+        let mut normal_map = image::RgbaImage::new(width, rows);
+        for (x, y, pixel) in normal_map.enumerate_pixels_mut() {
+            let (x, y) = (x as i64, y as i64);
+            // Sobel filter, image rows go down while +v goes up
+            let du = (at(x + 1, y - 1) + 2.0 * at(x + 1, y) + at(x + 1, y + 1)) - (at(x - 1, y - 1) + 2.0 * at(x - 1, y) + at(x - 1, y + 1));
+            let dv = (at(x - 1, y - 1) + 2.0 * at(x, y - 1) + at(x + 1, y - 1)) - (at(x - 1, y + 1) + 2.0 * at(x, y + 1) + at(x + 1, y + 1));
+            let normal = [-du * strength, -dv * strength, 1.0];
+            let length = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+            let encode = |c: f32| -> u8 { return ((c / length * 0.5 + 0.5) * 255.0).round() as u8; };
+            *pixel = image::Rgba([encode(normal[0]), encode(normal[1]), encode(normal[2]), 255]);
+        }
+        return glium::texture::RawImage2d::from_raw_rgba_reversed(&normal_map.into_raw(), (width, rows));
+    }
+
+    /**
+     * Height map from the brightness of an image, for parallax mapping
+     */
+    pub fn load_height_map(bytes: &'static [u8], format: image::ImageFormat) -> glium::texture::RawImage2d<'static, u8> {
+        let height = image::DynamicImage::ImageLuma8(image::load(Cursor::new(bytes), format).unwrap().to_luma8()).to_rgba8();
+        let dimensions = height.dimensions();
+        return glium::texture::RawImage2d::from_raw_rgba_reversed(&height.into_raw(), dimensions);
+    }
+
     pub fn load_image_from_color(color: [f32; 3]) -> glium::texture::RawImage2d<'static, u8> {
         let (dim_x, dim_y) = (512, 512);
         let mut image_buffer = image::ImageBuffer::<image::Rgba<u8>, _>::new(dim_x, dim_y);
@@ -23,7 +87,122 @@ impl GraphicsLoader {
         }
         return glium::texture::RawImage2d::from_raw_rgba_reversed(&image_buffer.into_raw(), (dim_x, dim_y));
     }
+
+    pub fn load_image_from_path(path: &Path) -> Result<glium::texture::RawImage2d<'static, u8>, String> {
+        let image = match image::open(path) {
+            Ok(image) => image,
+            Err(error) => return Err(format!("Could not load image {}: {}", path.display(), error)),
+        };
+        return Ok(GraphicsLoader::image_to_raw(image));
+    }
+
+    pub fn load_image_from_memory(bytes: &[u8]) -> Result<glium::texture::RawImage2d<'static, u8>, String> {
+        let image = match image::load_from_memory(bytes) {
+            Ok(image) => image,
+            Err(error) => return Err(format!("Could not decode image: {}", error)),
+        };
+        return Ok(GraphicsLoader::image_to_raw(image));
+    }
+
+    fn image_to_raw(image: image::DynamicImage) -> glium::texture::RawImage2d<'static, u8> {
+        let image = image.resize_exact(TEXTURE_SIZE, TEXTURE_SIZE, image::imageops::FilterType::Triangle).to_rgba8();
+        let image_dimensions = image.dimensions();
+        return glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
+    }
     
+    /**
+     * Loads an image as linear colors. 8 bit images are decoded from sRGB,
+     * floating point images like Radiance .hdr files are linear already.
+     */
+    pub fn load_linear_image_from_path(path: &Path) -> Result<RawImage2d<'static, f32>, String> {
+        let image = match image::open(path) {
+            Ok(image) => image,
+            Err(error) => return Err(format!("Could not load image {}: {}", path.display(), error)),
+        };
+        let is_linear = matches!(image, image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_));
+        let mut image = image.into_rgb32f();
+        if !is_linear {
+            for value in image.iter_mut() {
+                *value = srgb_to_linear(*value);
+            }
+        }
+        let dimensions = image.dimensions();
+        return Ok(RawImage2d::from_raw_rgb_reversed(&image.into_raw(), dimensions));
+    }
+
+    /**
+     * Loads an equirectangular image as a linear texture with mipmaps, +Z looks at the middle of the image
+     */
+    pub fn load_equirectangular(display: &glium::Display, path: &Path) -> Result<Texture2d, String> {
+        let image = GraphicsLoader::load_linear_image_from_path(path)?;
+        return match Texture2d::with_format(display, image, UncompressedFloatFormat::F32F32F32, MipmapsOption::AutoGeneratedMipmaps) {
+            Ok(texture) => Ok(texture),
+            Err(error) => Err(format!("Could not create texture of {}: {:?}", path.display(), error)),
+        };
+    }
+
+    /**
+     * Loads six square face images into a linear cube map with mipmaps, in the order of `CUBEMAP_LAYERS`.
+     * Face images are oriented like the faces seen from inside the cube, with +Y up on the side faces.
+     */
+    pub fn load_cubemap(display: &glium::Display, paths: &[PathBuf; 6]) -> Result<Cubemap, String> {
+        let mut faces: Vec<Texture2d> = Vec::new();
+        for path in paths.iter() {
+            let image = GraphicsLoader::load_linear_image_from_path(path)?;
+            if image.width != image.height || faces.first().map_or(false, |face| face.width() != image.width) {
+                return Err(format!("Cube map faces have to be square and of the same size: {}", path.display()));
+            }
+            match Texture2d::with_format(display, image, UncompressedFloatFormat::F16F16F16, MipmapsOption::AutoGeneratedMipmaps) {
+                Ok(face) => faces.push(face),
+                Err(error) => return Err(format!("Could not create texture of {}: {:?}", path.display(), error)),
+            }
+        }
+
+        let cubemap = match Cubemap::empty_with_format(display, UncompressedFloatFormat::F16F16F16, MipmapsOption::EmptyMipmaps, faces[0].width()) {
+            Ok(cubemap) => cubemap,
+            Err(error) => return Err(format!("Could not create cube map: {:?}", error)),
+        };
+        let screen_quad = ScreenQuad::new(display);
+        let program = glium::Program::from_source(display, Shaders::SCREEN_VERTEX_SHADER, Shaders::CUBEMAP_FACE_FRAGMENT_SHADER, None).unwrap();
+        for (face, layer) in faces.iter().zip(CUBEMAP_LAYERS.iter()) {
+            // Every mip level of the face is drawn from the mip level of the image covering as many texels
+            let sampler = face.sampled()
+                .wrap_function(SamplerWrapFunction::Clamp)
+                .minify_filter(MinifySamplerFilter::LinearMipmapLinear)
+                .magnify_filter(MagnifySamplerFilter::Linear);
+            for level in 0..cubemap.get_mipmap_levels() {
+                let mut framebuffer = glium::framebuffer::SimpleFrameBuffer::new(display, cubemap.mipmap(level).unwrap().image(*layer)).unwrap();
+                screen_quad.draw(&mut framebuffer, &program, &uniform! {face: sampler}, &Default::default()).unwrap();
+            }
+        }
+        return Ok(cubemap);
+    }
+
+    /**
+     * Loads a cube map from a directory with an image per face, named after the face like
+     * `px.png` / `nx.png` / ... or `right.jpg` / `left.jpg` / `top.jpg` / `bottom.jpg` / `front.jpg` / `back.jpg`
+     */
+    pub fn load_cubemap_from_directory(display: &glium::Display, directory: &Path) -> Result<Cubemap, String> {
+        let entries = match fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(error) => return Err(format!("Could not read cube map directory {}: {}", directory.display(), error)),
+        };
+        let files: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).filter(|path| path.is_file()).collect();
+
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for names in CUBEMAP_FACE_NAMES.iter() {
+            let path = files.iter().find(|path| {
+                let stem = path.file_stem().and_then(|stem| stem.to_str()).map(|stem| stem.to_lowercase());
+                return stem.map_or(false, |stem| names.contains(&stem.as_str()));
+            });
+            match path {
+                Some(path) => paths.push(path.clone()),
+                None => return Err(format!("Cube map face {} is missing in {}", names[0], directory.display())),
+            }
+        }
+        return GraphicsLoader::load_cubemap(display, &paths.try_into().unwrap());
+    }
+
     pub fn load_texture(display: &glium::Display, bytes: &'static [u8], format: image::ImageFormat) -> glium::texture::SrgbTexture2d {
         return glium::texture::SrgbTexture2d::new(display, GraphicsLoader::load_image(bytes, format)).unwrap();
     }
@@ -36,59 +215,38 @@ impl GraphicsLoader {
         return glium::texture::SrgbTexture2dArray::new(display, images).unwrap();
     }
 
-    pub fn load_all_textures(display: &glium::Display) -> glium::texture::SrgbTexture2dArray {
-        
+    pub fn load_default_images() -> Vec<glium::texture::RawImage2d<'static, u8>> {
         let mut images: Vec<glium::texture::RawImage2d<u8>> = Vec::new();
         images.push(GraphicsLoader::load_image_from_color([1.0, 0.0, 1.0]));
         images.push(GraphicsLoader::load_image(include_bytes!("textures/t_001.jpg"), image::ImageFormat::Jpeg));
         images.push(GraphicsLoader::load_image(include_bytes!("textures/t_002.jpg"), image::ImageFormat::Jpeg));
         images.push(GraphicsLoader::load_image(include_bytes!("textures/t_003.png"), image::ImageFormat::Png));
         images.push(GraphicsLoader::load_image(include_bytes!("textures/t_004.png"), image::ImageFormat::Png));
-        return GraphicsLoader::create_texture_array(display, images);
-
-        /*
-        let mut resource_dir = std::env::current_exe().unwrap();
-        resource_dir.pop();
-        resource_dir.push(directory);
-
-        let base_dir = option_env!("CARGO_MANIFEST_DIR").map_or_else(|| {
-            let exe_path = std::env::current_exe().expect("Failed to get exe path");
-            exe_path.parent().expect("Failed to get exe dir").to_path_buf()
-        }, |crate_dir| {
-            let mut pb = PathBuf::new();
-            pb.push(crate_dir);
-            pb.push("src");
-            pb
-        });
-
-        let mut images: Vec<glium::texture::RawImage2d<u8>> = Vec::new();
-
-        let paths = fs::read_dir(base_dir.join(directory)).unwrap();
-        for path in paths {
-            
-            let mut image_format: image::ImageFormat;
-            let path = path.unwrap().path();
-            match path.extension() {
-                Some(os_str) => {
-                    match os_str.to_str() {
-                        Some("jpg") => {image_format = image::ImageFormat::Jpeg},
-                        Some("png") => {image_format = image::ImageFormat::Png},
-                        _ => continue,
-                    }
-                },
-                None => continue,
-            }
-
-            let f = fs::File::open(path).unwrap();
-            let mut reader = std::io::BufReader::new(f);
-            let mut bytes: Vec<u8> = Vec::new();
-            reader.read_to_end(&mut bytes);
+        return images;
+    }
 
-            images.push(GraphicsLoader::load_image_direct(bytes, image_format));
-        }
+    /**
+     * Linear texture array for maps holding data rather than colors, like normal, height, metallic,
+     * roughness and occlusion maps, which must not be decoded from sRGB
+     */
+    pub fn create_data_texture_array(display: &glium::Display, images: Vec<glium::texture::RawImage2d<u8>>) -> glium::texture::Texture2dArray {
+        return glium::texture::Texture2dArray::new(display, images).unwrap();
+    }
 
-        return GraphicsLoader::create_texture_array(display, images);
-        */
+    pub fn load_default_data_images() -> Vec<glium::texture::RawImage2d<'static, u8>> {
+        let mut images: Vec<glium::texture::RawImage2d<u8>> = Vec::new();
+        // Normal maps of the stone tiles and the wooden boards
+        images.push(GraphicsLoader::load_normal_map_from_height(include_bytes!("textures/t_001.jpg"), image::ImageFormat::Jpeg, 2.0));
+        images.push(GraphicsLoader::load_normal_map_from_height(include_bytes!("textures/t_002.jpg"), image::ImageFormat::Jpeg, 1.0));
+        // Height map of the stone tiles
+        images.push(GraphicsLoader::load_height_map(include_bytes!("textures/t_001.jpg"), image::ImageFormat::Jpeg));
+        return images;
+    }
+}
 
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        return value / 12.92;
     }
-}
\ No newline at end of file
+    return ((value + 0.055) / 1.055).powf(2.4);
+}
@@ -0,0 +1,198 @@
+pub mod Hdr {
+    use std::time::Instant;
+
+    use glium::Surface;
+    use glium::framebuffer::{DepthRenderBuffer, SimpleFrameBuffer};
+    use glium::texture::{DepthFormat, MipmapsOption, Texture2d, UncompressedFloatFormat};
+
+    use crate::Screen3D::Screen::*;
+    use crate::Shaders;
+
+    /*
+    Size of the first luminance texture, it is halved down to a single texel holding the average
+    */
+    const LUMINANCE_SIZE: u32 = 256;
+
+    /*
+    Average scene luminance mapped to middle gray by automatic exposure
+    */
+    const MIDDLE_GRAY: f32 = 0.18;
+
+    /*
+    Range the adapted luminance is kept in, so almost black or very bright frames are not over corrected
+    */
+    const MIN_ADAPTED_LUMINANCE: f32 = 0.02;
+    const MAX_ADAPTED_LUMINANCE: f32 = 10.0;
+
+    /**
+     * Floating point color target the scene is lit into before tone mapping
+     */
+    pub struct HdrTarget {
+        color: Texture2d,
+        depth: DepthRenderBuffer,
+    }
+
+    impl HdrTarget {
+
+        pub fn new(display: &glium::Display, width: u32, height: u32) -> HdrTarget {
+            return HdrTarget {
+                color: Texture2d::empty_with_format(display, UncompressedFloatFormat::F16F16F16F16, MipmapsOption::NoMipmap, width, height).unwrap(),
+                depth: DepthRenderBuffer::new(display, DepthFormat::F32, width, height).unwrap(),
+            }
+        }
+
+        pub fn get_dimensions(&self) -> (u32, u32) {
+            return (self.color.width(), self.color.height());
+        }
+
+        pub fn get_color(&self) -> &Texture2d {
+            return &self.color;
+        }
+
+        pub fn get_depth(&self) -> &DepthRenderBuffer {
+            return &self.depth;
+        }
+
+        pub fn get_framebuffer(&self, display: &glium::Display) -> SimpleFrameBuffer<'_> {
+            return SimpleFrameBuffer::with_depth_buffer(display, &self.color, &self.depth).unwrap();
+        }
+    }
+
+    /**
+     * Curve mapping HDR colors to the displayable range
+     */
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum ToneMapOperator {
+        // Colors above 1 are clipped, how the scene looked before HDR
+        Clamp,
+        Reinhard,
+        // Filmic curve fitted to the ACES reference transform
+        Aces,
+    }
+
+    impl ToneMapOperator {
+
+        pub fn next(&self) -> ToneMapOperator {
+            return match self {
+                ToneMapOperator::Clamp => ToneMapOperator::Reinhard,
+                ToneMapOperator::Reinhard => ToneMapOperator::Aces,
+                ToneMapOperator::Aces => ToneMapOperator::Clamp,
+            };
+        }
+
+        fn get_index(&self) -> i32 {
+            return match self {
+                ToneMapOperator::Clamp => 0,
+                ToneMapOperator::Reinhard => 1,
+                ToneMapOperator::Aces => 2,
+            };
+        }
+    }
+
+    /**
+     * Maps an HDR image to the screen. Colors are scaled by `exposure` before the tone map
+     * operator is applied. With `auto_exposure` the exposure also scales the average
+     * luminance of the image to middle gray, adapting to changes over `adaptation_time` seconds.
+     */
+    pub struct ToneMapper {
+        tone_map_program: glium::Program,
+        luminance_program: glium::Program,
+        copy_program: glium::Program,
+        adaptation_program: glium::Program,
+        screen_quad: ScreenQuad,
+        luminance_chain: Vec<Texture2d>,
+        adapted_luminance: [Texture2d; 2],
+        current: usize,
+        adapted: bool,
+        last_update: Instant,
+        pub operator: ToneMapOperator,
+        pub exposure: f32,
+        pub auto_exposure: bool,
+        pub adaptation_time: f32,
+    }
+
+    impl ToneMapper {
+
+        pub fn new(display: &glium::Display) -> ToneMapper {
+            let luminance_texture = |size: u32| -> Texture2d {
+                return Texture2d::empty_with_format(display, UncompressedFloatFormat::F32, MipmapsOption::NoMipmap, size, size).unwrap();
+            };
+            let mut luminance_chain = Vec::new();
+            let mut size = LUMINANCE_SIZE;
+            while size >= 1 {
+                luminance_chain.push(luminance_texture(size));
+                size /= 2;
+            }
+
+            return ToneMapper {
+                tone_map_program: glium::Program::from_source(display, Shaders::SCREEN_VERTEX_SHADER, Shaders::TONE_MAP_FRAGMENT_SHADER, None).unwrap(),
+                luminance_program: glium::Program::from_source(display, Shaders::SCREEN_VERTEX_SHADER, Shaders::LUMINANCE_FRAGMENT_SHADER, None).unwrap(),
+                copy_program: glium::Program::from_source(display, Shaders::SCREEN_VERTEX_SHADER, Shaders::COPY_FRAGMENT_SHADER, None).unwrap(),
+                adaptation_program: glium::Program::from_source(display, Shaders::SCREEN_VERTEX_SHADER, Shaders::ADAPTATION_FRAGMENT_SHADER, None).unwrap(),
+                screen_quad: ScreenQuad::new(display),
+                luminance_chain: luminance_chain,
+                adapted_luminance: [luminance_texture(1), luminance_texture(1)],
+                current: 0,
+                adapted: false,
+                last_update: Instant::now(),
+                operator: ToneMapOperator::Aces,
+                exposure: 1.0,
+                auto_exposure: false,
+                adaptation_time: 1.0,
+            }
+        }
+
+        /**
+         * Tone maps `hdr` onto the whole of `target`
+         */
+        pub fn render<S: Surface>(&mut self, display: &glium::Display, target: &mut S, hdr: &Texture2d) {
+            let now = Instant::now();
+            let elapsed = (now - self.last_update).as_secs_f32();
+            self.last_update = now;
+
+            if self.auto_exposure {
+                self.update_luminance(display, hdr, elapsed);
+            } else {
+                // Start from the current image the next time automatic exposure is turned on
+                self.adapted = false;
+            }
+
+            let uniforms = uniform! {
+                hdr: get_linear_sampler(hdr),
+                adapted_luminance: self.adapted_luminance[self.current].sampled(),
+                tone_map_operator: self.operator.get_index(),
+                exposure: self.exposure,
+                auto_exposure: self.auto_exposure,
+                middle_gray: MIDDLE_GRAY,
+            };
+            self.screen_quad.draw(target, &self.tone_map_program, &uniforms, &Default::default()).unwrap();
+        }
+
+        /*
+        Averages the log luminance of `hdr` down to one texel and moves the adapted luminance towards it
+        */
+        fn update_luminance(&mut self, display: &glium::Display, hdr: &Texture2d, elapsed: f32) {
+            let mut framebuffer = SimpleFrameBuffer::new(display, &self.luminance_chain[0]).unwrap();
+            self.screen_quad.draw(&mut framebuffer, &self.luminance_program, &uniform! {hdr: get_linear_sampler(hdr)}, &Default::default()).unwrap();
+
+            // Every texel of a half sized texture samples the middle of four texels, linear filtering averages them
+            for i in 1..self.luminance_chain.len() {
+                let mut framebuffer = SimpleFrameBuffer::new(display, &self.luminance_chain[i]).unwrap();
+                self.screen_quad.draw(&mut framebuffer, &self.copy_program, &uniform! {source: get_linear_sampler(&self.luminance_chain[i - 1])}, &Default::default()).unwrap();
+            }
+
+            let adaptation = if self.adapted { 1.0 - (-elapsed / self.adaptation_time.max(1e-3)).exp() } else { 1.0 };
+            let previous = self.current;
+            self.current = 1 - self.current;
+            let uniforms = uniform! {
+                average_luminance: self.luminance_chain.last().unwrap().sampled(),
+                previous_luminance: self.adapted_luminance[previous].sampled(),
+                adaptation: adaptation,
+                luminance_range: [MIN_ADAPTED_LUMINANCE, MAX_ADAPTED_LUMINANCE],
+            };
+            let mut framebuffer = SimpleFrameBuffer::new(display, &self.adapted_luminance[self.current]).unwrap();
+            self.screen_quad.draw(&mut framebuffer, &self.adaptation_program, &uniforms, &Default::default()).unwrap();
+            self.adapted = true;
+        }
+    }
+}
@@ -0,0 +1,240 @@
+pub mod Ibl {
+    use std::path::Path;
+
+    use glium::Surface;
+    use glium::framebuffer::SimpleFrameBuffer;
+    use glium::texture::{CubeLayer, Cubemap, MipmapsOption, Texture2d, UncompressedFloatFormat};
+    use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, SamplerBehavior, SamplerWrapFunction, UniformValue};
+
+    use crate::GraphicsLoader2D::GraphicsLoader;
+    use crate::Screen3D::Screen::*;
+    use crate::Shaders;
+
+    /*
+    Face sizes of the baked cube maps, the prefiltered one has a mip level per roughness step
+    */
+    const ENVIRONMENT_SIZE: u32 = 512;
+    const IRRADIANCE_SIZE: u32 = 32;
+    const PREFILTERED_SIZE: u32 = 128;
+    const PREFILTERED_LEVELS: u32 = 5;
+    const BRDF_LUT_SIZE: u32 = 256;
+
+    /*
+    Angle between the irradiance samples, in radians
+    */
+    const IRRADIANCE_SAMPLE_DELTA: f32 = 0.025;
+
+    /*
+    Direction at the center of every cube map face and the directions its texture coordinates
+    grow along, so the direction of a texel is forward + s * right + t * up with s and t in [-1, 1]
+    */
+    const CUBE_FACES: [(CubeLayer, [f32; 3], [f32; 3], [f32; 3]); 6] = [
+        (CubeLayer::PositiveX, [1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+        (CubeLayer::NegativeX, [-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+        (CubeLayer::PositiveY, [0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        (CubeLayer::NegativeY, [0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+        (CubeLayer::PositiveZ, [0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+        (CubeLayer::NegativeZ, [0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ];
+
+    /**
+     * Clamped, trilinear sampler for the environment cube maps
+     */
+    pub fn get_cubemap_sampler() -> SamplerBehavior {
+        return SamplerBehavior {
+            wrap_function: (SamplerWrapFunction::Clamp, SamplerWrapFunction::Clamp, SamplerWrapFunction::Clamp),
+            minify_filter: MinifySamplerFilter::LinearMipmapLinear,
+            magnify_filter: MagnifySamplerFilter::Linear,
+            .. Default::default()
+        };
+    }
+
+    /*
+    Uniforms of a pass drawn over a cube map face, the pass' own uniforms plus the face vectors
+    */
+    struct FaceUniform<'a, U: glium::uniforms::Uniforms> {
+        uniforms: &'a U,
+        face: &'a (CubeLayer, [f32; 3], [f32; 3], [f32; 3]),
+    }
+
+    impl<U: glium::uniforms::Uniforms> glium::uniforms::Uniforms for FaceUniform<'_, U> {
+        fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut f: F) {
+            self.uniforms.visit_values(&mut f);
+            f("face_forward", UniformValue::Vec3(self.face.1));
+            f("face_right", UniformValue::Vec3(self.face.2));
+            f("face_up", UniformValue::Vec3(self.face.3));
+        }
+    }
+
+    /*
+    Draws `program` over all six faces of one mip level of `cubemap`
+    */
+    fn draw_faces<U: glium::uniforms::Uniforms>(display: &glium::Display, screen_quad: &ScreenQuad, program: &glium::Program, cubemap: &Cubemap, level: u32, uniforms: &U) {
+        for face in CUBE_FACES.iter() {
+            let mut framebuffer = SimpleFrameBuffer::new(display, cubemap.mipmap(level).unwrap().image(face.0)).unwrap();
+            screen_quad.draw(&mut framebuffer, program, &FaceUniform { uniforms: uniforms, face: face }, &Default::default()).unwrap();
+        }
+    }
+
+    fn cubemap(display: &glium::Display, size: u32, mipmaps: MipmapsOption) -> Cubemap {
+        return Cubemap::empty_with_format(display, UncompressedFloatFormat::F16F16F16, mipmaps, size).unwrap();
+    }
+
+    /*
+    Program of a pass over cube map faces or the BRDF lookup table
+    */
+    fn program(display: &glium::Display, fragment_shader: &str) -> glium::Program {
+        let source = Shaders::combine(&[Shaders::SHADER_VERSION, Shaders::ENVIRONMENT_SAMPLING, fragment_shader]);
+        return glium::Program::from_source(display, Shaders::SCREEN_VERTEX_SHADER, &source, None).unwrap();
+    }
+
+    /**
+     * Projects an equirectangular image with mipmaps onto a cube map with mipmaps,
+     * +Z looks at the middle of the image
+     */
+    pub fn equirectangular_to_cubemap(display: &glium::Display, equirectangular: &Texture2d) -> Cubemap {
+        let screen_quad = ScreenQuad::new(display);
+
+        // Every mip level is sampled from the image mip level covering as many texels
+        let environment = cubemap(display, ENVIRONMENT_SIZE, MipmapsOption::EmptyMipmaps);
+        let equirectangular_program = program(display, Shaders::EQUIRECTANGULAR_FRAGMENT_SHADER);
+        let equirectangular_sampler = equirectangular.sampled()
+            .wrap_function(SamplerWrapFunction::Repeat)
+            .minify_filter(MinifySamplerFilter::LinearMipmapLinear)
+            .magnify_filter(MagnifySamplerFilter::Linear);
+        for level in 0..environment.get_mipmap_levels() {
+            let size = (ENVIRONMENT_SIZE >> level).max(1);
+            // A face spans a quarter of the image width
+            let lod = (equirectangular.width() as f32 / (4.0 * size as f32)).log2().max(0.0);
+            draw_faces(display, &screen_quad, &equirectangular_program, &environment, level, &uniform! {equirectangular: equirectangular_sampler, lod: lod});
+        }
+        return environment;
+    }
+
+    /**
+     * Lighting from a surrounding HDR image. The image is baked into an irradiance cube map for
+     * diffuse light and a cube map prefiltered for increasing roughness in its mip levels for specular
+     * light, with a lookup table of the split sum BRDF. Lit surfaces use it in place of the constant
+     * ambient light of the lights while the environment is loaded and `enabled`, scaled by `intensity`.
+     */
+    pub struct Environment {
+        cubemap: Cubemap,
+        irradiance: Cubemap,
+        prefiltered: Cubemap,
+        brdf_lut: Texture2d,
+        loaded: bool,
+        pub enabled: bool,
+        pub intensity: f32,
+    }
+
+    impl Environment {
+
+        /**
+         * Black environment that lights nothing, so the samplers are bound when no image was loaded
+         */
+        pub fn empty(display: &glium::Display) -> Environment {
+            let environment = Environment {
+                cubemap: cubemap(display, 1, MipmapsOption::NoMipmap),
+                irradiance: cubemap(display, 1, MipmapsOption::NoMipmap),
+                prefiltered: cubemap(display, 1, MipmapsOption::NoMipmap),
+                brdf_lut: Texture2d::empty_with_format(display, UncompressedFloatFormat::F16F16, MipmapsOption::NoMipmap, 1, 1).unwrap(),
+                loaded: false,
+                enabled: false,
+                intensity: 1.0,
+            };
+            for cubemap in [&environment.cubemap, &environment.irradiance, &environment.prefiltered] {
+                for face in CUBE_FACES.iter() {
+                    SimpleFrameBuffer::new(display, cubemap.main_level().image(face.0)).unwrap().clear_color(0.0, 0.0, 0.0, 1.0);
+                }
+            }
+            SimpleFrameBuffer::new(display, &environment.brdf_lut).unwrap().clear_color(0.0, 0.0, 0.0, 1.0);
+            return environment;
+        }
+
+        /**
+         * Loads an equirectangular image, usually a Radiance .hdr file, and bakes it
+         */
+        pub fn load(display: &glium::Display, path: &Path) -> Result<Environment, String> {
+            let equirectangular = GraphicsLoader::load_equirectangular(display, path)?;
+            return Ok(Environment::from_equirectangular(display, &equirectangular));
+        }
+
+        /**
+         * Bakes an equirectangular image with mipmaps, +Z looks at the middle of the image
+         */
+        pub fn from_equirectangular(display: &glium::Display, equirectangular: &Texture2d) -> Environment {
+            return Environment::from_cubemap(display, equirectangular_to_cubemap(display, equirectangular));
+        }
+
+        /**
+         * Bakes a cube map with a full chain of mip levels, like the ones of `GraphicsLoader::load_cubemap`
+         */
+        pub fn from_cubemap(display: &glium::Display, environment: Cubemap) -> Environment {
+            let screen_quad = ScreenQuad::new(display);
+            let environment_sampler = environment.sampled()
+                .wrap_function(SamplerWrapFunction::Clamp)
+                .minify_filter(MinifySamplerFilter::LinearMipmapLinear)
+                .magnify_filter(MagnifySamplerFilter::Linear);
+
+            let irradiance = cubemap(display, IRRADIANCE_SIZE, MipmapsOption::NoMipmap);
+            let uniforms = uniform! {environment: environment_sampler, sample_delta: IRRADIANCE_SAMPLE_DELTA};
+            draw_faces(display, &screen_quad, &program(display, Shaders::IRRADIANCE_FRAGMENT_SHADER), &irradiance, 0, &uniforms);
+
+            let prefiltered = cubemap(display, PREFILTERED_SIZE, MipmapsOption::EmptyMipmapsMax(PREFILTERED_LEVELS - 1));
+            let prefilter_program = program(display, Shaders::PREFILTER_FRAGMENT_SHADER);
+            for level in 0..PREFILTERED_LEVELS {
+                let roughness = level as f32 / (PREFILTERED_LEVELS - 1) as f32;
+                let uniforms = uniform! {environment: environment_sampler, environment_size: environment.width() as f32, roughness: roughness};
+                draw_faces(display, &screen_quad, &prefilter_program, &prefiltered, level, &uniforms);
+            }
+
+            let brdf_lut = Texture2d::empty_with_format(display, UncompressedFloatFormat::F16F16, MipmapsOption::NoMipmap, BRDF_LUT_SIZE, BRDF_LUT_SIZE).unwrap();
+            let mut framebuffer = SimpleFrameBuffer::new(display, &brdf_lut).unwrap();
+            screen_quad.draw(&mut framebuffer, &program(display, Shaders::BRDF_LUT_FRAGMENT_SHADER), &glium::uniforms::EmptyUniforms, &Default::default()).unwrap();
+
+            return Environment {
+                cubemap: environment,
+                irradiance: irradiance,
+                prefiltered: prefiltered,
+                brdf_lut: brdf_lut,
+                loaded: true,
+                enabled: true,
+                intensity: 1.0,
+            }
+        }
+
+        /**
+         * Whether an image was baked, an empty environment stays black also when enabled
+         */
+        pub fn is_loaded(&self) -> bool {
+            return self.loaded;
+        }
+
+        /**
+         * Whether surfaces are lit by the environment instead of the ambient light of the lights
+         */
+        pub fn is_active(&self) -> bool {
+            return self.loaded && self.enabled;
+        }
+
+        pub fn get_cubemap(&self) -> &Cubemap {
+            return &self.cubemap;
+        }
+
+        pub fn get_irradiance(&self) -> &Cubemap {
+            return &self.irradiance;
+        }
+
+        pub fn get_prefiltered(&self) -> &Cubemap {
+            return &self.prefiltered;
+        }
+
+        pub fn get_prefiltered_max_level(&self) -> f32 {
+            return (self.prefiltered.get_mipmap_levels() - 1) as f32;
+        }
+
+        pub fn get_brdf_lut(&self) -> &Texture2d {
+            return &self.brdf_lut;
+        }
+    }
+}
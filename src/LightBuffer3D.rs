@@ -0,0 +1,180 @@
+pub mod LightBuffer {
+    use glium::texture::buffer_texture::{BufferTexture, BufferTextureType};
+
+    use crate::Lights3D::Lights::*;
+    use crate::Shadow3D::Shadow::*;
+
+    /*
+    Number of vec4 texels each light occupies, must match the fetch functions in `Shaders.rs`
+    */
+    const DIRECTIONAL_LIGHT_TEXELS: usize = 4;
+    const POINT_LIGHT_TEXELS: usize = 5;
+    const SPOT_LIGHT_TEXELS: usize = 6;
+
+    /**
+     * All lights of a frame packed into a float buffer texture, read by the shaders with `texelFetch`.
+     *
+     * The first texel holds the number of directional, point and spot lights, followed by
+     * the directional lights, the point lights and the spot lights:
+     *
+     * directional: (direction, shadow_map) (ambient, shadow_bias) (diffuse, 0) (specular, 0)
+     * point:       (position, constant) (ambient, linear) (diffuse, quadratic) (specular, shadow_map) (shadow_bias, shadow_far, 0, 0)
+     * spot:        (position, constant) (direction, linear) (ambient, quadratic) (diffuse, cutoff) (specular, outer_cutoff) (shadow_map, shadow_bias, 0, 0)
+     */
+    pub struct LightBuffer {
+        texture: BufferTexture<[f32; 4]>,
+        data: Vec<[f32; 4]>,
+    }
+
+    impl LightBuffer {
+
+        pub fn new(display: &glium::Display) -> LightBuffer {
+            return LightBuffer {
+                texture: BufferTexture::empty_dynamic(display, 256, BufferTextureType::Float).unwrap(),
+                data: Vec::new(),
+            }
+        }
+
+        /**
+         * Packs the lights and uploads them, the buffer grows when more lights are given than fit.
+         * Shadow map slots are looked up in `shadows`, so it has to be updated with the same lights first.
+         */
+        pub fn update(&mut self, display: &glium::Display, lights: &LightList, shadows: &ShadowRenderer) {
+            pack_lights(&mut self.data, lights, &ShadowSlots::from_renderer(lights, shadows));
+
+            if self.data.len() > self.texture.len() {
+                let capacity = self.data.len().next_power_of_two();
+                self.texture = BufferTexture::empty_dynamic(display, capacity, BufferTextureType::Float).unwrap();
+            }
+            self.texture.slice(0 .. self.data.len()).unwrap().write(&self.data);
+        }
+
+        pub fn get_texture(&self) -> &BufferTexture<[f32; 4]> {
+            return &self.texture;
+        }
+    }
+
+    /*
+    Shadow map slot of every light, -1 for lights without a shadow map,
+    and the far plane of the point light shadow maps
+    */
+    struct ShadowSlots {
+        directional: Vec<i32>,
+        point: Vec<(i32, f32)>,
+        spot: Vec<i32>,
+    }
+
+    impl ShadowSlots {
+        fn from_renderer(lights: &LightList, shadows: &ShadowRenderer) -> ShadowSlots {
+            return ShadowSlots {
+                directional: (0..lights.directional_lights.len()).map(|i| shadows.get_directional_slot(i)).collect(),
+                point: (0..lights.point_lights.len()).map(|i| {
+                    let slot = shadows.get_point_slot(i);
+                    let far = if slot < 0 { 1.0 } else { shadows.get_point_map(slot as usize).unwrap().get_far() };
+                    return (slot, far);
+                }).collect(),
+                spot: (0..lights.spot_lights.len()).map(|i| shadows.get_spot_slot(i)).collect(),
+            }
+        }
+    }
+
+    /*
+    Replaces `data` with the texels of the lights, in the layout described at `LightBuffer`
+    */
+    fn pack_lights(data: &mut Vec<[f32; 4]>, lights: &LightList, slots: &ShadowSlots) {
+        data.clear();
+        data.reserve(1 + lights.directional_lights.len() * DIRECTIONAL_LIGHT_TEXELS
+            + lights.point_lights.len() * POINT_LIGHT_TEXELS
+            + lights.spot_lights.len() * SPOT_LIGHT_TEXELS);
+        data.push([
+            lights.directional_lights.len() as f32,
+            lights.point_lights.len() as f32,
+            lights.spot_lights.len() as f32,
+            0.0,
+        ]);
+
+        for (light, slot) in lights.directional_lights.iter().zip(slots.directional.iter()) {
+            let d = light.direction;
+            data.push([d[0], d[1], d[2], *slot as f32]);
+            data.push(with_w(light.ambient_color, light.shadow.bias));
+            data.push(with_w(light.diffuse_color, 0.0));
+            data.push(with_w(light.specular_color, 0.0));
+        }
+
+        for (light, (slot, shadow_far)) in lights.point_lights.iter().zip(slots.point.iter()) {
+            data.push(with_w(light.position, light.constant));
+            data.push(with_w(light.ambient_color, light.linear));
+            data.push(with_w(light.diffuse_color, light.quadratic));
+            data.push(with_w(light.specular_color, *slot as f32));
+            data.push([light.shadow.bias, *shadow_far, 0.0, 0.0]);
+        }
+
+        for (light, slot) in lights.spot_lights.iter().zip(slots.spot.iter()) {
+            data.push(with_w(light.position, light.constant));
+            data.push(with_w(light.direction, light.linear));
+            data.push(with_w(light.ambient_color, light.quadratic));
+            data.push(with_w(light.diffuse_color, light.cutoff));
+            data.push(with_w(light.specular_color, light.outer_cutoff));
+            data.push([*slot as f32, light.shadow.bias, 0.0, 0.0]);
+        }
+    }
+
+    fn with_w(xyz: [f32; 3], w: f32) -> [f32; 4] {
+        return [xyz[0], xyz[1], xyz[2], w];
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::Shaders;
+
+        fn xyz(texel: [f32; 4]) -> [f32; 3] {
+            return [texel[0], texel[1], texel[2]];
+        }
+
+        #[test]
+        fn texel_counts_match_the_shaders() {
+            for (name, texels) in [("DIRECTIONAL", DIRECTIONAL_LIGHT_TEXELS), ("POINT", POINT_LIGHT_TEXELS), ("SPOT", SPOT_LIGHT_TEXELS)].iter() {
+                let declaration = format!("const int {}_LIGHT_TEXELS = {};", name, texels);
+                assert!(Shaders::LIGHTING.contains(&declaration), "{} is missing in the shaders", declaration);
+            }
+        }
+
+        #[test]
+        fn packs_one_light_of_each_kind() {
+            let mut lights = LightList::new();
+            let mut directional = DirectionalLight::new_colors([0.0, -1.0, 0.0], [0.1, 0.2, 0.3], [0.4, 0.5, 0.6], [0.7, 0.8, 0.9]);
+            directional.shadow.bias = 0.001;
+            lights.directional_lights.push(directional);
+            let mut point = PointLight::new_with_parameters([1.0, 2.0, 3.0], [1.0, 0.5, 0.25], 0.3, 0.4);
+            point.shadow.bias = 0.002;
+            lights.point_lights.push(point);
+            let mut spot = SpotLight::new([4.0, 5.0, 6.0], [0.0, 0.0, 1.0], 20.0, [0.25, 0.5, 1.0]);
+            spot.outer_cutoff = 0.8;
+            spot.shadow.bias = 0.003;
+            lights.spot_lights.push(spot);
+            let slots = ShadowSlots { directional: vec![1], point: vec![(3, 25.0)], spot: vec![-1] };
+
+            let mut data: Vec<[f32; 4]> = Vec::new();
+            pack_lights(&mut data, &lights, &slots);
+            assert_eq!(data.len(), 1 + DIRECTIONAL_LIGHT_TEXELS + POINT_LIGHT_TEXELS + SPOT_LIGHT_TEXELS);
+            assert_eq!(data[0], [1.0, 1.0, 1.0, 0.0]);
+
+            // Read back the way get_directional_light, get_point_light and get_spot_light do
+            let t = &data[1..];
+            assert_eq!((xyz(t[0]), xyz(t[1]), xyz(t[2]), xyz(t[3])), ([0.0, -1.0, 0.0], [0.1, 0.2, 0.3], [0.4, 0.5, 0.6], [0.7, 0.8, 0.9]));
+            assert_eq!((t[0][3] as i32, t[1][3]), (1, 0.001));
+
+            let t = &data[1 + DIRECTIONAL_LIGHT_TEXELS..];
+            assert_eq!((xyz(t[0]), t[0][3], t[1][3], t[2][3]), ([1.0, 2.0, 3.0], 1.0, 0.3, 0.4));
+            assert_eq!((xyz(t[1]), xyz(t[2]), xyz(t[3])), (point.ambient_color, [1.0, 0.5, 0.25], [1.0, 0.5, 0.25]));
+            assert_eq!((t[3][3] as i32, t[4][0], t[4][1]), (3, 0.002, 25.0));
+
+            let t = &data[1 + DIRECTIONAL_LIGHT_TEXELS + POINT_LIGHT_TEXELS..];
+            assert_eq!((xyz(t[0]), xyz(t[1]), t[3][3], t[4][3]), ([4.0, 5.0, 6.0], [0.0, 0.0, 1.0], spot.cutoff, 0.8));
+            assert_eq!((t[0][3], t[1][3], t[2][3]), (spot.constant, spot.linear, spot.quadratic));
+            assert_eq!((xyz(t[2]), xyz(t[3]), xyz(t[4])), (spot.ambient_color, [0.25, 0.5, 1.0], [0.25, 0.5, 1.0]));
+            assert_eq!((t[5][0] as i32, t[5][1]), (-1, 0.003));
+        }
+    }
+}
@@ -1,11 +1,51 @@
 pub mod Lights {
 
-    pub const MAX_DIRECTIONAL_LIGHTS: i32 = 2;
-    pub const MAX_POINT_LIGHTS: i32 = 124;
-    pub const MAX_SPOT_LIGHTS: i32 = 2;
-
     const AMBIENT_FACTOR: f32 = 0.03;
 
+    /*
+    Distance at which the attenuation drops the light to 1/256 of its intensity
+    */
+    fn attenuation_range(constant: f32, linear: f32, quadratic: f32) -> f32 {
+        let threshold = 256.0;
+        if quadratic > 0.0 {
+            return (-linear + (linear*linear - 4.0*quadratic*(constant - threshold)).sqrt()) / (2.0*quadratic);
+        }
+        if linear > 0.0 {
+            return (threshold - constant) / linear;
+        }
+        return f32::INFINITY;
+    }
+
+    /**
+     * Shadow casting options of a light. Shadows are off unless enabled.
+     * `bias` is the depth offset used against shadow acne, in shadow map depth units,
+     * and `resolution` the width and height of the shadow map in texels.
+     */
+    #[derive(Copy, Clone)]
+    pub struct ShadowSettings {
+        pub enabled: bool,
+        pub bias: f32,
+        pub resolution: u32,
+    }
+
+    impl ShadowSettings {
+        pub fn new(bias: f32, resolution: u32) -> ShadowSettings {
+            return ShadowSettings {
+                enabled: true,
+                bias: bias,
+                resolution: resolution,
+            }
+        }
+
+        pub fn disabled() -> ShadowSettings {
+            return ShadowSettings {
+                enabled: false,
+                bias: 0.0005,
+                resolution: 1024,
+            }
+        }
+    }
+
     #[derive(Copy, Clone)]
     pub struct DirectionalLight {
         pub direction: [f32; 3],
@@ -13,6 +53,8 @@ pub mod Lights {
         pub ambient_color: [f32; 3],
         pub diffuse_color: [f32; 3],
         pub specular_color: [f32; 3],
+
+        pub shadow: ShadowSettings,
     }
 
     impl DirectionalLight {
@@ -22,6 +64,7 @@ pub mod Lights {
                 ambient_color: [color[0]*AMBIENT_FACTOR, color[1]*AMBIENT_FACTOR, color[2]*AMBIENT_FACTOR],
                 diffuse_color: color,
                 specular_color: color,
+                shadow: ShadowSettings::disabled(),
             }
         }
 
@@ -31,6 +74,7 @@ pub mod Lights {
                 ambient_color: ambient_color,
                 diffuse_color: diffuse_color,
                 specular_color: specular_color,
+                shadow: ShadowSettings::disabled(),
             }
         }
     }
@@ -46,6 +90,8 @@ pub mod Lights {
         pub ambient_color: [f32; 3],
         pub diffuse_color: [f32; 3],
         pub specular_color: [f32; 3],
+
+        pub shadow: ShadowSettings,
     }
 
     impl PointLight {
@@ -59,6 +105,7 @@ pub mod Lights {
                 ambient_color: [color[0]*AMBIENT_FACTOR, color[1]*AMBIENT_FACTOR, color[2]*AMBIENT_FACTOR],
                 diffuse_color: color,
                 specular_color: color,
+                shadow: ShadowSettings::disabled(),
             }
         }
 
@@ -72,10 +119,17 @@ pub mod Lights {
                 ambient_color: [color[0]*AMBIENT_FACTOR, color[1]*AMBIENT_FACTOR, color[2]*AMBIENT_FACTOR],
                 diffuse_color: color,
                 specular_color: color,
+                shadow: ShadowSettings::disabled(),
             }
         }
     }
 
+    impl PointLight {
+        pub fn get_range(&self) -> f32 {
+            return attenuation_range(self.constant, self.linear, self.quadratic);
+        }
+    }
+
     #[derive(Copy, Clone)]
     pub struct SpotLight {
         pub position: [f32; 3],
@@ -88,6 +142,7 @@ pub mod Lights {
         pub ambient_color: [f32; 3],
         pub diffuse_color: [f32; 3],
         pub specular_color: [f32; 3],
+        pub shadow: ShadowSettings,
     }
 
     impl SpotLight {
@@ -103,7 +158,31 @@ pub mod Lights {
                 ambient_color: [color[0]*AMBIENT_FACTOR, color[1]*AMBIENT_FACTOR, color[2]*AMBIENT_FACTOR],
                 diffuse_color: color,
                 specular_color: color,
+                shadow: ShadowSettings::disabled(),
+            }
+        }
+
+        pub fn get_range(&self) -> f32 {
+            return attenuation_range(self.constant, self.linear, self.quadratic);
+        }
+    }
+
+    /**
+     * All lights of a frame, as many of each type as needed
+     */
+    pub struct LightList {
+        pub directional_lights: Vec<DirectionalLight>,
+        pub point_lights: Vec<PointLight>,
+        pub spot_lights: Vec<SpotLight>,
+    }
+
+    impl LightList {
+        pub fn new() -> LightList {
+            return LightList {
+                directional_lights: Vec::new(),
+                point_lights: Vec::new(),
+                spot_lights: Vec::new(),
             }
         }
     }
-}
\ No newline at end of file
+}
@@ -2,20 +2,137 @@ pub mod Material {
 
     pub const MAX_MATERIALS: i32 = 32;
 
+    /**
+     * Texture id of material maps that are not used, only their factor applies
+     */
+    pub const NO_TEXTURE: i32 = -1;
+
+    /**
+     * Light model a material is shaded with
+     */
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum ShadingModel {
+        // Blinn-Phong style, using `diffuse`, `specular` and `shininess`
+        Phong,
+        // Metallic / roughness Cook-Torrance GGX, using the base color, metallic and roughness maps
+        Pbr,
+    }
+
+    impl ShadingModel {
+        pub fn get_index(&self) -> i32 {
+            return match self {
+                ShadingModel::Phong => 0,
+                ShadingModel::Pbr => 1,
+            };
+        }
+    }
+
+    /**
+     * How the alpha of a material is used
+     */
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum AlphaMode {
+        // Alpha is ignored
+        Opaque,
+        // Alpha test, fragments below `alpha_cutoff` are cut out and the rest is opaque
+        Mask,
+        // Drawn over the opaque scene in the transparent pass, see `Transparency3D.rs`
+        Blend,
+    }
+
+    impl AlphaMode {
+        pub fn get_index(&self) -> i32 {
+            return match self {
+                AlphaMode::Opaque => 0,
+                AlphaMode::Mask => 1,
+                AlphaMode::Blend => 2,
+            };
+        }
+    }
+
+    /**
+     * Texture ids of color maps index the sRGB texture array, those of the metallic, roughness, occlusion,
+     * normal and height maps the linear data texture array. Every PBR map is multiplied by its factor.
+     * Like in glTF, metallic is read from the blue channel of its map, roughness from the green
+     * and ambient occlusion from the red one, so a combined map can be used for all three.
+     * The tangent space `normal` map applies to both shading models, its slopes are scaled by `normal_scale`.
+     * A `height` map, white where the surface is highest, offsets the texture coordinates with parallax occlusion
+     * mapping, the relief reaching `height_scale` texture units below the surface. With `parallax_shadows`
+     * the relief also shadows itself, which needs the texture coordinates and only works in the forward path.
+     * Unless the `alpha_mode` is opaque, alpha is `opacity` times the alpha of the diffuse or base color texture.
+     * Shadow maps and ambient occlusion treat every material as opaque.
+     */
     #[derive(Copy, Clone)]
     pub struct Material {
+        pub shading: ShadingModel,
         pub diffuse: i32, // diffuse texture id
         pub specular: i32, // specular texture id
         pub shininess: f32,
+        pub base_color: i32,
+        pub base_color_factor: [f32; 3],
+        pub metallic: i32,
+        pub metallic_factor: f32,
+        pub roughness: i32,
+        pub roughness_factor: f32,
+        pub occlusion: i32,
+        pub occlusion_strength: f32,
+        pub emissive: i32,
+        pub emissive_factor: [f32; 3],
+        pub normal: i32,
+        pub normal_scale: f32,
+        pub height: i32,
+        pub height_scale: f32,
+        pub parallax_shadows: bool,
+        pub alpha_mode: AlphaMode,
+        pub opacity: f32,
+        pub alpha_cutoff: f32,
     }
 
     impl Material {
         pub fn new(diffuse: i32, specular: i32, shininess: f32) -> Material {
             return Material {
+                shading: ShadingModel::Phong,
                 diffuse: diffuse,
                 specular: specular,
                 shininess: shininess,
+                base_color: diffuse,
+                base_color_factor: [1.0, 1.0, 1.0],
+                metallic: NO_TEXTURE,
+                metallic_factor: 0.0,
+                roughness: NO_TEXTURE,
+                roughness_factor: 1.0,
+                occlusion: NO_TEXTURE,
+                occlusion_strength: 1.0,
+                emissive: NO_TEXTURE,
+                emissive_factor: [0.0, 0.0, 0.0],
+                normal: NO_TEXTURE,
+                normal_scale: 1.0,
+                height: NO_TEXTURE,
+                height_scale: 0.05,
+                parallax_shadows: false,
+                alpha_mode: AlphaMode::Opaque,
+                opacity: 1.0,
+                alpha_cutoff: 0.5,
             }
         }
+
+        /**
+         * PBR material with a base color texture, or `NO_TEXTURE` for a plain `base_color_factor`
+         */
+        pub fn new_pbr(base_color: i32, base_color_factor: [f32; 3], metallic_factor: f32, roughness_factor: f32) -> Material {
+            let mut material = Material::new(base_color, base_color, 16.0);
+            material.shading = ShadingModel::Pbr;
+            material.base_color_factor = base_color_factor;
+            material.metallic_factor = metallic_factor;
+            material.roughness_factor = roughness_factor;
+            return material;
+        }
+
+        /**
+         * Whether the material is drawn in the transparent pass
+         */
+        pub fn is_blended(&self) -> bool {
+            return self.alpha_mode == AlphaMode::Blend;
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,115 @@
+pub mod MaterialBuffer {
+    use glium::texture::buffer_texture::{BufferTexture, BufferTextureType};
+
+    use crate::Material3D::Material::*;
+
+    /*
+    Number of vec4 texels each material occupies, must match `get_material` in `Shaders.rs`
+    */
+    const MATERIAL_TEXELS: usize = 7;
+
+    /**
+     * The material array packed into a float buffer texture, read by the shaders with `texelFetch`.
+     * Material `i` starts at texel `i * 7`, texture ids and enums are stored as floats:
+     *
+     * (base_color_factor, shading) (emissive_factor, alpha_mode) (diffuse, specular, shininess, base_color)
+     * (metallic, metallic_factor, roughness, roughness_factor) (occlusion, occlusion_strength, emissive, opacity)
+     * (normal, normal_scale, height, height_scale) (parallax_shadows, alpha_cutoff, 0, 0)
+     */
+    pub struct MaterialBuffer {
+        texture: BufferTexture<[f32; 4]>,
+        data: Vec<[f32; 4]>,
+    }
+
+    impl MaterialBuffer {
+
+        pub fn new(display: &glium::Display) -> MaterialBuffer {
+            return MaterialBuffer {
+                texture: BufferTexture::empty_dynamic(display, MAX_MATERIALS as usize * MATERIAL_TEXELS, BufferTextureType::Float).unwrap(),
+                data: Vec::new(),
+            }
+        }
+
+        /**
+         * Packs the materials and uploads them, materials past `MAX_MATERIALS` are left out
+         */
+        pub fn update(&mut self, materials: &[Material]) {
+            pack_materials(&mut self.data, &materials[..materials.len().min(MAX_MATERIALS as usize)]);
+            if !self.data.is_empty() {
+                self.texture.slice(0 .. self.data.len()).unwrap().write(&self.data);
+            }
+        }
+
+        pub fn get_texture(&self) -> &BufferTexture<[f32; 4]> {
+            return &self.texture;
+        }
+    }
+
+    /*
+    Replaces `data` with the texels of the materials, in the layout described at `MaterialBuffer`
+    */
+    fn pack_materials(data: &mut Vec<[f32; 4]>, materials: &[Material]) {
+        data.clear();
+        data.reserve(materials.len() * MATERIAL_TEXELS);
+        for material in materials.iter() {
+            data.push(with_w(material.base_color_factor, material.shading.get_index() as f32));
+            data.push(with_w(material.emissive_factor, material.alpha_mode.get_index() as f32));
+            data.push([material.diffuse as f32, material.specular as f32, material.shininess, material.base_color as f32]);
+            data.push([material.metallic as f32, material.metallic_factor, material.roughness as f32, material.roughness_factor]);
+            data.push([material.occlusion as f32, material.occlusion_strength, material.emissive as f32, material.opacity]);
+            data.push([material.normal as f32, material.normal_scale, material.height as f32, material.height_scale]);
+            data.push([if material.parallax_shadows { 1.0 } else { 0.0 }, material.alpha_cutoff, 0.0, 0.0]);
+        }
+    }
+
+    fn with_w(xyz: [f32; 3], w: f32) -> [f32; 4] {
+        return [xyz[0], xyz[1], xyz[2], w];
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::Shaders;
+
+        #[test]
+        fn texel_count_matches_the_shaders() {
+            let declaration = format!("const int MATERIAL_TEXELS = {};", MATERIAL_TEXELS);
+            assert!(Shaders::MATERIALS.contains(&declaration), "{} is missing in the shaders", declaration);
+        }
+
+        #[test]
+        fn packs_every_field() {
+            let phong = Material::new(3, 4, 32.0);
+            let mut pbr = Material::new_pbr(5, [0.1, 0.2, 0.3], 0.4, 0.6);
+            pbr.metallic = 1;
+            pbr.roughness = 2;
+            pbr.occlusion = 3;
+            pbr.occlusion_strength = 0.7;
+            pbr.emissive = 6;
+            pbr.emissive_factor = [1.0, 0.5, 0.25];
+            pbr.normal = 4;
+            pbr.normal_scale = 0.8;
+            pbr.height = 5;
+            pbr.height_scale = 0.02;
+            pbr.parallax_shadows = true;
+            pbr.alpha_mode = AlphaMode::Mask;
+            pbr.opacity = 0.9;
+            pbr.alpha_cutoff = 0.3;
+
+            let mut data: Vec<[f32; 4]> = Vec::new();
+            pack_materials(&mut data, &[phong, pbr]);
+            assert_eq!(data.len(), 2 * MATERIAL_TEXELS);
+
+            // Read back the way get_material does
+            let t = &data[..MATERIAL_TEXELS];
+            assert_eq!((t[0][3] as i32, t[2][0] as i32, t[2][1] as i32, t[2][2]), (0, 3, 4, 32.0));
+            assert_eq!((t[3][0] as i32, t[5][0] as i32, t[5][2] as i32, t[6][0]), (NO_TEXTURE, NO_TEXTURE, NO_TEXTURE, 0.0));
+
+            let t = &data[MATERIAL_TEXELS..];
+            assert_eq!((t[0], t[1]), ([0.1, 0.2, 0.3, 1.0], [1.0, 0.5, 0.25, 1.0]));
+            assert_eq!((t[2][3] as i32, t[3]), (5, [1.0, 0.4, 2.0, 0.6]));
+            assert_eq!((t[4], t[5]), ([3.0, 0.7, 6.0, 0.9], [4.0, 0.8, 5.0, 0.02]));
+            assert_eq!(t[6], [1.0, 0.3, 0.0, 0.0]);
+        }
+    }
+}
@@ -0,0 +1,356 @@
+pub mod Math {
+
+    /*
+    Matrices are column major, m[column][row], matching the layout glium expects for uniforms
+    */
+
+    pub const IDENTITY: [[f32; 4]; 4] = [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ];
+
+    pub fn vadd(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+        return [a[0] + b[0], a[1] + b[1], a[2] + b[2]];
+    }
+
+    pub fn vsub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+        return [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+    }
+
+    pub fn vscale(a: [f32; 3], factor: f32) -> [f32; 3] {
+        return [a[0]*factor, a[1]*factor, a[2]*factor];
+    }
+
+    pub fn vdot(a: [f32; 3], b: [f32; 3]) -> f32 {
+        return a[0]*b[0] + a[1]*b[1] + a[2]*b[2];
+    }
+
+    pub fn vcross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+        return [
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        ];
+    }
+
+    pub fn vlength(a: [f32; 3]) -> f32 {
+        return vdot(a, a).sqrt();
+    }
+
+    pub fn vnormalize(a: [f32; 3]) -> [f32; 3] {
+        let len = vlength(a);
+        if len == 0.0 {
+            return a;
+        }
+        return vscale(a, 1.0/len);
+    }
+
+    pub fn mat4_mul(a: &[[f32; 4]; 4], b: &[[f32; 4]; 4]) -> [[f32; 4]; 4] {
+        let mut result = [[0.0f32; 4]; 4];
+        for column in 0..4 {
+            for row in 0..4 {
+                for k in 0..4 {
+                    result[column][row] += a[k][row] * b[column][k];
+                }
+            }
+        }
+        return result;
+    }
+
+    pub fn mat4_mul_vec4(m: &[[f32; 4]; 4], v: [f32; 4]) -> [f32; 4] {
+        let mut result = [0.0f32; 4];
+        for row in 0..4 {
+            for k in 0..4 {
+                result[row] += m[k][row] * v[k];
+            }
+        }
+        return result;
+    }
+
+    pub fn transform_point(m: &[[f32; 4]; 4], p: [f32; 3]) -> [f32; 3] {
+        let v = mat4_mul_vec4(m, [p[0], p[1], p[2], 1.0]);
+        return [v[0], v[1], v[2]];
+    }
+
+    pub fn transform_direction(m: &[[f32; 4]; 4], d: [f32; 3]) -> [f32; 3] {
+        let v = mat4_mul_vec4(m, [d[0], d[1], d[2], 0.0]);
+        return [v[0], v[1], v[2]];
+    }
+
+    pub fn upper_3x3(m: &[[f32; 4]; 4]) -> [[f32; 3]; 3] {
+        return [
+            [m[0][0], m[0][1], m[0][2]],
+            [m[1][0], m[1][1], m[1][2]],
+            [m[2][0], m[2][1], m[2][2]],
+        ];
+    }
+
+    pub fn determinant_3x3(m: &[[f32; 3]; 3]) -> f32 {
+        return m[0][0] * (m[1][1] * m[2][2] - m[2][1] * m[1][2])
+             - m[1][0] * (m[0][1] * m[2][2] - m[2][1] * m[0][2])
+             + m[2][0] * (m[0][1] * m[1][2] - m[1][1] * m[0][2]);
+    }
+
+    /**
+     * Inverse transpose of the upper 3x3 part of a matrix, used to transform normals.
+     * Singular matrices return the cofactor matrix, which still gives correct directions.
+     */
+    pub fn normal_matrix(m: &[[f32; 4]; 4]) -> [[f32; 3]; 3] {
+        let a = upper_3x3(m);
+        let mut cofactors = [[0.0f32; 3]; 3];
+        for column in 0..3 {
+            for row in 0..3 {
+                let c0 = (column + 1) % 3;
+                let c1 = (column + 2) % 3;
+                let r0 = (row + 1) % 3;
+                let r1 = (row + 2) % 3;
+                cofactors[column][row] = a[c0][r0] * a[c1][r1] - a[c1][r0] * a[c0][r1];
+            }
+        }
+        let determinant = determinant_3x3(&a);
+        if determinant != 0.0 {
+            for column in 0..3 {
+                for row in 0..3 {
+                    cofactors[column][row] /= determinant;
+                }
+            }
+        }
+        return cofactors;
+    }
+
+    pub fn mat4_inverse(m: &[[f32; 4]; 4]) -> [[f32; 4]; 4] {
+        // Flatten column major and invert with cofactor expansion
+        let a: Vec<f32> = m.iter().flat_map(|column| column.iter().copied()).collect();
+        let mut inv = [0.0f32; 16];
+
+        inv[0] = a[5]*a[10]*a[15] - a[5]*a[11]*a[14] - a[9]*a[6]*a[15] + a[9]*a[7]*a[14] + a[13]*a[6]*a[11] - a[13]*a[7]*a[10];
+        inv[4] = -a[4]*a[10]*a[15] + a[4]*a[11]*a[14] + a[8]*a[6]*a[15] - a[8]*a[7]*a[14] - a[12]*a[6]*a[11] + a[12]*a[7]*a[10];
+        inv[8] = a[4]*a[9]*a[15] - a[4]*a[11]*a[13] - a[8]*a[5]*a[15] + a[8]*a[7]*a[13] + a[12]*a[5]*a[11] - a[12]*a[7]*a[9];
+        inv[12] = -a[4]*a[9]*a[14] + a[4]*a[10]*a[13] + a[8]*a[5]*a[14] - a[8]*a[6]*a[13] - a[12]*a[5]*a[10] + a[12]*a[6]*a[9];
+        inv[1] = -a[1]*a[10]*a[15] + a[1]*a[11]*a[14] + a[9]*a[2]*a[15] - a[9]*a[3]*a[14] - a[13]*a[2]*a[11] + a[13]*a[3]*a[10];
+        inv[5] = a[0]*a[10]*a[15] - a[0]*a[11]*a[14] - a[8]*a[2]*a[15] + a[8]*a[3]*a[14] + a[12]*a[2]*a[11] - a[12]*a[3]*a[10];
+        inv[9] = -a[0]*a[9]*a[15] + a[0]*a[11]*a[13] + a[8]*a[1]*a[15] - a[8]*a[3]*a[13] - a[12]*a[1]*a[11] + a[12]*a[3]*a[9];
+        inv[13] = a[0]*a[9]*a[14] - a[0]*a[10]*a[13] - a[8]*a[1]*a[14] + a[8]*a[2]*a[13] + a[12]*a[1]*a[10] - a[12]*a[2]*a[9];
+        inv[2] = a[1]*a[6]*a[15] - a[1]*a[7]*a[14] - a[5]*a[2]*a[15] + a[5]*a[3]*a[14] + a[13]*a[2]*a[7] - a[13]*a[3]*a[6];
+        inv[6] = -a[0]*a[6]*a[15] + a[0]*a[7]*a[14] + a[4]*a[2]*a[15] - a[4]*a[3]*a[14] - a[12]*a[2]*a[7] + a[12]*a[3]*a[6];
+        inv[10] = a[0]*a[5]*a[15] - a[0]*a[7]*a[13] - a[4]*a[1]*a[15] + a[4]*a[3]*a[13] + a[12]*a[1]*a[7] - a[12]*a[3]*a[5];
+        inv[14] = -a[0]*a[5]*a[14] + a[0]*a[6]*a[13] + a[4]*a[1]*a[14] - a[4]*a[2]*a[13] - a[12]*a[1]*a[6] + a[12]*a[2]*a[5];
+        inv[3] = -a[1]*a[6]*a[11] + a[1]*a[7]*a[10] + a[5]*a[2]*a[11] - a[5]*a[3]*a[10] - a[9]*a[2]*a[7] + a[9]*a[3]*a[6];
+        inv[7] = a[0]*a[6]*a[11] - a[0]*a[7]*a[10] - a[4]*a[2]*a[11] + a[4]*a[3]*a[10] + a[8]*a[2]*a[7] - a[8]*a[3]*a[6];
+        inv[11] = -a[0]*a[5]*a[11] + a[0]*a[7]*a[9] + a[4]*a[1]*a[11] - a[4]*a[3]*a[9] - a[8]*a[1]*a[7] + a[8]*a[3]*a[5];
+        inv[15] = a[0]*a[5]*a[10] - a[0]*a[6]*a[9] - a[4]*a[1]*a[10] + a[4]*a[2]*a[9] + a[8]*a[1]*a[6] - a[8]*a[2]*a[5];
+
+        let determinant = a[0]*inv[0] + a[1]*inv[4] + a[2]*inv[8] + a[3]*inv[12];
+        let factor = if determinant == 0.0 { 0.0 } else { 1.0 / determinant };
+
+        let mut result = [[0.0f32; 4]; 4];
+        for column in 0..4 {
+            for row in 0..4 {
+                result[column][row] = inv[column*4 + row] * factor;
+            }
+        }
+        return result;
+    }
+
+    /**
+     * View matrix looking from `eye` along `direction`, the engine looks down +Z
+     */
+    pub fn look_at_matrix(eye: [f32; 3], direction: [f32; 3], up: [f32; 3]) -> [[f32; 4]; 4] {
+        let f = vnormalize(direction);
+        let mut s = vcross(up, f);
+        if vlength(s) < 1e-6 {
+            s = vcross([1.0, 0.0, 0.0], f);
+        }
+        let s = vnormalize(s);
+        let u = vcross(f, s);
+        return [
+            [s[0], u[0], f[0], 0.0],
+            [s[1], u[1], f[1], 0.0],
+            [s[2], u[2], f[2], 0.0],
+            [-vdot(s, eye), -vdot(u, eye), -vdot(f, eye), 1.0],
+        ];
+    }
+
+    /**
+     * Perspective projection, `aspect_ratio` is height / width
+     */
+    pub fn perspective_matrix(fov: f32, aspect_ratio: f32, znear: f32, zfar: f32) -> [[f32; 4]; 4] {
+        let f = 1.0 / (fov / 2.0).tan();
+        return [
+            [f * aspect_ratio, 0.0, 0.0, 0.0],
+            [0.0, f, 0.0, 0.0],
+            [0.0, 0.0, (zfar + znear)/(zfar - znear), 1.0],
+            [0.0, 0.0, -(2.0 * zfar * znear)/(zfar - znear), 0.0],
+        ];
+    }
+
+    pub fn orthographic_matrix(left: f32, right: f32, bottom: f32, top: f32, znear: f32, zfar: f32) -> [[f32; 4]; 4] {
+        return [
+            [2.0 / (right - left), 0.0, 0.0, 0.0],
+            [0.0, 2.0 / (top - bottom), 0.0, 0.0],
+            [0.0, 0.0, 2.0 / (zfar - znear), 0.0],
+            [-(right + left)/(right - left), -(top + bottom)/(top - bottom), -(zfar + znear)/(zfar - znear), 1.0],
+        ];
+    }
+
+    /**
+     * Unit quaternion (x, y, z, w) describing a rotation
+     */
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub struct Quaternion {
+        pub x: f32,
+        pub y: f32,
+        pub z: f32,
+        pub w: f32,
+    }
+
+    impl Quaternion {
+
+        pub fn identity() -> Quaternion {
+            return Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 };
+        }
+
+        pub fn from_axis_angle(axis: [f32; 3], angle: f32) -> Quaternion {
+            let axis = vnormalize(axis);
+            let s = (angle*0.5).sin();
+            return Quaternion { x: axis[0]*s, y: axis[1]*s, z: axis[2]*s, w: (angle*0.5).cos() };
+        }
+
+        /**
+         * Rotation around the X, then Y, then Z axis, the same order as `Shape3D::rotate`
+         */
+        pub fn from_euler(angle_XYZ: [f32; 3]) -> Quaternion {
+            let qx = Quaternion::from_axis_angle([1.0, 0.0, 0.0], angle_XYZ[0]);
+            let qy = Quaternion::from_axis_angle([0.0, 1.0, 0.0], angle_XYZ[1]);
+            let qz = Quaternion::from_axis_angle([0.0, 0.0, 1.0], angle_XYZ[2]);
+            return qz.mul(&qy).mul(&qx);
+        }
+
+        /**
+         * Rotation turning +Z towards `direction`, keeping `up` as close to +Y as possible
+         */
+        pub fn look_rotation(direction: [f32; 3], up: [f32; 3]) -> Quaternion {
+            let f = vnormalize(direction);
+            let mut s = vcross(up, f);
+            if vlength(s) < 1e-6 {
+                s = vcross([1.0, 0.0, 0.0], f);
+            }
+            let s = vnormalize(s);
+            let u = vcross(f, s);
+            return Quaternion::from_matrix(&[s, u, f]);
+        }
+
+        /**
+         * Builds a quaternion from an orthonormal column major rotation matrix
+         */
+        pub fn from_matrix(m: &[[f32; 3]; 3]) -> Quaternion {
+            let trace = m[0][0] + m[1][1] + m[2][2];
+            let q = if trace > 0.0 {
+                let s = (trace + 1.0).sqrt() * 2.0;
+                Quaternion { w: 0.25 * s, x: (m[1][2] - m[2][1]) / s, y: (m[2][0] - m[0][2]) / s, z: (m[0][1] - m[1][0]) / s }
+            } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+                let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+                Quaternion { w: (m[1][2] - m[2][1]) / s, x: 0.25 * s, y: (m[1][0] + m[0][1]) / s, z: (m[2][0] + m[0][2]) / s }
+            } else if m[1][1] > m[2][2] {
+                let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+                Quaternion { w: (m[2][0] - m[0][2]) / s, x: (m[1][0] + m[0][1]) / s, y: 0.25 * s, z: (m[2][1] + m[1][2]) / s }
+            } else {
+                let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+                Quaternion { w: (m[0][1] - m[1][0]) / s, x: (m[2][0] + m[0][2]) / s, y: (m[2][1] + m[1][2]) / s, z: 0.25 * s }
+            };
+            return q.normalize();
+        }
+
+        /**
+         * Hamilton product, the resulting rotation applies `other` first
+         */
+        pub fn mul(&self, other: &Quaternion) -> Quaternion {
+            return Quaternion {
+                x: self.w*other.x + self.x*other.w + self.y*other.z - self.z*other.y,
+                y: self.w*other.y - self.x*other.z + self.y*other.w + self.z*other.x,
+                z: self.w*other.z + self.x*other.y - self.y*other.x + self.z*other.w,
+                w: self.w*other.w - self.x*other.x - self.y*other.y - self.z*other.z,
+            };
+        }
+
+        pub fn normalize(&self) -> Quaternion {
+            let len = (self.x*self.x + self.y*self.y + self.z*self.z + self.w*self.w).sqrt();
+            if len == 0.0 {
+                return Quaternion::identity();
+            }
+            return Quaternion { x: self.x/len, y: self.y/len, z: self.z/len, w: self.w/len };
+        }
+
+        pub fn to_matrix(&self) -> [[f32; 3]; 3] {
+            let (x, y, z, w) = (self.x, self.y, self.z, self.w);
+            return [
+                [1.0 - 2.0*(y*y + z*z), 2.0*(x*y + z*w), 2.0*(x*z - y*w)],
+                [2.0*(x*y - z*w), 1.0 - 2.0*(x*x + z*z), 2.0*(y*z + x*w)],
+                [2.0*(x*z + y*w), 2.0*(y*z - x*w), 1.0 - 2.0*(x*x + y*y)],
+            ];
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn assert_near(a: [f32; 3], b: [f32; 3]) {
+            assert!(vlength(vsub(a, b)) < 1e-5, "{:?} != {:?}", a, b);
+        }
+
+        fn apply(m: &[[f32; 3]; 3], v: [f32; 3]) -> [f32; 3] {
+            return vadd(vadd(vscale(m[0], v[0]), vscale(m[1], v[1])), vscale(m[2], v[2]));
+        }
+
+        #[test]
+        fn axis_angle_rotates_counter_clockwise() {
+            let m = Quaternion::from_axis_angle([0.0, 0.0, 2.0], std::f32::consts::FRAC_PI_2).to_matrix();
+            assert_near(apply(&m, [1.0, 0.0, 0.0]), [0.0, 1.0, 0.0]);
+            assert_near(apply(&m, [0.0, 0.0, 1.0]), [0.0, 0.0, 1.0]);
+        }
+
+        #[test]
+        fn euler_angles_rotate_around_x_then_y_then_z() {
+            let angles = [0.4, -1.3, 2.2];
+            let euler = Quaternion::from_euler(angles).to_matrix();
+            let axes = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+            for v in [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.3, -0.5, 0.8]].iter() {
+                let mut rotated = *v;
+                for (axis, angle) in axes.iter().zip(angles.iter()) {
+                    rotated = apply(&Quaternion::from_axis_angle(*axis, *angle).to_matrix(), rotated);
+                }
+                assert_near(apply(&euler, *v), rotated);
+            }
+            for i in 0..3 {
+                let mut single = [0.0; 3];
+                single[i] = angles[i];
+                assert_eq!(Quaternion::from_euler(single), Quaternion::from_axis_angle(axes[i], angles[i]));
+            }
+        }
+
+        #[test]
+        fn rotation_matrices_are_orthonormal() {
+            let rotations = [
+                Quaternion::from_axis_angle([1.0, 2.0, 3.0], 0.8),
+                Quaternion::from_axis_angle([-1.0, 0.0, 0.5], 3.0),
+                Quaternion::from_euler([1.5, 0.2, -2.7]),
+                Quaternion::look_rotation([0.2, -0.9, 0.1], [0.0, 1.0, 0.0]),
+            ];
+            for rotation in rotations.iter() {
+                let m = rotation.to_matrix();
+                for a in 0..3 {
+                    for b in 0..3 {
+                        let expected = if a == b { 1.0 } else { 0.0 };
+                        assert!((vdot(m[a], m[b]) - expected).abs() < 1e-5, "{:?}", rotation);
+                    }
+                }
+                assert!((determinant_3x3(&m) - 1.0).abs() < 1e-5);
+                let restored = Quaternion::from_matrix(&m);
+                assert!((vdot([restored.x, restored.y, restored.z], [rotation.x, rotation.y, rotation.z]) + restored.w*rotation.w).abs() > 1.0 - 1e-5);
+            }
+        }
+    }
+}
@@ -0,0 +1,580 @@
+pub mod Mesh {
+    use std::collections::HashMap;
+
+    use crate::Base3D::General::*;
+    use crate::Bounds3D::Bounds::*;
+    use crate::Bvh3D::Bvh::*;
+    use crate::Math3D::Math::*;
+    use crate::Transform3D::Transform::*;
+
+    /*
+    Hash key for welding, -0.0 is mapped to 0.0 so both are merged
+    */
+    fn weld_key(vertex: &Vertex, normal: &Normal, tangent: &Tangent) -> [u32; 13] {
+        let bits = |f: f32| -> u32 { if f == 0.0 { 0 } else { f.to_bits() } };
+        return [
+            bits(vertex.position[0]), bits(vertex.position[1]), bits(vertex.position[2]),
+            bits(vertex.texture[0]), bits(vertex.texture[1]),
+            bits(normal.normal[0]), bits(normal.normal[1]), bits(normal.normal[2]),
+            bits(tangent.tangent[0]), bits(tangent.tangent[1]), bits(tangent.tangent[2]), bits(tangent.tangent[3]),
+            vertex.material_id as u32,
+        ];
+    }
+
+    /**
+     * Indexed triangle mesh where every unique position/texture/normal/tangent/material
+     * combination is stored once and triangles reference it through `indices`
+     */
+    #[derive(Clone)]
+    pub struct IndexedShape {
+        pub vertices: Vec<Vertex>,
+        pub normals: Vec<Normal>,
+        pub tangents: Vec<Tangent>,
+        pub indices: Vec<u32>,
+    }
+
+    impl IndexedShape {
+
+        /**
+         * Builds an indexed mesh from a triangle soup with generated tangents, merging identical vertices
+         */
+        pub fn from_shape<T: Shape3D>(shape: &T) -> IndexedShape {
+            let mut vertices: Vec<Vertex> = Vec::new();
+            let mut normals: Vec<Normal> = Vec::new();
+            let mut tangents: Vec<Tangent> = Vec::new();
+            let mut indices: Vec<u32> = Vec::with_capacity(shape.get_vertices().len());
+            let mut lookup: HashMap<[u32; 13], u32> = HashMap::new();
+
+            let shape_tangents = shape.generate_tangents();
+            for ((vertex, normal), tangent) in shape.get_vertices().iter().zip(shape.get_normals().iter()).zip(shape_tangents.iter()) {
+                let key = weld_key(vertex, normal, tangent);
+                let index = match lookup.get(&key) {
+                    Some(index) => *index,
+                    None => {
+                        let index = vertices.len() as u32;
+                        vertices.push(*vertex);
+                        normals.push(*normal);
+                        tangents.push(*tangent);
+                        lookup.insert(key, index);
+                        index
+                    }
+                };
+                indices.push(index);
+            }
+
+            return IndexedShape {
+                vertices: vertices,
+                normals: normals,
+                tangents: tangents,
+                indices: indices,
+            };
+        }
+
+        /**
+         * Builds an indexed mesh from a triangle soup with generated tangents, keeping every vertex.
+         * The vertex count only depends on the number of triangles, so deformed copies of a shape
+         * can replace the vertices of a mesh with `GpuMesh::update_vertices`.
+         */
+        pub fn from_shape_unwelded<T: Shape3D>(shape: &T) -> IndexedShape {
+            return IndexedShape {
+                vertices: shape.get_vertices().clone(),
+                normals: shape.get_normals().clone(),
+                tangents: shape.generate_tangents(),
+                indices: (0..shape.get_vertices().len() as u32).collect(),
+            };
+        }
+
+        /**
+         * Expands the mesh back into a triangle soup, tangents are dropped as triangle soups regenerate them
+         */
+        pub fn to_shape(&self) -> AShape {
+            return AShape {
+                vertices: self.indices.iter().map(|i| self.vertices[*i as usize]).collect(),
+                normals: self.indices.iter().map(|i| self.normals[*i as usize]).collect(),
+            };
+        }
+
+        pub fn get_vertices(&self) -> &Vec<Vertex> {
+            return &self.vertices;
+        }
+
+        pub fn get_normals(&self) -> &Vec<Normal> {
+            return &self.normals;
+        }
+
+        pub fn get_tangents(&self) -> &Vec<Tangent> {
+            return &self.tangents;
+        }
+
+        pub fn get_indices(&self) -> &Vec<u32> {
+            return &self.indices;
+        }
+    }
+
+    /**
+     * How the buffers of a `GpuMesh` are allocated.
+     * Static meshes are uploaded once and never written again, dynamic meshes
+     * are rewritten in place with `GpuMesh::update_vertices` and persistent meshes keep their
+     * buffers mapped, which avoids reallocations when geometry changes every frame.
+     */
+    #[derive(Copy, Clone, PartialEq)]
+    pub enum BufferUsage {
+        Static,
+        Dynamic,
+        Persistent,
+    }
+
+    /*
+    Bounding box and sphere of a mesh, for culling and sorting transparent meshes by distance
+    */
+    fn get_bounds(shape: &IndexedShape) -> (Aabb, BoundingSphere) {
+        let positions: Vec<[f32; 3]> = shape.get_vertices().iter().map(|vertex| vertex.position).collect();
+        return (Aabb::from_points(positions.iter().cloned()), BoundingSphere::from_points(&positions));
+    }
+
+    /*
+    Distinct material ids used by the vertices of a mesh, in ascending order
+    */
+    fn get_material_ids(shape: &IndexedShape) -> Vec<i32> {
+        let mut materials: Vec<i32> = shape.get_vertices().iter().map(|vertex| vertex.material_id).collect();
+        materials.sort_unstable();
+        materials.dedup();
+        return materials;
+    }
+
+    /**
+     * Per instance attributes of an instanced draw. `instance_model` and `instance_normal_matrix` place the
+     * instance within the model space of its draw call, `instance_material` replaces the materials of the
+     * mesh unless it is -1 and `instance_color` multiplies the diffuse or base color.
+     */
+    #[derive(Copy, Clone)]
+    pub struct Instance {
+        pub instance_model: [[f32; 4]; 4],
+        pub instance_normal_matrix: [[f32; 3]; 3],
+        pub instance_material: i32,
+        pub instance_color: [f32; 3],
+    }
+
+    implement_vertex!(Instance, instance_model, instance_normal_matrix, instance_material, instance_color);
+
+    impl Instance {
+        pub fn new(model: [[f32; 4]; 4]) -> Instance {
+            return Instance {
+                instance_model: model,
+                instance_normal_matrix: normal_matrix(&model),
+                instance_material: -1,
+                instance_color: [1.0, 1.0, 1.0],
+            }
+        }
+
+        pub fn from_transform(transform: &Transform) -> Instance {
+            return Instance {
+                instance_model: transform.get_model_matrix(),
+                instance_normal_matrix: transform.get_normal_matrix(),
+                instance_material: -1,
+                instance_color: [1.0, 1.0, 1.0],
+            }
+        }
+    }
+
+    /**
+     * Per instance buffer for drawing one mesh many times in a single call. The buffer grows to the next
+     * power of two when more instances are written, so rewriting it every frame does not reallocate.
+     */
+    pub struct InstanceBuffer {
+        buffer: glium::VertexBuffer<Instance>,
+        count: usize,
+        origins: Aabb,
+        max_scale: f32,
+        materials: Vec<i32>,
+    }
+
+    impl InstanceBuffer {
+
+        pub fn new(display: &glium::Display, instances: &[Instance]) -> InstanceBuffer {
+            let mut instance_buffer = InstanceBuffer {
+                buffer: glium::VertexBuffer::empty_dynamic(display, instances.len().max(1).next_power_of_two()).unwrap(),
+                count: 0,
+                origins: Aabb::empty(),
+                max_scale: 0.0,
+                materials: Vec::new(),
+            };
+            instance_buffer.update(display, instances);
+            return instance_buffer;
+        }
+
+        /**
+         * Replaces all instances
+         */
+        pub fn update(&mut self, display: &glium::Display, instances: &[Instance]) {
+            if instances.len() > self.buffer.len() {
+                self.buffer = glium::VertexBuffer::empty_dynamic(display, instances.len().next_power_of_two()).unwrap();
+            }
+            if !instances.is_empty() {
+                self.buffer.slice(0 .. instances.len()).unwrap().write(instances);
+            }
+            self.count = instances.len();
+
+            self.origins = Aabb::from_points(instances.iter().map(|instance| [instance.instance_model[3][0], instance.instance_model[3][1], instance.instance_model[3][2]]));
+            self.max_scale = instances.iter().map(|instance| get_max_scale(&instance.instance_model)).fold(0.0, f32::max);
+
+            self.materials = instances.iter().map(|instance| instance.instance_material).filter(|material| *material >= 0).collect();
+            self.materials.sort_unstable();
+            self.materials.dedup();
+        }
+
+        pub fn len(&self) -> usize {
+            return self.count;
+        }
+
+        pub fn is_empty(&self) -> bool {
+            return self.count == 0;
+        }
+
+        /**
+         * Center of the bounding box of the instance origins
+         */
+        pub fn get_center(&self) -> [f32; 3] {
+            return self.origins.get_center();
+        }
+
+        /**
+         * Box around the instances of a mesh with the given bounding sphere, in the model space of the draw call.
+         * Every instance is covered by the sphere moved to the instance, scaled by the largest scale of all instances.
+         */
+        pub fn get_bounding_box(&self, mesh_sphere: &BoundingSphere) -> Aabb {
+            if mesh_sphere.is_empty() {
+                return Aabb::empty();
+            }
+            return self.origins.expand(self.max_scale * (vlength(mesh_sphere.center) + mesh_sphere.radius));
+        }
+
+        /**
+         * Distinct materials the instances replace the ones of the mesh with
+         */
+        pub fn get_materials(&self) -> &Vec<i32> {
+            return &self.materials;
+        }
+    }
+
+    /**
+     * GPU resident vertex, normal, tangent and index buffers of an indexed mesh,
+     * with its bounding box and sphere, a BVH over its triangles and the materials it uses.
+     * Meshes drawn without instances are drawn as a single instance at the origin.
+     */
+    pub struct GpuMesh {
+        vertex_buffer: glium::VertexBuffer<Vertex>,
+        normal_buffer: glium::VertexBuffer<Normal>,
+        tangent_buffer: glium::VertexBuffer<Tangent>,
+        index_buffer: glium::IndexBuffer<u32>,
+        usage: BufferUsage,
+        bounding_box: Aabb,
+        bounding_sphere: BoundingSphere,
+        triangles: TriangleBvh,
+        materials: Vec<i32>,
+        identity_instance: glium::VertexBuffer<Instance>,
+    }
+
+    impl GpuMesh {
+
+        pub fn new(display: &glium::Display, shape: &IndexedShape, usage: BufferUsage) -> GpuMesh {
+            let primitive = glium::index::PrimitiveType::TrianglesList;
+            let (bounding_box, bounding_sphere) = get_bounds(shape);
+            let triangles = TriangleBvh::from_shape(&shape.to_shape());
+            let materials = get_material_ids(shape);
+            let identity_instance = glium::VertexBuffer::immutable(display, &[Instance::new(IDENTITY)]).unwrap();
+            return match usage {
+                BufferUsage::Static => GpuMesh {
+                    vertex_buffer: glium::VertexBuffer::immutable(display, shape.get_vertices()).unwrap(),
+                    normal_buffer: glium::VertexBuffer::immutable(display, shape.get_normals()).unwrap(),
+                    tangent_buffer: glium::VertexBuffer::immutable(display, shape.get_tangents()).unwrap(),
+                    index_buffer: glium::IndexBuffer::immutable(display, primitive, shape.get_indices()).unwrap(),
+                    usage: usage,
+                    bounding_box: bounding_box,
+                    bounding_sphere: bounding_sphere,
+                    triangles: triangles,
+                    materials: materials,
+                    identity_instance: identity_instance,
+                },
+                BufferUsage::Dynamic => GpuMesh {
+                    vertex_buffer: glium::VertexBuffer::dynamic(display, shape.get_vertices()).unwrap(),
+                    normal_buffer: glium::VertexBuffer::dynamic(display, shape.get_normals()).unwrap(),
+                    tangent_buffer: glium::VertexBuffer::dynamic(display, shape.get_tangents()).unwrap(),
+                    index_buffer: glium::IndexBuffer::dynamic(display, primitive, shape.get_indices()).unwrap(),
+                    usage: usage,
+                    bounding_box: bounding_box,
+                    bounding_sphere: bounding_sphere,
+                    triangles: triangles,
+                    materials: materials,
+                    identity_instance: identity_instance,
+                },
+                BufferUsage::Persistent => {
+                    // Persistent mapping needs GL 4.4 or ARB_buffer_storage, fall back to dynamic buffers
+                    let vertex_buffer = glium::VertexBuffer::persistent(display, shape.get_vertices());
+                    let normal_buffer = glium::VertexBuffer::persistent(display, shape.get_normals());
+                    let tangent_buffer = glium::VertexBuffer::persistent(display, shape.get_tangents());
+                    let index_buffer = glium::IndexBuffer::persistent(display, primitive, shape.get_indices());
+                    match (vertex_buffer, normal_buffer, tangent_buffer, index_buffer) {
+                        (Ok(vertex_buffer), Ok(normal_buffer), Ok(tangent_buffer), Ok(index_buffer)) => GpuMesh {
+                            vertex_buffer: vertex_buffer,
+                            normal_buffer: normal_buffer,
+                            tangent_buffer: tangent_buffer,
+                            index_buffer: index_buffer,
+                            usage: usage,
+                            bounding_box: bounding_box,
+                            bounding_sphere: bounding_sphere,
+                            triangles: triangles,
+                            materials: materials,
+                            identity_instance: identity_instance,
+                        },
+                        _ => GpuMesh::new(display, shape, BufferUsage::Dynamic),
+                    }
+                },
+            };
+        }
+
+        pub fn new_static(display: &glium::Display, shape: &IndexedShape) -> GpuMesh {
+            return GpuMesh::new(display, shape, BufferUsage::Static);
+        }
+
+        pub fn new_persistent(display: &glium::Display, shape: &IndexedShape) -> GpuMesh {
+            return GpuMesh::new(display, shape, BufferUsage::Persistent);
+        }
+
+        /**
+         * Center of the bounding box in model space
+         */
+        pub fn get_center(&self) -> [f32; 3] {
+            return self.bounding_box.get_center();
+        }
+
+        /**
+         * Bounding box in model space
+         */
+        pub fn get_bounding_box(&self) -> &Aabb {
+            return &self.bounding_box;
+        }
+
+        /**
+         * Bounding sphere in model space
+         */
+        pub fn get_bounding_sphere(&self) -> &BoundingSphere {
+            return &self.bounding_sphere;
+        }
+
+        /**
+         * BVH over the triangles in model space, for ray casts and nearest point queries
+         */
+        pub fn get_triangles(&self) -> &TriangleBvh {
+            return &self.triangles;
+        }
+
+        pub fn get_materials(&self) -> &Vec<i32> {
+            return &self.materials;
+        }
+
+        /**
+         * Rewrites the positions, texture coordinates, normals and tangents of a dynamic or persistent mesh in place.
+         * The indices and materials stay those the mesh was created with, so `shape` must have as many vertices;
+         * meshes changing their topology or materials are rebuilt with `GpuMesh::new`.
+         */
+        pub fn update_vertices(&mut self, shape: &IndexedShape) -> Result<(), String> {
+            if self.usage == BufferUsage::Static {
+                return Err("Static meshes can not be updated".to_string());
+            }
+            if self.vertex_buffer.len() != shape.get_vertices().len() {
+                return Err(format!("The mesh has {} vertices, the new vertices are {}", self.vertex_buffer.len(), shape.get_vertices().len()));
+            }
+
+            self.vertex_buffer.write(shape.get_vertices());
+            self.normal_buffer.write(shape.get_normals());
+            self.tangent_buffer.write(shape.get_tangents());
+            (self.bounding_box, self.bounding_sphere) = get_bounds(shape);
+            // Same indices, so the same triangles
+            self.triangles.refit(&shape.to_shape())?;
+            return Ok(());
+        }
+
+        pub fn draw<S: glium::Surface, U: glium::uniforms::Uniforms>(&self, target: &mut S, program: &glium::Program, uniforms: &U, draw_parameters: &glium::DrawParameters) -> Result<(), glium::DrawError> {
+            let instances = self.identity_instance.per_instance().unwrap();
+            return target.draw((&self.vertex_buffer, &self.normal_buffer, &self.tangent_buffer, instances), &self.index_buffer, program, uniforms, draw_parameters);
+        }
+
+        /**
+         * Draws every instance of `instances` with one call
+         */
+        pub fn draw_instanced<S: glium::Surface, U: glium::uniforms::Uniforms>(&self, target: &mut S, instances: &InstanceBuffer, program: &glium::Program, uniforms: &U, draw_parameters: &glium::DrawParameters) -> Result<(), glium::DrawError> {
+            if instances.is_empty() {
+                return Ok(());
+            }
+            let slice = instances.buffer.slice(0 .. instances.len()).unwrap();
+            return target.draw((&self.vertex_buffer, &self.normal_buffer, &self.tangent_buffer, slice.per_instance().unwrap()), &self.index_buffer, program, uniforms, draw_parameters);
+        }
+    }
+
+    /**
+     * A single draw call, a mesh and the model and normal matrices it is drawn with,
+     * optionally drawn once for every instance of an instance buffer
+     */
+    #[derive(Copy, Clone)]
+    pub struct DrawCall<'a> {
+        pub mesh: &'a GpuMesh,
+        pub model: [[f32; 4]; 4],
+        pub normal_matrix: [[f32; 3]; 3],
+        pub instances: Option<&'a InstanceBuffer>,
+    }
+
+    impl<'a> DrawCall<'a> {
+        pub fn new(mesh: &'a GpuMesh, model: [[f32; 4]; 4]) -> DrawCall<'a> {
+            return DrawCall {
+                mesh: mesh,
+                model: model,
+                normal_matrix: normal_matrix(&model),
+                instances: None,
+            }
+        }
+
+        pub fn with_instances(mesh: &'a GpuMesh, model: [[f32; 4]; 4], instances: &'a InstanceBuffer) -> DrawCall<'a> {
+            return DrawCall {
+                instances: Some(instances),
+                .. DrawCall::new(mesh, model)
+            }
+        }
+
+        /**
+         * Center of the mesh, or of the instances, in model space
+         */
+        pub fn get_center(&self) -> [f32; 3] {
+            return match self.instances {
+                Some(instances) => instances.get_center(),
+                None => self.mesh.get_center(),
+            };
+        }
+
+        /**
+         * World space box around the mesh, or around all of its instances
+         */
+        pub fn get_bounding_box(&self) -> Aabb {
+            return match self.instances {
+                Some(instances) => instances.get_bounding_box(self.mesh.get_bounding_sphere()).transform(&self.model),
+                None => self.mesh.get_bounding_box().transform(&self.model),
+            };
+        }
+
+        /**
+         * World space sphere around the mesh, or around all of its instances
+         */
+        pub fn get_bounding_sphere(&self) -> BoundingSphere {
+            return match self.instances {
+                Some(instances) => BoundingSphere::from_aabb(&instances.get_bounding_box(self.mesh.get_bounding_sphere())).transform(&self.model),
+                None => self.mesh.get_bounding_sphere().transform(&self.model),
+            };
+        }
+
+        /**
+         * World space distance at which a ray hits the mesh within `max_distance`,
+         * instanced draw calls are hit on the box around their instances
+         */
+        pub fn intersect_ray(&self, ray: &Ray, max_distance: f32) -> Option<f32> {
+            if self.instances.is_some() {
+                return ray.intersect_aabb(&self.get_bounding_box()).filter(|distance| *distance <= max_distance);
+            }
+            // Cast in model space, where distances along the ray are scaled by the length of the transformed direction
+            let inverse = mat4_inverse(&self.model);
+            let direction = transform_direction(&inverse, ray.direction);
+            let scale = vlength(direction);
+            if !scale.is_normal() {
+                return None;
+            }
+            let model_ray = Ray::new(transform_point(&inverse, ray.origin), direction);
+            let hit = self.mesh.get_triangles().cast_ray(&model_ray, max_distance * scale)?;
+            return Some(hit.distance / scale);
+        }
+
+        /**
+         * Point of the mesh close to `point` in world space, of the box around the instances for instanced draw calls.
+         * It is searched for in model space, so it is the closest point unless the model matrix scales non-uniformly.
+         */
+        pub fn get_closest_point(&self, point: [f32; 3]) -> [f32; 3] {
+            if self.instances.is_none() {
+                let model_point = transform_point(&mat4_inverse(&self.model), point);
+                if let Some(hit) = self.mesh.get_triangles().find_nearest(model_point, f32::INFINITY) {
+                    return transform_point(&self.model, hit.point);
+                }
+            }
+            return self.get_bounding_box().closest_point(point);
+        }
+
+        /**
+         * Materials of the mesh and the ones the instances replace them with
+         */
+        pub fn get_materials(&self) -> Vec<i32> {
+            let mut materials = self.mesh.get_materials().clone();
+            if let Some(instances) = self.instances {
+                materials.extend(instances.get_materials().iter());
+                materials.sort_unstable();
+                materials.dedup();
+            }
+            return materials;
+        }
+
+        /**
+         * Draws the mesh, the model and normal matrices of the uniforms have to be set to the ones of the draw call
+         */
+        pub fn draw<S: glium::Surface, U: glium::uniforms::Uniforms>(&self, target: &mut S, program: &glium::Program, uniforms: &U, draw_parameters: &glium::DrawParameters) -> Result<(), glium::DrawError> {
+            return match self.instances {
+                Some(instances) => self.mesh.draw_instanced(target, instances, program, uniforms, draw_parameters),
+                None => self.mesh.draw(target, program, uniforms, draw_parameters),
+            };
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn welds_cube_corners_per_face() {
+            // Corners are shared by three faces with different normals, so only the two triangles of a face merge
+            let indexed = IndexedShape::from_shape(&Cube::new([0.0, 0.0, 0.0], 1.0, 0));
+            assert_eq!(indexed.vertices.len(), 24);
+            assert_eq!(indexed.normals.len(), 24);
+            assert_eq!(indexed.tangents.len(), 24);
+            assert_eq!(indexed.indices.len(), 36);
+            assert!(indexed.indices.iter().all(|i| (*i as usize) < indexed.vertices.len()));
+        }
+
+        #[test]
+        fn round_trips_cube_through_indices() {
+            let cube = Cube::new([0.0, 0.0, 0.0], 1.0, 0);
+            let shape = IndexedShape::from_shape(&cube).to_shape();
+            assert_eq!(shape.vertices.len(), cube.vertices.len());
+            for (triangle, (vertices, normals)) in cube.vertices.chunks(3).zip(cube.normals.chunks(3)).enumerate() {
+                for j in 0..3 {
+                    let (original, restored) = (&vertices[j], &shape.vertices[3*triangle + j]);
+                    assert_eq!(restored.position, original.position, "position {} of triangle {}", j, triangle);
+                    assert_eq!(restored.texture, original.texture, "texture coordinate {} of triangle {}", j, triangle);
+                    assert_eq!(restored.material_id, original.material_id);
+                    assert_eq!(shape.normals[3*triangle + j].normal, normals[j].normal, "normal {} of triangle {}", j, triangle);
+                }
+            }
+        }
+
+        #[test]
+        fn welds_negative_zero() {
+            let vertex = |position: [f32; 3]| -> Vertex { return Vertex { position: position, texture: [0.0, 0.0], material_id: 0 }; };
+            let normal = Normal { normal: [0.0, 0.0, -1.0] };
+            let tangent = Tangent { tangent: [1.0, 0.0, 0.0, 1.0] };
+            assert_eq!(weld_key(&vertex([-0.0, 0.0, -0.0]), &normal, &tangent), weld_key(&vertex([0.0, 0.0, 0.0]), &normal, &tangent));
+            assert_ne!(weld_key(&vertex([1.0, 0.0, 0.0]), &normal, &tangent), weld_key(&vertex([0.0, 0.0, 0.0]), &normal, &tangent));
+
+            let shape = AShape {
+                vertices: vec![vertex([0.0, 0.0, 0.0]), vertex([1.0, 0.0, 0.0]), vertex([0.0, 1.0, 0.0]), vertex([-0.0, -0.0, 0.0]), vertex([1.0, 0.0, 0.0]), vertex([0.0, 1.0, -0.0])],
+                normals: vec![normal; 6],
+            };
+            let indexed = IndexedShape::from_shape(&shape);
+            assert_eq!(indexed.vertices.len(), 3);
+            assert_eq!(indexed.indices, vec![0, 1, 2, 0, 1, 2]);
+        }
+    }
+}
@@ -0,0 +1,442 @@
+pub mod ObjLoader {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use crate::Base3D::General::*;
+    use crate::Material3D::Material::*;
+    use crate::GraphicsLoader2D::GraphicsLoader;
+
+    /*
+    Wavefront files use a right-handed coordinate system with outward facing normals,
+    while the engine is left-handed (+Z forward) and its shaders expect normals that
+    point into the surface. Positions and normals are therefore mirrored along Z
+    and normals are flipped on import. Mirroring Z together with the change of
+    handedness keeps the on-screen winding, so faces keep their vertex order.
+    */
+
+    fn convert_position(p: [f32; 3]) -> [f32; 3] {
+        return [p[0], p[1], -p[2]];
+    }
+
+    fn convert_normal(n: [f32; 3]) -> [f32; 3] {
+        return [-n[0], -n[1], n[2]];
+    }
+
+    fn normalize(v: [f32; 3]) -> [f32; 3] {
+        let len = (v[0]*v[0] + v[1]*v[1] + v[2]*v[2]).sqrt();
+        if len == 0.0 {
+            return [0.0, 1.0, 0.0];
+        }
+        return [v[0]/len, v[1]/len, v[2]/len];
+    }
+
+    fn face_normal(p0: [f32; 3], p1: [f32; 3], p2: [f32; 3]) -> [f32; 3] {
+        let a = [p1[0] - p0[0], p1[1] - p0[1], p1[2] - p0[2]];
+        let b = [p2[0] - p0[0], p2[1] - p0[1], p2[2] - p0[2]];
+        return normalize(Normal::normal([a, b]).normal);
+    }
+
+    /**
+     * Result of importing an OBJ file.
+     * Material ids stored in the vertices of `shape` point into the global
+     * material array starting at the offset given to `load`, and the texture
+     * ids in `materials` point into the texture array starting at the texture offset,
+     * so `images` must be appended to the images passed to `GraphicsLoader::create_texture_array`.
+     */
+    pub struct ObjModel {
+        pub shape: AShape,
+        pub materials: Vec<Material>,
+        pub images: Vec<glium::texture::RawImage2d<'static, u8>>,
+    }
+
+    struct MtlEntry {
+        name: String,
+        diffuse_color: [f32; 3],
+        specular_color: [f32; 3],
+        shininess: f32,
+        dissolve: f32,
+        diffuse_map: Option<PathBuf>,
+        specular_map: Option<PathBuf>,
+    }
+
+    impl MtlEntry {
+        fn new(name: &str) -> MtlEntry {
+            return MtlEntry {
+                name: name.to_string(),
+                diffuse_color: [0.8, 0.8, 0.8],
+                specular_color: [0.5, 0.5, 0.5],
+                shininess: 16.0,
+                dissolve: 1.0,
+                diffuse_map: None,
+                specular_map: None,
+            }
+        }
+    }
+
+    /*
+    Keeps track of images already registered so textures shared
+    between materials are only uploaded once
+    */
+    struct TextureRegistry {
+        offset: i32,
+        images: Vec<glium::texture::RawImage2d<'static, u8>>,
+        by_path: HashMap<PathBuf, i32>,
+        by_color: HashMap<[u8; 3], i32>,
+    }
+
+    impl TextureRegistry {
+        fn register_path(&mut self, path: &Path) -> Result<i32, String> {
+            if let Some(id) = self.by_path.get(path) {
+                return Ok(*id);
+            }
+            let image = GraphicsLoader::load_image_from_path(path)?;
+            let id = self.offset + self.images.len() as i32;
+            self.images.push(image);
+            self.by_path.insert(path.to_path_buf(), id);
+            return Ok(id);
+        }
+
+        fn register_color(&mut self, color: [f32; 3]) -> i32 {
+            // Colors are keyed the way they end up in the image, so colors sharing a texel share the image
+            let key = [(color[0]*255.0).floor() as u8, (color[1]*255.0).floor() as u8, (color[2]*255.0).floor() as u8];
+            if let Some(id) = self.by_color.get(&key) {
+                return *id;
+            }
+            let id = self.offset + self.images.len() as i32;
+            self.images.push(GraphicsLoader::load_image_from_color(color));
+            self.by_color.insert(key, id);
+            return id;
+        }
+    }
+
+    fn parse_floats(tokens: &[&str], line_number: usize) -> Result<Vec<f32>, String> {
+        let mut values: Vec<f32> = Vec::new();
+        for token in tokens {
+            match token.parse::<f32>() {
+                Ok(value) => values.push(value),
+                Err(_) => return Err(format!("Line {}: invalid number '{}'", line_number, token)),
+            }
+        }
+        return Ok(values);
+    }
+
+    fn parse_vec3(tokens: &[&str], line_number: usize) -> Result<[f32; 3], String> {
+        let values = parse_floats(tokens, line_number)?;
+        if values.len() < 3 {
+            return Err(format!("Line {}: expected 3 components", line_number));
+        }
+        return Ok([values[0], values[1], values[2]]);
+    }
+
+    /*
+    Texture statements may carry options such as "-s 1 1 1" before the file name,
+    so only the last token is used as path
+    */
+    fn parse_map(tokens: &[&str], directory: &Path) -> Option<PathBuf> {
+        return tokens.last().map(|name| directory.join(name.replace('\\', "/")));
+    }
+
+    fn load_mtl(path: &Path) -> Result<Vec<MtlEntry>, String> {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(error) => return Err(format!("Could not read {}: {}", path.display(), error)),
+        };
+        let directory = path.parent().unwrap_or(Path::new(""));
+
+        let mut entries: Vec<MtlEntry> = Vec::new();
+        for (i, line) in source.lines().enumerate() {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if tokens.is_empty() || tokens[0].starts_with('#') {
+                continue;
+            }
+
+            if tokens[0] == "newmtl" {
+                entries.push(MtlEntry::new(tokens.get(1).unwrap_or(&"")));
+                continue;
+            }
+
+            let entry = match entries.last_mut() {
+                Some(entry) => entry,
+                None => continue,
+            };
+
+            match tokens[0] {
+                "Kd" => entry.diffuse_color = parse_vec3(&tokens[1..], i + 1)?,
+                "Ks" => entry.specular_color = parse_vec3(&tokens[1..], i + 1)?,
+                "Ns" => entry.shininess = parse_floats(&tokens[1..], i + 1)?.first().copied().unwrap_or(16.0).max(1.0),
+                // Opacity, some exporters write the transparency 1 - d as Tr instead
+                "d" => entry.dissolve = parse_floats(&tokens[1..], i + 1)?.first().copied().unwrap_or(1.0).clamp(0.0, 1.0),
+                "Tr" => entry.dissolve = 1.0 - parse_floats(&tokens[1..], i + 1)?.first().copied().unwrap_or(0.0).clamp(0.0, 1.0),
+                "map_Kd" => entry.diffuse_map = parse_map(&tokens[1..], directory),
+                "map_Ks" => entry.specular_map = parse_map(&tokens[1..], directory),
+                _ => (),
+            }
+        }
+
+        return Ok(entries);
+    }
+
+    /*
+    Resolves a (possibly negative) OBJ index into a zero based index
+    */
+    fn resolve_index(token: &str, count: usize, line_number: usize) -> Result<usize, String> {
+        let index = match token.parse::<i64>() {
+            Ok(index) => index,
+            Err(_) => return Err(format!("Line {}: invalid index '{}'", line_number, token)),
+        };
+        let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+        if resolved < 0 || resolved >= count as i64 {
+            return Err(format!("Line {}: index {} out of range", line_number, index));
+        }
+        return Ok(resolved as usize);
+    }
+
+    #[derive(Copy, Clone)]
+    struct FaceVertex {
+        position: usize,
+        texture: Option<usize>,
+        normal: Option<usize>,
+    }
+
+    pub struct ObjLoader {}
+    impl ObjLoader {
+
+        /**
+         * Loads an OBJ file and the MTL libraries it references.
+         * `material_offset` is the first free slot in the material array and
+         * `texture_offset` is the number of images already in the texture array.
+         */
+        pub fn load(path: &Path, material_offset: i32, texture_offset: i32) -> Result<ObjModel, String> {
+            let source = match fs::read_to_string(path) {
+                Ok(source) => source,
+                Err(error) => return Err(format!("Could not read {}: {}", path.display(), error)),
+            };
+            let directory = path.parent().unwrap_or(Path::new(""));
+
+            let mut positions: Vec<[f32; 3]> = Vec::new();
+            let mut textures: Vec<[f32; 2]> = Vec::new();
+            let mut normals: Vec<[f32; 3]> = Vec::new();
+
+            let mut mtl_entries: Vec<MtlEntry> = Vec::new();
+            let mut current_material: Option<usize> = None;
+            let mut used_materials: Vec<usize> = Vec::new();
+            let mut uses_default_material = false;
+
+            // Triangles as (material entry, three face vertices)
+            let mut triangles: Vec<(Option<usize>, [FaceVertex; 3])> = Vec::new();
+
+            for (i, line) in source.lines().enumerate() {
+                let line_number = i + 1;
+                let tokens: Vec<&str> = line.split_whitespace().collect();
+                if tokens.is_empty() || tokens[0].starts_with('#') {
+                    continue;
+                }
+
+                match tokens[0] {
+                    "v" => positions.push(convert_position(parse_vec3(&tokens[1..], line_number)?)),
+                    "vt" => {
+                        let values = parse_floats(&tokens[1..], line_number)?;
+                        if values.len() < 2 {
+                            return Err(format!("Line {}: expected 2 texture coordinates", line_number));
+                        }
+                        textures.push([values[0], values[1]]);
+                    },
+                    "vn" => normals.push(convert_normal(normalize(parse_vec3(&tokens[1..], line_number)?))),
+                    "mtllib" => {
+                        for name in &tokens[1..] {
+                            mtl_entries.extend(load_mtl(&directory.join(name))?);
+                        }
+                    },
+                    "usemtl" => {
+                        let name = tokens.get(1).unwrap_or(&"");
+                        current_material = mtl_entries.iter().position(|entry| entry.name == *name);
+                    },
+                    "f" => {
+                        let mut face: Vec<FaceVertex> = Vec::new();
+                        for token in &tokens[1..] {
+                            let parts: Vec<&str> = token.split('/').collect();
+                            face.push(FaceVertex {
+                                position: resolve_index(parts[0], positions.len(), line_number)?,
+                                texture: match parts.get(1) {
+                                    Some(part) if !part.is_empty() => Some(resolve_index(part, textures.len(), line_number)?),
+                                    _ => None,
+                                },
+                                normal: match parts.get(2) {
+                                    Some(part) if !part.is_empty() => Some(resolve_index(part, normals.len(), line_number)?),
+                                    _ => None,
+                                },
+                            });
+                        }
+                        if face.len() < 3 {
+                            return Err(format!("Line {}: face with less than 3 vertices", line_number));
+                        }
+
+                        match current_material {
+                            Some(entry) => if !used_materials.contains(&entry) { used_materials.push(entry) },
+                            None => uses_default_material = true,
+                        }
+
+                        // Triangulate as a fan, which is exact for convex faces
+                        for j in 1..(face.len() - 1) {
+                            triangles.push((current_material, [face[0], face[j], face[j + 1]]));
+                        }
+                    },
+                    _ => (),
+                }
+            }
+
+            // Register materials in order of first use
+            let mut registry = TextureRegistry {
+                offset: texture_offset,
+                images: Vec::new(),
+                by_path: HashMap::new(),
+                by_color: HashMap::new(),
+            };
+            let mut materials: Vec<Material> = Vec::new();
+            let mut material_ids: HashMap<usize, i32> = HashMap::new();
+
+            for entry_index in used_materials.iter() {
+                let entry = &mtl_entries[*entry_index];
+                let diffuse = match &entry.diffuse_map {
+                    Some(map) => registry.register_path(map)?,
+                    None => registry.register_color(entry.diffuse_color),
+                };
+                let specular = match &entry.specular_map {
+                    Some(map) => registry.register_path(map)?,
+                    None => registry.register_color(entry.specular_color),
+                };
+                material_ids.insert(*entry_index, material_offset + materials.len() as i32);
+                let mut material = Material::new(diffuse, specular, entry.shininess);
+                if entry.dissolve < 1.0 {
+                    material.alpha_mode = AlphaMode::Blend;
+                    material.opacity = entry.dissolve;
+                }
+                materials.push(material);
+            }
+
+            let default_material = material_offset + materials.len() as i32;
+            if uses_default_material {
+                let entry = MtlEntry::new("default");
+                let diffuse = registry.register_color(entry.diffuse_color);
+                let specular = registry.register_color(entry.specular_color);
+                materials.push(Material::new(diffuse, specular, entry.shininess));
+            }
+
+            if material_offset + materials.len() as i32 > MAX_MATERIALS {
+                return Err(format!("{} needs {} materials, only {} slots are available", path.display(), materials.len(), MAX_MATERIALS - material_offset));
+            }
+
+            // Build the triangle soup
+            let mut shape = AShape {
+                vertices: Vec::with_capacity(triangles.len() * 3),
+                normals: Vec::with_capacity(triangles.len() * 3),
+            };
+
+            for (entry, triangle) in triangles.iter() {
+                let material_id = match entry {
+                    Some(entry) => material_ids[entry],
+                    None => default_material,
+                };
+
+                let p: Vec<[f32; 3]> = triangle.iter().map(|v| positions[v.position]).collect();
+                let flat_normal = face_normal(p[0], p[1], p[2]);
+
+                for (j, face_vertex) in triangle.iter().enumerate() {
+                    shape.vertices.push(Vertex {
+                        position: p[j],
+                        texture: match face_vertex.texture {
+                            Some(t) => textures[t],
+                            None => [0.0, 0.0],
+                        },
+                        material_id: material_id,
+                    });
+                    shape.normals.push(Normal {
+                        normal: match face_vertex.normal {
+                            Some(n) => normals[n],
+                            None => flat_normal,
+                        }
+                    });
+                }
+            }
+
+            return Ok(ObjModel {
+                shape: shape,
+                materials: materials,
+                images: registry.images,
+            });
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /* Writes the given files into a fresh directory and loads the first one */
+        fn load_files(name: &str, files: &[(&str, &str)]) -> Result<ObjModel, String> {
+            let directory = std::env::temp_dir().join(format!("obj_loader_{}_{}", name, std::process::id()));
+            fs::create_dir_all(&directory).unwrap();
+            for (file, source) in files.iter() {
+                fs::write(directory.join(file), source).unwrap();
+            }
+            let result = ObjLoader::load(&directory.join(files[0].0), 0, 0);
+            fs::remove_dir_all(&directory).unwrap();
+            return result;
+        }
+
+        fn positions(shape: &AShape) -> Vec<[f32; 3]> {
+            return shape.vertices.iter().map(|v| v.position).collect();
+        }
+
+        const QUAD: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+        #[test]
+        fn triangulates_polygons_as_fan() {
+            let model = load_files("fan", &[("quad.obj", &format!("{}f 1 2 3 4\n", QUAD))]).unwrap();
+            assert_eq!(positions(&model.shape), vec![
+                [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0],
+            ]);
+        }
+
+        #[test]
+        fn resolves_negative_indices() {
+            let relative = load_files("relative", &[("quad.obj", &format!("{}vt 0 0\nvt 1 1\nf -4/-2 -3/-1 -2/-1 -1/-2\n", QUAD))]).unwrap();
+            let absolute = load_files("absolute", &[("quad.obj", &format!("{}vt 0 0\nvt 1 1\nf 1/1 2/2 3/2 4/1\n", QUAD))]).unwrap();
+            assert_eq!(positions(&relative.shape), positions(&absolute.shape));
+            let textures: Vec<[f32; 2]> = relative.shape.vertices.iter().map(|v| v.texture).collect();
+            assert_eq!(textures, absolute.shape.vertices.iter().map(|v| v.texture).collect::<Vec<[f32; 2]>>());
+
+            assert!(load_files("out_of_range", &[("quad.obj", &format!("{}f -5 1 2\n", QUAD))]).is_err());
+        }
+
+        #[test]
+        fn mirrors_positions_along_z() {
+            let model = load_files("mirror", &[("point.obj", "v 1 2 3\nv 2 2 3\nv 1 3 3\nf 1 2 3\n")]).unwrap();
+            assert_eq!(model.shape.vertices[0].position, [1.0, 2.0, -3.0]);
+        }
+
+        #[test]
+        fn falls_back_to_flat_normals() {
+            // Without normals the face normal has to match the imported normal of the front side
+            let flat = load_files("flat", &[("triangle.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n")]).unwrap();
+            let given = load_files("given", &[("triangle.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1\n")]).unwrap();
+            assert_eq!(flat.shape.normals.len(), 3);
+            for (flat, given) in flat.shape.normals.iter().zip(given.shape.normals.iter()) {
+                assert_eq!(flat.normal, given.normal);
+            }
+        }
+
+        #[test]
+        fn shares_images_of_equal_colors() {
+            let mtl = "newmtl red\nKd 1 0 0\nKs 0.5 0.5 0.5\nnewmtl also_red\nKd 1 0 0\nKs 0.5 0.5 0.5\n";
+            let obj = format!("mtllib colors.mtl\n{}usemtl red\nf 1 2 3\nusemtl also_red\nf 1 3 4\n", QUAD);
+            let model = load_files("colors", &[("colors.obj", &obj), ("colors.mtl", mtl)]).unwrap();
+            assert_eq!(model.materials.len(), 2);
+            assert_eq!(model.images.len(), 2);
+            assert_eq!(model.materials[0].diffuse, model.materials[1].diffuse);
+            assert_eq!(model.materials[0].specular, model.materials[1].specular);
+            assert_ne!(model.materials[0].diffuse, model.materials[0].specular);
+        }
+    }
+}
@@ -0,0 +1,323 @@
+pub mod PostProcess {
+    use std::path::Path;
+    use std::rc::Rc;
+    use std::time::Instant;
+
+    use glium::Surface;
+    use glium::framebuffer::SimpleFrameBuffer;
+    use glium::texture::{MipmapsOption, Texture2d, Texture3d, UncompressedFloatFormat};
+    use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, SamplerBehavior, SamplerWrapFunction, UniformValue};
+
+    use crate::Screen3D::Screen::*;
+    use crate::Shaders;
+
+    /**
+     * Names of the built-in passes, in the order they run
+     */
+    pub const FXAA: &str = "fxaa";
+    pub const COLOR_GRADING: &str = "color grading";
+    pub const GRAYSCALE: &str = "grayscale";
+    pub const CHROMATIC_ABERRATION: &str = "chromatic aberration";
+    pub const VIGNETTE: &str = "vignette";
+    pub const FILM_GRAIN: &str = "film grain";
+
+    /*
+    Edge length of the color grading table built when no LUT file is given
+    */
+    const DEFAULT_LUT_SIZE: u32 = 16;
+
+    /**
+     * Value of a uniform set on a post-processing pass
+     */
+    #[derive(Clone)]
+    pub enum PassValue {
+        Float(f32),
+        Texture3d(Rc<Texture3d>),
+    }
+
+    /**
+     * A full screen pass, a fragment shader and the uniforms it is drawn with.
+     *
+     * Besides its own values every pass gets the output of the pass before it as `source`,
+     * the size of the screen in pixels as `resolution` and the seconds since start as `time`.
+     * The fragment shader reads `v_texture` from `SCREEN_VERTEX_SHADER`.
+     */
+    pub struct PostProcessPass {
+        name: String,
+        program: glium::Program,
+        values: Vec<(String, PassValue)>,
+        pub enabled: bool,
+    }
+
+    impl PostProcessPass {
+
+        pub fn new(display: &glium::Display, name: &str, fragment_shader: &str) -> Result<PostProcessPass, String> {
+            let program = match glium::Program::from_source(display, Shaders::SCREEN_VERTEX_SHADER, fragment_shader, None) {
+                Ok(program) => program,
+                Err(error) => return Err(format!("Could not compile post-processing pass {}: {}", name, error)),
+            };
+            return Ok(PostProcessPass {
+                name: name.to_string(),
+                program: program,
+                values: Vec::new(),
+                enabled: true,
+            });
+        }
+
+        /**
+         * Sets the uniform `name`, replacing an earlier value
+         */
+        pub fn set_value(&mut self, name: &str, value: PassValue) {
+            match self.values.iter_mut().find(|(value_name, _)| value_name == name) {
+                Some(entry) => entry.1 = value,
+                None => self.values.push((name.to_string(), value)),
+            }
+        }
+    }
+
+    /*
+    Uniforms of one pass, the built-in ones followed by the values of the pass
+    */
+    struct PassUniforms<'a> {
+        source: &'a Texture2d,
+        resolution: [f32; 2],
+        time: f32,
+        values: &'a [(String, PassValue)],
+    }
+
+    impl glium::uniforms::Uniforms for PassUniforms<'_> {
+        fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut f: F) {
+            f("source", UniformValue::Texture2d(self.source, Some(get_linear_sampler(self.source).1)));
+            f("resolution", UniformValue::Vec2(self.resolution));
+            f("time", UniformValue::Float(self.time));
+
+            for (name, value) in self.values.iter() {
+                match value {
+                    PassValue::Float(value) => f(name, UniformValue::Float(*value)),
+                    PassValue::Texture3d(texture) => f(name, UniformValue::Texture3d(texture, Some(get_lut_sampler()))),
+                }
+            }
+        }
+    }
+
+    /**
+     * Ordered list of full screen passes run on the tone mapped image. The image is drawn into
+     * the framebuffer from `get_framebuffer`, every enabled pass then reads the result of the one
+     * before from one buffer and writes the other, and the last one draws to the screen.
+     */
+    pub struct PostProcessStack {
+        passes: Vec<PostProcessPass>,
+        copy_program: glium::Program,
+        screen_quad: ScreenQuad,
+        buffers: Vec<Texture2d>,
+        start: Instant,
+    }
+
+    impl PostProcessStack {
+
+        pub fn new(display: &glium::Display) -> PostProcessStack {
+            return PostProcessStack {
+                passes: Vec::new(),
+                copy_program: glium::Program::from_source(display, Shaders::SCREEN_VERTEX_SHADER, Shaders::COPY_FRAGMENT_SHADER, None).unwrap(),
+                screen_quad: ScreenQuad::new(display),
+                buffers: Vec::new(),
+                start: Instant::now(),
+            }
+        }
+
+        /**
+         * Stack with all built-in passes, only FXAA starts enabled
+         */
+        pub fn with_default_passes(display: &glium::Display) -> PostProcessStack {
+            let mut stack = PostProcessStack::new(display);
+            let built_in = |name: &str, fragment_shader: &str, values: Vec<(&str, PassValue)>| -> PostProcessPass {
+                let mut pass = PostProcessPass::new(display, name, fragment_shader).unwrap();
+                for (value_name, value) in values.into_iter() {
+                    pass.set_value(value_name, value);
+                }
+                pass.enabled = name == FXAA;
+                return pass;
+            };
+
+            stack.add_pass(built_in(FXAA, Shaders::FXAA_FRAGMENT_SHADER, vec![]));
+            stack.add_pass(built_in(COLOR_GRADING, Shaders::COLOR_GRADING_FRAGMENT_SHADER, vec![
+                ("lut", PassValue::Texture3d(Rc::new(create_film_lut(display, DEFAULT_LUT_SIZE)))),
+                ("intensity", PassValue::Float(1.0)),
+            ]));
+            stack.add_pass(built_in(GRAYSCALE, Shaders::GRAYSCALE_FRAGMENT_SHADER, vec![
+                ("amount", PassValue::Float(1.0)),
+            ]));
+            stack.add_pass(built_in(CHROMATIC_ABERRATION, Shaders::CHROMATIC_ABERRATION_FRAGMENT_SHADER, vec![
+                ("strength", PassValue::Float(0.006)),
+            ]));
+            stack.add_pass(built_in(VIGNETTE, Shaders::VIGNETTE_FRAGMENT_SHADER, vec![
+                ("strength", PassValue::Float(0.6)),
+                ("radius", PassValue::Float(0.55)),
+                ("softness", PassValue::Float(0.45)),
+            ]));
+            stack.add_pass(built_in(FILM_GRAIN, Shaders::FILM_GRAIN_FRAGMENT_SHADER, vec![
+                ("intensity", PassValue::Float(0.05)),
+            ]));
+            return stack;
+        }
+
+        /**
+         * Appends a pass, it runs after all passes added before it
+         */
+        pub fn add_pass(&mut self, pass: PostProcessPass) {
+            self.passes.push(pass);
+        }
+
+        pub fn get_pass_mut(&mut self, name: &str) -> Option<&mut PostProcessPass> {
+            return self.passes.iter_mut().find(|pass| pass.name == name);
+        }
+
+        /**
+         * Turns the pass `name` on or off, returns whether it is enabled now or None if there is no such pass
+         */
+        pub fn toggle(&mut self, name: &str) -> Option<bool> {
+            let pass = self.get_pass_mut(name)?;
+            pass.enabled = !pass.enabled;
+            return Some(pass.enabled);
+        }
+
+        /**
+         * Framebuffer the image to post-process is drawn into, resized to `dimensions` when needed
+         */
+        pub fn get_framebuffer(&mut self, display: &glium::Display, dimensions: (u32, u32)) -> SimpleFrameBuffer<'_> {
+            if self.buffers.first().map_or(true, |buffer| (buffer.width(), buffer.height()) != dimensions) {
+                self.buffers = (0..2).map(|_| {
+                    Texture2d::empty_with_format(display, UncompressedFloatFormat::F16F16F16F16, MipmapsOption::NoMipmap, dimensions.0, dimensions.1).unwrap()
+                }).collect();
+            }
+            return SimpleFrameBuffer::new(display, &self.buffers[0]).unwrap();
+        }
+
+        /**
+         * Runs the enabled passes on the image drawn into `get_framebuffer` and draws the result to `target`
+         */
+        pub fn render<S: Surface>(&self, display: &glium::Display, target: &mut S) {
+            let source = &self.buffers[0];
+            let resolution = [source.width() as f32, source.height() as f32];
+            let time = self.start.elapsed().as_secs_f32();
+            let enabled: Vec<&PostProcessPass> = self.passes.iter().filter(|pass| pass.enabled).collect();
+
+            if enabled.is_empty() {
+                self.screen_quad.draw(target, &self.copy_program, &uniform! {source: get_linear_sampler(source)}, &Default::default()).unwrap();
+                return;
+            }
+
+            let mut current = 0;
+            for (i, pass) in enabled.iter().enumerate() {
+                let uniforms = PassUniforms {
+                    source: &self.buffers[current],
+                    resolution: resolution,
+                    time: time,
+                    values: &pass.values,
+                };
+                if i + 1 == enabled.len() {
+                    self.screen_quad.draw(target, &pass.program, &uniforms, &Default::default()).unwrap();
+                } else {
+                    let mut framebuffer = SimpleFrameBuffer::new(display, &self.buffers[1 - current]).unwrap();
+                    self.screen_quad.draw(&mut framebuffer, &pass.program, &uniforms, &Default::default()).unwrap();
+                    current = 1 - current;
+                }
+            }
+        }
+    }
+
+    fn get_lut_sampler() -> SamplerBehavior {
+        return SamplerBehavior {
+            wrap_function: (SamplerWrapFunction::Clamp, SamplerWrapFunction::Clamp, SamplerWrapFunction::Clamp),
+            minify_filter: MinifySamplerFilter::Linear,
+            magnify_filter: MagnifySamplerFilter::Linear,
+            .. Default::default()
+        };
+    }
+
+    /**
+     * Color grading table of `size`^3 entries mapping sRGB colors to graded sRGB colors,
+     * red changes fastest, then green, then blue
+     */
+    pub fn create_lut(display: &glium::Display, size: u32, table: &[[f32; 3]]) -> Texture3d {
+        let size = size as usize;
+        let data: Vec<Vec<Vec<(f32, f32, f32)>>> = (0..size).map(|b| {
+            (0..size).map(|g| {
+                (0..size).map(|r| {
+                    let color = table[(b * size + g) * size + r];
+                    (color[0], color[1], color[2])
+                }).collect()
+            }).collect()
+        }).collect();
+        return Texture3d::with_format(display, data, UncompressedFloatFormat::F16F16F16, MipmapsOption::NoMipmap).unwrap();
+    }
+
+    /**
+     * Built-in grade with a little more contrast, warmer highlights and cooler shadows
+     */
+    pub fn create_film_lut(display: &glium::Display, size: u32) -> Texture3d {
+        let mut table: Vec<[f32; 3]> = Vec::with_capacity((size * size * size) as usize);
+        let step = 1.0 / (size - 1) as f32;
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let color = [r as f32 * step, g as f32 * step, b as f32 * step];
+                    let luma = 0.299 * color[0] + 0.587 * color[1] + 0.114 * color[2];
+                    let tint = [0.04 * (luma - 0.5), 0.0, -0.04 * (luma - 0.5)];
+                    let mut graded = [0.0; 3];
+                    for c in 0..3 {
+                        // Smoothstep S-curve mixed halfway with the input
+                        let x = color[c];
+                        let curve = x * x * (3.0 - 2.0 * x);
+                        graded[c] = (0.5 * (x + curve) + tint[c]).clamp(0.0, 1.0);
+                    }
+                    table.push(graded);
+                }
+            }
+        }
+        return create_lut(display, size, &table);
+    }
+
+    /**
+     * Loads a color grading table in the Adobe / Resolve `.cube` format
+     */
+    pub fn load_cube_lut(display: &glium::Display, path: &Path) -> Result<Texture3d, String> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) => return Err(format!("Could not read {}: {}", path.display(), error)),
+        };
+
+        let mut size: Option<u32> = None;
+        let mut table: Vec<[f32; 3]> = Vec::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let first = parts.next().unwrap();
+            if first == "LUT_3D_SIZE" {
+                size = parts.next().and_then(|value| value.parse().ok());
+                continue;
+            }
+            if first.chars().next().map_or(false, |c| c.is_ascii_alphabetic()) {
+                // TITLE, DOMAIN_MIN, DOMAIN_MAX and other keywords
+                continue;
+            }
+            let values: Vec<f32> = line.split_whitespace().filter_map(|value| value.parse().ok()).collect();
+            if values.len() != 3 {
+                return Err(format!("Invalid LUT entry: {}", line));
+            }
+            table.push([values[0], values[1], values[2]]);
+        }
+
+        let size = match size {
+            Some(size) if size >= 2 => size,
+            _ => return Err(format!("{} has no valid LUT_3D_SIZE", path.display())),
+        };
+        if table.len() != (size * size * size) as usize {
+            return Err(format!("{} has {} entries, expected {}", path.display(), table.len(), size * size * size));
+        }
+        return Ok(create_lut(display, size, &table));
+    }
+}
@@ -0,0 +1,510 @@
+pub mod Scene {
+    use std::rc::Rc;
+
+    use crate::Bounds3D::Bounds::*;
+    use crate::Bvh3D::Bvh::*;
+    use crate::Lights3D::Lights::*;
+    use crate::Math3D::Math::*;
+    use crate::Mesh3D::Mesh::*;
+    use crate::Transform3D::Transform::*;
+
+    /**
+     * Handle to a node. The generation makes handles of removed nodes invalid
+     * even after their slot has been reused.
+     */
+    #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+    pub struct NodeId {
+        index: usize,
+        generation: u32,
+    }
+
+    /**
+     * Light attached to a node, described in the node's local space
+     */
+    #[derive(Copy, Clone)]
+    pub enum SceneLight {
+        Directional(DirectionalLight),
+        Point(PointLight),
+        Spot(SpotLight),
+    }
+
+    pub struct SceneNode {
+        pub name: String,
+        pub mesh: Option<Rc<GpuMesh>>,
+        // Draws the mesh once for every instance, placed relative to the node
+        pub instances: Option<InstanceBuffer>,
+        pub light: Option<SceneLight>,
+        pub unlit: bool,
+        transform: Transform,
+        parent: Option<NodeId>,
+        children: Vec<NodeId>,
+        world_transform: [[f32; 4]; 4],
+        normal_matrix: [[f32; 3]; 3],
+        dirty: bool,
+    }
+
+    impl SceneNode {
+        pub fn get_transform(&self) -> &Transform {
+            return &self.transform;
+        }
+
+        pub fn get_parent(&self) -> Option<NodeId> {
+            return self.parent;
+        }
+
+        pub fn get_children(&self) -> &Vec<NodeId> {
+            return &self.children;
+        }
+
+        /**
+         * World transform as of the last `update_world_transforms`
+         */
+        pub fn get_world_transform(&self) -> [[f32; 4]; 4] {
+            return self.world_transform;
+        }
+
+        /**
+         * Draw call of the node's mesh with its world transform as of the last `update_world_transforms`
+         */
+        pub fn get_draw_call(&self) -> Option<DrawCall<'_>> {
+            let mesh = self.mesh.as_ref()?;
+            return Some(DrawCall {
+                mesh: mesh,
+                model: self.world_transform,
+                normal_matrix: self.normal_matrix,
+                instances: self.instances.as_ref(),
+            });
+        }
+    }
+
+    /**
+     * Tree of nodes, addressed by `NodeId`s. A BVH over the world space bounding boxes of the mesh nodes
+     * is refit along with the world transforms and rebuilt when meshes are added or removed.
+     */
+    pub struct SceneGraph {
+        nodes: Vec<Option<SceneNode>>,
+        generations: Vec<u32>,
+        free_slots: Vec<usize>,
+        roots: Vec<NodeId>,
+        bvh: Bvh,
+        mesh_nodes: Vec<NodeId>,
+    }
+
+    impl SceneGraph {
+
+        pub fn new() -> SceneGraph {
+            return SceneGraph {
+                nodes: Vec::new(),
+                generations: Vec::new(),
+                free_slots: Vec::new(),
+                roots: Vec::new(),
+                bvh: Bvh::build(&[]),
+                mesh_nodes: Vec::new(),
+            }
+        }
+
+        pub fn contains(&self, id: NodeId) -> bool {
+            return self.get(id).is_some();
+        }
+
+        pub fn get(&self, id: NodeId) -> Option<&SceneNode> {
+            if self.generations.get(id.index) != Some(&id.generation) {
+                return None;
+            }
+            return self.nodes[id.index].as_ref();
+        }
+
+        /**
+         * Mutable access to a node's contents. Use `set_transform` or
+         * `get_transform_mut` to move nodes, so world transforms are refreshed.
+         */
+        pub fn get_mut(&mut self, id: NodeId) -> Option<&mut SceneNode> {
+            if self.generations.get(id.index) != Some(&id.generation) {
+                return None;
+            }
+            return self.nodes[id.index].as_mut();
+        }
+
+        pub fn get_roots(&self) -> &Vec<NodeId> {
+            return &self.roots;
+        }
+
+        pub fn find(&self, name: &str) -> Option<NodeId> {
+            for (index, node) in self.nodes.iter().enumerate() {
+                if let Some(node) = node {
+                    if node.name == name {
+                        return Some(NodeId { index: index, generation: self.generations[index] });
+                    }
+                }
+            }
+            return None;
+        }
+
+        pub fn add_node(&mut self, name: &str, transform: Transform, parent: Option<NodeId>) -> NodeId {
+            let parent = parent.filter(|parent| self.contains(*parent));
+            let node = SceneNode {
+                name: name.to_string(),
+                mesh: None,
+                instances: None,
+                light: None,
+                unlit: false,
+                transform: transform,
+                parent: parent,
+                children: Vec::new(),
+                world_transform: IDENTITY,
+                normal_matrix: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+                dirty: true,
+            };
+
+            let id = match self.free_slots.pop() {
+                Some(index) => {
+                    self.nodes[index] = Some(node);
+                    NodeId { index: index, generation: self.generations[index] }
+                },
+                None => {
+                    self.nodes.push(Some(node));
+                    self.generations.push(0);
+                    NodeId { index: self.nodes.len() - 1, generation: 0 }
+                },
+            };
+
+            match parent {
+                Some(parent) => self.get_mut(parent).unwrap().children.push(id),
+                None => self.roots.push(id),
+            }
+            return id;
+        }
+
+        pub fn add_mesh(&mut self, name: &str, mesh: &Rc<GpuMesh>, transform: Transform, parent: Option<NodeId>) -> NodeId {
+            let id = self.add_node(name, transform, parent);
+            self.get_mut(id).unwrap().mesh = Some(Rc::clone(mesh));
+            return id;
+        }
+
+        pub fn add_instanced_mesh(&mut self, name: &str, mesh: &Rc<GpuMesh>, instances: InstanceBuffer, transform: Transform, parent: Option<NodeId>) -> NodeId {
+            let id = self.add_mesh(name, mesh, transform, parent);
+            self.get_mut(id).unwrap().instances = Some(instances);
+            return id;
+        }
+
+        pub fn add_light(&mut self, name: &str, light: SceneLight, transform: Transform, parent: Option<NodeId>) -> NodeId {
+            let id = self.add_node(name, transform, parent);
+            self.get_mut(id).unwrap().light = Some(light);
+            return id;
+        }
+
+        /**
+         * Removes a node together with all of its descendants
+         */
+        pub fn remove_node(&mut self, id: NodeId) {
+            let parent = match self.get(id) {
+                Some(node) => node.parent,
+                None => return,
+            };
+            self.detach(id, parent);
+
+            let mut stack: Vec<NodeId> = vec![id];
+            while let Some(current) = stack.pop() {
+                if let Some(node) = self.nodes[current.index].take() {
+                    stack.extend(node.children);
+                    self.generations[current.index] += 1;
+                    self.free_slots.push(current.index);
+                }
+            }
+        }
+
+        /**
+         * Moves a node under a new parent, or to the root when `new_parent` is None.
+         * With `keep_world_transform` the local transform is adjusted so the node stays in place.
+         */
+        pub fn reparent(&mut self, id: NodeId, new_parent: Option<NodeId>, keep_world_transform: bool) -> Result<(), String> {
+            let old_parent = match self.get(id) {
+                Some(node) => node.parent,
+                None => return Err("Node does not exist".to_string()),
+            };
+
+            if let Some(new_parent) = new_parent {
+                if !self.contains(new_parent) {
+                    return Err("New parent does not exist".to_string());
+                }
+                // Refuse to create cycles
+                let mut ancestor = Some(new_parent);
+                while let Some(current) = ancestor {
+                    if current == id {
+                        return Err("A node can not be moved below itself".to_string());
+                    }
+                    ancestor = self.get(current).unwrap().parent;
+                }
+            }
+
+            if keep_world_transform {
+                self.update_world_transforms();
+                let world = self.get(id).unwrap().world_transform;
+                let parent_world = match new_parent {
+                    Some(new_parent) => self.get(new_parent).unwrap().world_transform,
+                    None => IDENTITY,
+                };
+                let local = mat4_mul(&mat4_inverse(&parent_world), &world);
+                self.get_mut(id).unwrap().transform = Transform::from_matrix(&local);
+            }
+
+            self.detach(id, old_parent);
+            match new_parent {
+                Some(new_parent) => self.get_mut(new_parent).unwrap().children.push(id),
+                None => self.roots.push(id),
+            }
+            let node = self.get_mut(id).unwrap();
+            node.parent = new_parent;
+            node.dirty = true;
+            return Ok(());
+        }
+
+        fn detach(&mut self, id: NodeId, parent: Option<NodeId>) {
+            match parent.and_then(|parent| self.get_mut(parent)) {
+                Some(parent) => parent.children.retain(|child| *child != id),
+                None => self.roots.retain(|root| *root != id),
+            }
+        }
+
+        pub fn set_transform(&mut self, id: NodeId, transform: Transform) {
+            if let Some(node) = self.get_mut(id) {
+                node.transform = transform;
+                node.dirty = true;
+            }
+        }
+
+        pub fn get_transform_mut(&mut self, id: NodeId) -> Option<&mut Transform> {
+            let node = self.get_mut(id)?;
+            node.dirty = true;
+            return Some(&mut node.transform);
+        }
+
+        /**
+         * Recomputes the cached world transforms of dirty nodes and their descendants, then updates the BVH
+         */
+        pub fn update_world_transforms(&mut self) {
+            let mut stack: Vec<(NodeId, [[f32; 4]; 4], bool)> = self.roots.iter().map(|root| (*root, IDENTITY, false)).collect();
+            while let Some((id, parent_world, parent_changed)) = stack.pop() {
+                let node = match self.nodes[id.index].as_mut() {
+                    Some(node) => node,
+                    None => continue,
+                };
+                let changed = parent_changed || node.dirty;
+                if changed {
+                    node.world_transform = mat4_mul(&parent_world, &node.transform.get_model_matrix());
+                    node.normal_matrix = normal_matrix(&node.world_transform);
+                    node.dirty = false;
+                }
+                for child in node.children.iter() {
+                    stack.push((*child, node.world_transform, changed));
+                }
+            }
+            self.update_bvh();
+        }
+
+        /*
+        Refits the BVH to the current bounds of the mesh nodes. Meshes and instances may have changed
+        without their nodes being marked dirty, so every mesh node is refit, not only the moved ones.
+        */
+        fn update_bvh(&mut self) {
+            let mut mesh_nodes: Vec<NodeId> = Vec::new();
+            let mut bounds: Vec<Aabb> = Vec::new();
+            self.for_each_node(|id, node| {
+                if let Some(draw_call) = node.get_draw_call() {
+                    mesh_nodes.push(id);
+                    bounds.push(draw_call.get_bounding_box());
+                }
+            });
+            if mesh_nodes != self.mesh_nodes || self.bvh.refit(&bounds).is_err() {
+                self.bvh = Bvh::build(&bounds);
+                self.mesh_nodes = mesh_nodes;
+            }
+        }
+
+        /**
+         * BVH over the bounding boxes of the mesh nodes as of the last `update_world_transforms`,
+         * item `i` is the node `get_mesh_nodes()[i]`
+         */
+        pub fn get_bvh(&self) -> &Bvh {
+            return &self.bvh;
+        }
+
+        /**
+         * Nodes with meshes in the order of `collect_draw_calls`
+         */
+        pub fn get_mesh_nodes(&self) -> &Vec<NodeId> {
+            return &self.mesh_nodes;
+        }
+
+        /**
+         * Closest mesh node hit by a ray within `max_distance` and the distance to the hit.
+         * Meshes are hit on their triangles, instanced meshes on the box around their instances.
+         */
+        pub fn cast_ray(&self, ray: &Ray, max_distance: f32) -> Option<(NodeId, f32)> {
+            let hit = self.bvh.cast_ray(ray, max_distance, |item, ray| {
+                return self.get(self.mesh_nodes[item]).and_then(|node| node.get_draw_call()).and_then(|draw_call| draw_call.intersect_ray(ray, max_distance));
+            })?;
+            return Some((self.mesh_nodes[hit.item], hit.distance));
+        }
+
+        /**
+         * Mesh node closest to `point` within `max_distance`, with the point of its mesh closest to `point`
+         */
+        pub fn find_nearest(&self, point: [f32; 3], max_distance: f32) -> Option<(NodeId, NearestHit)> {
+            let hit = self.bvh.find_nearest(point, max_distance, |item, point| {
+                return match self.get(self.mesh_nodes[item]).and_then(|node| node.get_draw_call()) {
+                    Some(draw_call) => draw_call.get_closest_point(point),
+                    // Removed since the last update, infinitely far away
+                    None => [f32::INFINITY; 3],
+                };
+            })?;
+            return Some((self.mesh_nodes[hit.item], hit));
+        }
+
+        fn for_each_node<'a, F: FnMut(NodeId, &'a SceneNode)>(&'a self, mut f: F) {
+            let mut stack: Vec<NodeId> = self.roots.clone();
+            while let Some(id) = stack.pop() {
+                if let Some(node) = self.get(id) {
+                    f(id, node);
+                    stack.extend(node.children.iter());
+                }
+            }
+        }
+
+        /**
+         * Draw calls for every mesh in the scene, either the lit ones or the unlit ones.
+         * Call `update_world_transforms` first.
+         */
+        pub fn collect_draw_calls(&self, unlit: bool) -> Vec<DrawCall<'_>> {
+            let mut draw_calls: Vec<DrawCall> = Vec::new();
+            self.for_each_node(|_, node| {
+                if node.unlit == unlit {
+                    draw_calls.extend(node.get_draw_call());
+                }
+            });
+            return draw_calls;
+        }
+
+        /**
+         * All lights in world space. Call `update_world_transforms` first.
+         */
+        pub fn collect_lights(&self) -> LightList {
+            let mut lights = LightList::new();
+            self.for_each_node(|_, node| {
+                let world = &node.world_transform;
+                match node.light {
+                    Some(SceneLight::Directional(light)) => {
+                        let mut light = light;
+                        light.direction = vnormalize(transform_direction(world, light.direction));
+                        lights.directional_lights.push(light);
+                    },
+                    Some(SceneLight::Point(light)) => {
+                        let mut light = light;
+                        light.position = transform_point(world, light.position);
+                        lights.point_lights.push(light);
+                    },
+                    Some(SceneLight::Spot(light)) => {
+                        let mut light = light;
+                        light.position = transform_point(world, light.position);
+                        light.direction = vnormalize(transform_direction(world, light.direction));
+                        lights.spot_lights.push(light);
+                    },
+                    None => (),
+                }
+            });
+            return lights;
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn get_position(scene_graph: &SceneGraph, id: NodeId) -> [f32; 3] {
+            let world = scene_graph.get(id).unwrap().get_world_transform();
+            return [world[3][0], world[3][1], world[3][2]];
+        }
+
+        fn assert_near(a: [f32; 3], b: [f32; 3]) {
+            assert!(vlength(vsub(a, b)) < 1e-5, "{:?} != {:?}", a, b);
+        }
+
+        #[test]
+        fn reparents_under_a_moved_parent() {
+            let mut scene_graph = SceneGraph::new();
+            let parent_transform = Transform::new([1.0, 0.0, 0.0], Quaternion::from_axis_angle([0.0, 1.0, 0.0], 0.5), [2.0, 2.0, 2.0]);
+            let parent = scene_graph.add_node("parent", parent_transform, None);
+            let kept = scene_graph.add_node("kept", Transform::from_translation([0.0, 2.0, 0.0]), None);
+            let moved = scene_graph.add_node("moved", Transform::from_translation([0.0, 2.0, 0.0]), None);
+            scene_graph.update_world_transforms();
+
+            // Keeping the world transform leaves the node in place, otherwise its transform becomes relative to the parent
+            scene_graph.reparent(kept, Some(parent), true).unwrap();
+            scene_graph.reparent(moved, Some(parent), false).unwrap();
+            scene_graph.update_world_transforms();
+            assert_near(get_position(&scene_graph, kept), [0.0, 2.0, 0.0]);
+            assert_near(get_position(&scene_graph, moved), [1.0, 4.0, 0.0]);
+            assert_eq!(scene_graph.get(parent).unwrap().get_children(), &vec![kept, moved]);
+            assert_eq!(scene_graph.get(kept).unwrap().get_parent(), Some(parent));
+            assert_eq!(scene_graph.get_roots(), &vec![parent]);
+
+            // Children follow their parent
+            scene_graph.get_transform_mut(parent).unwrap().translation = [1.0, 0.0, 5.0];
+            scene_graph.update_world_transforms();
+            assert_near(get_position(&scene_graph, kept), [0.0, 2.0, 5.0]);
+            assert_near(get_position(&scene_graph, moved), [1.0, 4.0, 5.0]);
+
+            // Moving back to the root keeps the world transform
+            scene_graph.reparent(moved, None, true).unwrap();
+            scene_graph.update_world_transforms();
+            assert_near(get_position(&scene_graph, moved), [1.0, 4.0, 5.0]);
+            assert_eq!(scene_graph.get_roots(), &vec![parent, moved]);
+        }
+
+        #[test]
+        fn invalidates_removed_nodes() {
+            let mut scene_graph = SceneGraph::new();
+            let parent = scene_graph.add_node("parent", Transform::identity(), None);
+            let child = scene_graph.add_node("child", Transform::identity(), Some(parent));
+            let other = scene_graph.add_node("other", Transform::identity(), None);
+            scene_graph.remove_node(parent);
+            assert!(!scene_graph.contains(parent));
+            assert!(!scene_graph.contains(child));
+            assert!(scene_graph.contains(other));
+            assert_eq!(scene_graph.find("child"), None);
+            assert_eq!(scene_graph.get_roots(), &vec![other]);
+
+            // New nodes reuse the freed slots, but the old handles stay invalid
+            let reused = scene_graph.add_node("reused", Transform::identity(), None);
+            let reused_child = scene_graph.add_node("reused child", Transform::identity(), Some(parent));
+            assert!(scene_graph.get(parent).is_none());
+            assert!(scene_graph.get_mut(child).is_none());
+            assert!(scene_graph.get_transform_mut(parent).is_none());
+            assert!(scene_graph.reparent(parent, Some(other), false).is_err());
+            assert_eq!(scene_graph.get(reused).unwrap().name, "reused");
+            // A stale parent handle adds the node as a root
+            assert_eq!(scene_graph.get(reused_child).unwrap().get_parent(), None);
+            assert_eq!(scene_graph.find("reused child"), Some(reused_child));
+            scene_graph.remove_node(parent);
+            assert!(scene_graph.contains(reused));
+        }
+
+        #[test]
+        fn rejects_cycles() {
+            let mut scene_graph = SceneGraph::new();
+            let a = scene_graph.add_node("a", Transform::identity(), None);
+            let b = scene_graph.add_node("b", Transform::identity(), Some(a));
+            let c = scene_graph.add_node("c", Transform::identity(), Some(b));
+            assert!(scene_graph.reparent(a, Some(c), false).is_err());
+            assert!(scene_graph.reparent(b, Some(b), true).is_err());
+            assert_eq!(scene_graph.get(a).unwrap().get_children(), &vec![b]);
+            assert_eq!(scene_graph.get(c).unwrap().get_parent(), Some(b));
+            assert_eq!(scene_graph.get_roots(), &vec![a]);
+
+            // Moving a node below a sibling branch is fine
+            scene_graph.reparent(c, Some(a), false).unwrap();
+            scene_graph.reparent(b, Some(c), false).unwrap();
+            assert_eq!(scene_graph.get(b).unwrap().get_parent(), Some(c));
+        }
+    }
+}
@@ -0,0 +1,49 @@
+pub mod Screen {
+    use glium::Surface;
+    use glium::texture::Texture2d;
+    use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, Sampler, SamplerWrapFunction};
+
+    #[derive(Copy, Clone)]
+    pub struct ScreenVertex {
+        pub position: [f32; 2],
+    }
+
+    implement_vertex!(ScreenVertex, position);
+
+    /**
+     * Quad covering the whole viewport in normalized device coordinates, for full screen passes
+     */
+    pub struct ScreenQuad {
+        vertex_buffer: glium::VertexBuffer<ScreenVertex>,
+    }
+
+    impl ScreenQuad {
+
+        pub fn new(display: &glium::Display) -> ScreenQuad {
+            let vertices = [
+                ScreenVertex { position: [-1.0, -1.0] },
+                ScreenVertex { position: [1.0, -1.0] },
+                ScreenVertex { position: [-1.0, 1.0] },
+                ScreenVertex { position: [1.0, 1.0] },
+            ];
+            return ScreenQuad {
+                vertex_buffer: glium::VertexBuffer::immutable(display, &vertices).unwrap(),
+            }
+        }
+
+        pub fn draw<S: Surface, U: glium::uniforms::Uniforms>(&self, target: &mut S, program: &glium::Program, uniforms: &U, draw_parameters: &glium::DrawParameters) -> Result<(), glium::DrawError> {
+            let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+            return target.draw(&self.vertex_buffer, &indices, program, uniforms, draw_parameters);
+        }
+    }
+
+    /**
+     * Clamped, linearly filtered sampler for reading textures in full screen passes
+     */
+    pub fn get_linear_sampler(texture: &Texture2d) -> Sampler<'_, Texture2d> {
+        return texture.sampled()
+            .wrap_function(SamplerWrapFunction::Clamp)
+            .minify_filter(MinifySamplerFilter::Linear)
+            .magnify_filter(MagnifySamplerFilter::Linear);
+    }
+}
@@ -1,55 +1,78 @@
 
 /*
-Main vertex shader
+Main vertex shader. Every draw is instanced, the per instance attributes place a copy of the mesh within the
+model space, replace its materials unless `instance_material` is -1 and tint its diffuse or base color.
+Meshes drawn without instances are drawn as one instance at the origin.
 */
 pub const VERTEX_SHADER: &str = r#"
     #version 150
 
     in vec3 position;
     in vec3 normal;
+    in vec4 tangent;
 
     in vec2 texture;
     in int material_id;
 
+    in mat4 instance_model;
+    in mat3 instance_normal_matrix;
+    in int instance_material;
+    in vec3 instance_color;
+
     out vec3 v_normal;
+    out vec4 v_tangent;
     out vec3 v_position;
     out vec2 v_texture;
     flat out int i_material;
+    flat out vec3 v_tint;
 
     uniform mat4 perspective;
     uniform mat4 view;
     uniform mat4 model;
+    uniform mat3 normal_matrix;
 
     void main() {
         v_texture = texture;
-        i_material = material_id;
+        i_material = instance_material >= 0 ? instance_material : material_id;
+        v_tint = instance_color;
 
-        mat4 modelview = view * model;
-        
-        gl_Position = perspective * modelview * vec4(position, 1.0);
+        mat4 world = model * instance_model;
+        vec4 world_position = world * vec4(position, 1.0);
 
-        v_position = vec3(model * vec4(position, 1.0));
-        v_normal = transpose(inverse(mat3(model))) * normal;
+        gl_Position = perspective * view * world_position;
+
+        v_position = world_position.xyz;
+        v_normal = normal_matrix * instance_normal_matrix * normal;
+        v_tangent = vec4(mat3(world) * tangent.xyz, tangent.w);
     }
 "#;
 
 /*
-Main fragment shader
+Version line every shader assembled from the chunks below has to start with
 */
-pub const FRAGMENT_SHADER: &str = r#"
+pub const SHADER_VERSION: &str = r#"
     #version 150
+"#;
 
-    struct Material {
-        int diffuse;
-        int specular;
-        float shininess;
-    };
+/**
+ * Concatenates shader chunks into one source
+ */
+pub fn combine(sources: &[&str]) -> String {
+    return sources.concat();
+}
 
+/*
+Light models shared by the forward and the deferred fragment shaders.
+Shading only depends on the `Surface` handed to the calc functions, not on where it was read from.
+*/
+pub const LIGHTING: &str = r#"
     struct DirectionalLight {
         vec3 direction;
         vec3 ambient_color;
         vec3 diffuse_color;
         vec3 specular_color;
+        int shadow_map;
+        float shadow_bias;
     };
 
     struct PointLight {
@@ -60,6 +83,9 @@ pub const FRAGMENT_SHADER: &str = r#"
         vec3 ambient_color;
         vec3 diffuse_color;
         vec3 specular_color;
+        int shadow_map;
+        float shadow_bias;
+        float shadow_far;
     };
 
     struct SpotLight {
@@ -73,135 +99,1621 @@ pub const FRAGMENT_SHADER: &str = r#"
         vec3 ambient_color;
         vec3 diffuse_color;
         vec3 specular_color;
+        int shadow_map;
+        float shadow_bias;
     };
 
-    in vec3 v_normal;
-    in vec3 v_position;
-    in vec2 v_texture;
-    flat in int i_material;
+    // Values of `ShadingModel` in `Material3D.rs`
+    const int SHADING_PHONG = 0;
+    const int SHADING_PBR = 1;
 
-    out vec4 color;
+    // World space position and normal of the shaded point with its material colors already looked up,
+    // `occlusion` scales the ambient light reaching it. PBR surfaces keep their base color in `diffuse`
+    // and use `metallic` and `roughness` instead of `specular` and `shininess`.
+    // `material`, `uv` and `tangent_space` (tangent, bitangent and outward normal) are only known where
+    // the material was looked up, surfaces read back from the G-buffer have `material` -1.
+    struct Surface {
+        vec3 position;
+        vec3 normal;
+        vec3 diffuse;
+        vec3 specular;
+        float shininess;
+        float occlusion;
+        int shading;
+        float metallic;
+        float roughness;
+        int material;
+        vec2 uv;
+        mat3 tangent_space;
+    };
+
+    // Fraction of the light reaching the surface past its own parallax relief, defined with the materials
+    float get_self_shadow(Surface surface, vec3 to_light);
 
-    uniform vec3 u_light;
     uniform vec3 v_view;
 
-    uniform sampler2DArray textures;
-    uniform Material materials[32];
+    // Screen space ambient occlusion of every pixel, see `Ssao3D.rs`
+    uniform sampler2D ambient_occlusion;
+
+    float get_ambient_occlusion() {
+        return texelFetch(ambient_occlusion, ivec2(gl_FragCoord.xy), 0).r;
+    }
+
+    // Image based lighting from an environment map, see `Ibl3D.rs`
+    uniform bool environment_enabled;
+    uniform float environment_intensity;
+    uniform samplerCube irradiance_map;
+    uniform samplerCube prefiltered_map;
+    uniform float prefiltered_max_level;
+    uniform sampler2D brdf_lut;
+
+    // Packed light list, the layout is described in `LightBuffer3D.rs`
+    uniform samplerBuffer lights;
+
+    const int DIRECTIONAL_LIGHT_TEXELS = 4;
+    const int POINT_LIGHT_TEXELS = 5;
+    const int SPOT_LIGHT_TEXELS = 6;
+
+    ivec3 get_light_counts() {
+        return ivec3(texelFetch(lights, 0).xyz);
+    }
+
+    DirectionalLight get_directional_light(int i) {
+        int offset = 1 + i * DIRECTIONAL_LIGHT_TEXELS;
+        vec4 t0 = texelFetch(lights, offset);
+        vec4 t1 = texelFetch(lights, offset + 1);
+        vec4 t2 = texelFetch(lights, offset + 2);
+        vec4 t3 = texelFetch(lights, offset + 3);
+        return DirectionalLight(t0.xyz, t1.xyz, t2.xyz, t3.xyz, int(t0.w), t1.w);
+    }
+
+    PointLight get_point_light(int i) {
+        int offset = 1 + get_light_counts().x * DIRECTIONAL_LIGHT_TEXELS + i * POINT_LIGHT_TEXELS;
+        vec4 t0 = texelFetch(lights, offset);
+        vec4 t1 = texelFetch(lights, offset + 1);
+        vec4 t2 = texelFetch(lights, offset + 2);
+        vec4 t3 = texelFetch(lights, offset + 3);
+        vec4 t4 = texelFetch(lights, offset + 4);
+        return PointLight(t0.xyz, t0.w, t1.w, t2.w, t1.xyz, t2.xyz, t3.xyz, int(t3.w), t4.x, t4.y);
+    }
+
+    SpotLight get_spot_light(int i) {
+        ivec3 counts = get_light_counts();
+        int offset = 1 + counts.x * DIRECTIONAL_LIGHT_TEXELS + counts.y * POINT_LIGHT_TEXELS + i * SPOT_LIGHT_TEXELS;
+        vec4 t0 = texelFetch(lights, offset);
+        vec4 t1 = texelFetch(lights, offset + 1);
+        vec4 t2 = texelFetch(lights, offset + 2);
+        vec4 t3 = texelFetch(lights, offset + 3);
+        vec4 t4 = texelFetch(lights, offset + 4);
+        vec4 t5 = texelFetch(lights, offset + 5);
+        return SpotLight(t0.xyz, t1.xyz, t3.w, t4.w, t0.w, t1.w, t2.w, t2.xyz, t3.xyz, t4.xyz, int(t5.x), t5.y);
+    }
+
+    uniform sampler2DShadow directional_shadow_maps[2];
+    uniform mat4 directional_shadow_matrices[2];
+
+    uniform sampler2DShadow spot_shadow_maps[2];
+    uniform mat4 spot_shadow_matrices[2];
+
+    uniform samplerCubeShadow point_shadow_maps[4];
+
+    // Sample directions for soft point light shadows, spread around the lookup direction
+    const vec3 point_shadow_offsets[20] = vec3[](
+        vec3(1, 1, 1), vec3(1, -1, 1), vec3(-1, -1, 1), vec3(-1, 1, 1),
+        vec3(1, 1, -1), vec3(1, -1, -1), vec3(-1, -1, -1), vec3(-1, 1, -1),
+        vec3(1, 1, 0), vec3(1, -1, 0), vec3(-1, -1, 0), vec3(-1, 1, 0),
+        vec3(1, 0, 1), vec3(-1, 0, 1), vec3(1, 0, -1), vec3(-1, 0, -1),
+        vec3(0, 1, 1), vec3(0, -1, 1), vec3(0, -1, -1), vec3(0, 1, -1)
+    );
+
+    // Fraction of a 3x3 PCF kernel that is lit, positions outside the shadow map are lit
+    float sample_shadow(sampler2DShadow shadow_map, mat4 light_space, vec3 position, float bias) {
+        vec4 light_position = light_space * vec4(position, 1.0);
+        vec3 projected = light_position.xyz / light_position.w * 0.5 + 0.5;
+        if (light_position.w <= 0.0 || projected.z > 1.0 || any(lessThan(projected.xy, vec2(0.0))) || any(greaterThan(projected.xy, vec2(1.0)))) {
+            return 1.0;
+        }
+
+        vec2 texel = 1.0 / vec2(textureSize(shadow_map, 0));
+        float lit = 0.0;
+        for (int x = -1; x <= 1; x++) {
+            for (int y = -1; y <= 1; y++) {
+                lit += texture(shadow_map, vec3(projected.xy + vec2(x, y) * texel, projected.z - bias));
+            }
+        }
+        return lit / 9.0;
+    }
+
+    // Bias grows with the angle between surface and light, where depth changes fastest across a texel
+    float slope_bias(float bias, float n_dot_l) {
+        return bias * clamp(tan(acos(clamp(n_dot_l, 0.0, 1.0))), 1.0, 10.0);
+    }
+
+    // Point shadow maps store the distance to the light divided by `far`, the filter grows softer further from the viewer
+    float sample_point_shadow(samplerCubeShadow shadow_map, vec3 light_to_position, float far, float bias, float view_distance) {
+        float distance = length(light_to_position);
+        float depth = distance / far - bias;
+        if (depth >= 1.0) {
+            return 1.0;
+        }
+
+        float radius = distance * (1.0 + view_distance) * 2.0 / float(textureSize(shadow_map, 0).x);
+        float lit = 0.0;
+        for (int i = 0; i < 20; i++) {
+            lit += texture(shadow_map, vec4(light_to_position + point_shadow_offsets[i] * radius, depth));
+        }
+        return lit / 20.0;
+    }
+
+    // Sampler arrays can only be indexed by constants in GLSL 1.50
+    float directional_shadow(DirectionalLight light, vec3 position, float n_dot_l) {
+        float bias = slope_bias(light.shadow_bias, n_dot_l);
+        if (light.shadow_map == 0) return sample_shadow(directional_shadow_maps[0], directional_shadow_matrices[0], position, bias);
+        if (light.shadow_map == 1) return sample_shadow(directional_shadow_maps[1], directional_shadow_matrices[1], position, bias);
+        return 1.0;
+    }
+
+    float point_shadow(PointLight light, vec3 position, float n_dot_l) {
+        float bias = slope_bias(light.shadow_bias, n_dot_l);
+        vec3 light_to_position = position - light.position;
+        float view_distance = length(v_view - position);
+        if (light.shadow_map == 0) return sample_point_shadow(point_shadow_maps[0], light_to_position, light.shadow_far, bias, view_distance);
+        if (light.shadow_map == 1) return sample_point_shadow(point_shadow_maps[1], light_to_position, light.shadow_far, bias, view_distance);
+        if (light.shadow_map == 2) return sample_point_shadow(point_shadow_maps[2], light_to_position, light.shadow_far, bias, view_distance);
+        if (light.shadow_map == 3) return sample_point_shadow(point_shadow_maps[3], light_to_position, light.shadow_far, bias, view_distance);
+        return 1.0;
+    }
+
+    float spot_shadow(SpotLight light, vec3 position, float n_dot_l) {
+        float bias = slope_bias(light.shadow_bias, n_dot_l);
+        if (light.shadow_map == 0) return sample_shadow(spot_shadow_maps[0], spot_shadow_matrices[0], position, bias);
+        if (light.shadow_map == 1) return sample_shadow(spot_shadow_maps[1], spot_shadow_matrices[1], position, bias);
+        return 1.0;
+    }
+
+    const float PI = 3.14159265;
+
+    // GGX / Trowbridge-Reitz normal distribution
+    float distribution_ggx(float n_dot_h, float roughness) {
+        float a = roughness * roughness;
+        float a2 = a * a;
+        float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+        return a2 / (PI * d * d);
+    }
+
+    // Smith masking and shadowing with the Schlick-GGX approximation for direct light
+    float geometry_smith(float n_dot_v, float n_dot_l, float roughness) {
+        float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+        float g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+        float g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+        return g_v * g_l;
+    }
+
+    vec3 fresnel_schlick(float cos_theta, vec3 f0) {
+        return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+    }
+
+    // Cook-Torrance GGX reflectance of `radiance` arriving from `to_light`, times n·l.
+    // Scaled by PI so a white dielectric gets as bright as a white Phong surface under the same light.
+    vec3 calc_pbr(Surface surface, vec3 to_light, vec3 view_dir, vec3 radiance) {
+        // Normals point into the surface
+        vec3 normal = -surface.normal;
+        vec3 halfway = normalize(view_dir + to_light);
+        float n_dot_l = max(dot(normal, to_light), 0.0);
+        float n_dot_v = max(dot(normal, view_dir), 1e-4);
+        float n_dot_h = max(dot(normal, halfway), 0.0);
+        float roughness = clamp(surface.roughness, 0.04, 1.0);
+
+        vec3 f0 = mix(vec3(0.04), surface.diffuse, surface.metallic);
+        vec3 fresnel = fresnel_schlick(max(dot(halfway, view_dir), 0.0), f0);
+        vec3 specular = distribution_ggx(n_dot_h, roughness) * geometry_smith(n_dot_v, n_dot_l, roughness) * fresnel / (4.0 * n_dot_v * n_dot_l + 1e-4);
+        vec3 k_d = (1.0 - fresnel) * (1.0 - surface.metallic);
+
+        return (k_d * surface.diffuse / PI + specular) * radiance * n_dot_l * PI;
+    }
+
+    // Constant ambient light of a light, the environment replaces it when enabled
+    vec3 calc_light_ambient(vec3 ambient_color, Surface surface) {
+        if (environment_enabled) {
+            return vec3(0.0);
+        }
+        return ambient_color * surface.diffuse * surface.occlusion;
+    }
 
-    uniform int num_directional_lights;
-    uniform DirectionalLight directional_lights[2];
+    vec3 fresnel_schlick_roughness(float cos_theta, vec3 f0, float roughness) {
+        return f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+    }
+
+    // Diffuse and specular light reflected from the environment, added once per pixel.
+    // Phong surfaces reflect their specular color with a roughness matching their shininess.
+    vec3 calc_environment(Surface surface, vec3 view_dir) {
+        if (!environment_enabled) {
+            return vec3(0.0);
+        }
+        vec3 normal = -surface.normal;
+        float n_dot_v = max(dot(normal, view_dir), 1e-4);
+
+        vec3 f0 = surface.specular;
+        vec3 albedo = surface.diffuse;
+        float roughness = sqrt(2.0 / (surface.shininess + 2.0));
+        if (surface.shading == SHADING_PBR) {
+            f0 = mix(vec3(0.04), surface.diffuse, surface.metallic);
+            albedo = surface.diffuse * (1.0 - surface.metallic);
+            roughness = surface.roughness;
+        }
+
+        vec3 fresnel = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+        vec3 diffuse = texture(irradiance_map, normal).rgb * albedo * (1.0 - fresnel);
 
-    uniform int num_point_lights;
-    uniform PointLight point_lights[124];
+        vec3 prefiltered = textureLod(prefiltered_map, reflect(-view_dir, normal), roughness * prefiltered_max_level).rgb;
+        vec2 brdf = texture(brdf_lut, vec2(n_dot_v, roughness)).rg;
+        vec3 specular = prefiltered * (fresnel * brdf.x + brdf.y);
+
+        return (diffuse + specular) * surface.occlusion * environment_intensity;
+    }
 
-    uniform int num_spot_lights;
-    uniform SpotLight spot_lights[2];
+    vec3 calc_dir_light(DirectionalLight light, Surface surface, vec3 view_dir) {
+        if (surface.shading == SHADING_PBR) {
+            vec3 to_light = normalize(-light.direction);
+            float shadow = directional_shadow(light, surface.position, dot(-surface.normal, to_light)) * get_self_shadow(surface, to_light);
+            return calc_light_ambient(light.ambient_color, surface) + calc_pbr(surface, to_light, view_dir, light.diffuse_color) * shadow;
+        }
 
-    vec3 calc_dir_light(DirectionalLight light, vec3 normal, vec3 view_dir) {
         vec3 light_dir = normalize(-light.direction);
 
-        float diff = max(dot(normal, -light_dir), 0.0);
+        float diff = max(dot(surface.normal, -light_dir), 0.0);
         
-        vec3 reflect_dir = reflect(-light_dir, normal);
-        float spec = pow(max(dot(view_dir, reflect_dir), 0.0), materials[i_material].shininess);
+        vec3 reflect_dir = reflect(-light_dir, surface.normal);
+        float spec = pow(max(dot(view_dir, reflect_dir), 0.0), surface.shininess);
+
+        vec3 ambient = calc_light_ambient(light.ambient_color, surface);
+        vec3 diffuse = light.diffuse_color * diff * surface.diffuse;
+        vec3 specular = light.specular_color * spec * surface.specular;
 
-        vec3 ambient = light.ambient_color * vec3(texture(textures, vec3(v_texture, materials[i_material].diffuse)));
-        vec3 diffuse = light.diffuse_color * diff * vec3(texture(textures, vec3(v_texture, materials[i_material].diffuse)));
-        vec3 specular = light.specular_color * spec * vec3(texture(textures, vec3(v_texture, materials[i_material].specular)));
+        float shadow = directional_shadow(light, surface.position, diff) * get_self_shadow(surface, -light_dir);
 
-        return (ambient + diffuse + specular);
+        return (ambient + (diffuse + specular)*shadow);
     }
 
-    vec3 calc_point_light(PointLight light, vec3 normal, vec3 position, vec3 view_dir) {
-        float distance = length(light.position - position);
+    vec3 calc_point_light(PointLight light, Surface surface, vec3 view_dir) {
+        float distance = length(light.position - surface.position);
         float attenuation = 1.0 / (light.constant + light.linear * distance + light.quadratic * (distance * distance));
 
-        vec3 light_dir = -normalize(light.position - position);
+        if (surface.shading == SHADING_PBR) {
+            vec3 to_light = normalize(light.position - surface.position);
+            float shadow = point_shadow(light, surface.position, dot(-surface.normal, to_light)) * get_self_shadow(surface, to_light);
+            return (calc_light_ambient(light.ambient_color, surface) + calc_pbr(surface, to_light, view_dir, light.diffuse_color) * shadow) * attenuation;
+        }
+
+        vec3 light_dir = -normalize(light.position - surface.position);
 
-        float diff = max(dot(normal, light_dir), 0.0);
+        float diff = max(dot(surface.normal, light_dir), 0.0);
         
-        vec3 reflect_dir = reflect(light_dir, normal);
-        float spec = pow(max(dot(view_dir, reflect_dir), 0.0), materials[i_material].shininess);
+        vec3 reflect_dir = reflect(light_dir, surface.normal);
+        float spec = pow(max(dot(view_dir, reflect_dir), 0.0), surface.shininess);
 
-        vec3 ambient = light.ambient_color * vec3(texture(textures, vec3(v_texture, materials[i_material].diffuse)));
-        vec3 diffuse = light.diffuse_color * diff * vec3(texture(textures, vec3(v_texture, materials[i_material].diffuse)));
-        vec3 specular = light.specular_color * spec * vec3(texture(textures, vec3(v_texture, materials[i_material].specular)));
+        vec3 ambient = calc_light_ambient(light.ambient_color, surface);
+        vec3 diffuse = light.diffuse_color * diff * surface.diffuse;
+        vec3 specular = light.specular_color * spec * surface.specular;
 
-        return (ambient + diffuse + specular)*attenuation;
+        float shadow = point_shadow(light, surface.position, diff) * get_self_shadow(surface, -light_dir);
+
+        return (ambient + (diffuse + specular)*shadow)*attenuation;
     }
 
-    vec3 calc_spot_light(SpotLight light, vec3 normal, vec3 position, vec3 view_dir) {
-        float distance = length(light.position - position);
+    vec3 calc_spot_light(SpotLight light, Surface surface, vec3 view_dir) {
+        float distance = length(light.position - surface.position);
         float attenuation = 1.0 / (light.constant + light.linear * distance + light.quadratic * (distance * distance));
         
         // Compute light direction
-        vec3 light_dir = -normalize(light.position - position);
-
-        float diff = max(dot(normal, light_dir), 0.0);
+        vec3 light_dir = -normalize(light.position - surface.position);
 
-        //vec3 reflect_dir = reflect(light_dir, normal);
-        vec3 reflect_dir = reflect(light.direction, normal);
-        float spec = pow(max(dot(view_dir, reflect_dir), 0.0), materials[i_material].shininess);
-    
         float theta = dot(-light_dir, normalize(-light.direction));
         float epsilon = light.cutoff - light.outer_cutoff;
         //float intensity = clamp((theta - light.outer_cutoff) / epsilon, 0.0, 1.0);
         float intensity = smoothstep(0.0, 1.0, (theta - light.outer_cutoff) / epsilon);
 
+        if (surface.shading == SHADING_PBR) {
+            float shadow = spot_shadow(light, surface.position, dot(surface.normal, light_dir)) * get_self_shadow(surface, -light_dir);
+            return (calc_light_ambient(light.ambient_color, surface) + calc_pbr(surface, -light_dir, view_dir, light.diffuse_color) * shadow) * attenuation * intensity;
+        }
+
+        float diff = max(dot(surface.normal, light_dir), 0.0);
+
+        //vec3 reflect_dir = reflect(light_dir, normal);
+        vec3 reflect_dir = reflect(light.direction, surface.normal);
+        float spec = pow(max(dot(view_dir, reflect_dir), 0.0), surface.shininess);
+
         // Combine
-        vec3 ambient = light.ambient_color * vec3(texture(textures, vec3(v_texture, materials[i_material].diffuse)));
-        vec3 diffuse = light.diffuse_color * diff * vec3(texture(textures, vec3(v_texture, materials[i_material].diffuse)));
-        vec3 specular = light.specular_color * spec * vec3(texture(textures, vec3(v_texture, materials[i_material].specular)));
+        vec3 ambient = calc_light_ambient(light.ambient_color, surface);
+        vec3 diffuse = light.diffuse_color * diff * surface.diffuse;
+        vec3 specular = light.specular_color * spec * surface.specular;
+
+        float shadow = spot_shadow(light, surface.position, diff) * get_self_shadow(surface, -light_dir);
         
-        return (ambient + diffuse + specular)*attenuation*intensity;
+        return (ambient + (diffuse + specular)*shadow)*attenuation*intensity;
         //return specular*attenuation*intensity;
     }
+"#;
 
-    void main() {
+/*
+Material lookup shared by the forward and the G-buffer fragment shaders, combined after `LIGHTING`.
+The fields match `Material3D.rs`, texture ids below 0 are unused maps. Color maps are read from the sRGB
+`textures` array and the normal, height, metallic, roughness and occlusion maps from the linear `data_textures`.
+*/
+pub const MATERIALS: &str = r#"
+    struct Material {
+        int shading;
+        int diffuse;
+        int specular;
+        float shininess;
+        int base_color;
+        vec3 base_color_factor;
+        int metallic;
+        float metallic_factor;
+        int roughness;
+        float roughness_factor;
+        int occlusion;
+        float occlusion_strength;
+        int emissive;
+        vec3 emissive_factor;
+        int normal;
+        float normal_scale;
+        int height;
+        float height_scale;
+        bool parallax_shadows;
+        int alpha_mode;
+        float opacity;
+        float alpha_cutoff;
+    };
+
+    // Values of `AlphaMode` in `Material3D.rs`
+    const int ALPHA_OPAQUE = 0;
+    const int ALPHA_MASK = 1;
+    const int ALPHA_BLEND = 2;
+
+    // Packed material array, the layout is described in `MaterialBuffer3D.rs`
+    uniform samplerBuffer materials;
+
+    const int MATERIAL_TEXELS = 7;
+
+    Material get_material(int id) {
+        int offset = id * MATERIAL_TEXELS;
+        vec4 t0 = texelFetch(materials, offset);
+        vec4 t1 = texelFetch(materials, offset + 1);
+        vec4 t2 = texelFetch(materials, offset + 2);
+        vec4 t3 = texelFetch(materials, offset + 3);
+        vec4 t4 = texelFetch(materials, offset + 4);
+        vec4 t5 = texelFetch(materials, offset + 5);
+        vec4 t6 = texelFetch(materials, offset + 6);
+        return Material(int(t0.w), int(t2.x), int(t2.y), t2.z, int(t2.w), t0.xyz, int(t3.x), t3.y, int(t3.z), t3.w,
+            int(t4.x), t4.y, int(t4.z), t1.xyz, int(t5.x), t5.y, int(t5.z), t5.w, t6.x > 0.5, int(t1.w), t4.w, t6.y);
+    }
+
+    uniform sampler2DArray textures;
+    uniform sampler2DArray data_textures;
+
+    vec3 sample_color(int id, vec2 uv) {
+        return texture(textures, vec3(uv, id)).rgb;
+    }
+
+    vec3 sample_data(int id, vec2 uv) {
+        return texture(data_textures, vec3(uv, id)).rgb;
+    }
+
+    // Tangent, bitangent and outward normal. The normals of the meshes point into the surface,
+    // the bitangent is rebuilt from the handedness in `tangent.w` like MikkTSpace expects.
+    mat3 get_tangent_space(vec3 normal, vec4 tangent) {
+        vec3 t = normalize(tangent.xyz - normal * dot(normal, tangent.xyz));
+        vec3 b = tangent.w * cross(normal, t);
+        return mat3(t, b, -normal);
+    }
+
+    // Tangent space normal maps point out of the surface
+    vec3 apply_normal_map(Material material, mat3 tangent_space, vec2 uv) {
+        vec3 mapped = sample_data(material.normal, uv) * 2.0 - 1.0;
+        mapped.xy *= material.normal_scale;
+        return -normalize(tangent_space * mapped);
+    }
+
+    // Parallax occlusion mapping, the layer count grows at grazing angles
+    const float PARALLAX_MIN_LAYERS = 8.0;
+    const float PARALLAX_MAX_LAYERS = 32.0;
+    const int PARALLAX_REFINEMENT_STEPS = 5;
+    // Depth of relief above a ray towards a light that shadows it completely
+    const float PARALLAX_SHADOW_SOFTNESS = 0.1;
+
+    // Depth below the surface, 1 - height. Sampled with explicit gradients as implicit ones are undefined in loops.
+    float sample_depth(int id, vec2 uv, vec2 dx, vec2 dy) {
+        return 1.0 - textureGrad(data_textures, vec3(uv, id), dx, dy).r;
+    }
+
+    // Texture coordinates where the view ray entering the surface at `uv` hits the relief. Steep parallax mapping
+    // steps the ray through depth layers until it is below the height map, relief mapping then refines the hit
+    // with a binary search between the last two layers.
+    vec2 get_parallax_uv(Material material, vec2 uv, vec3 view_ts, vec2 dx, vec2 dy) {
+        float layers = mix(PARALLAX_MAX_LAYERS, PARALLAX_MIN_LAYERS, view_ts.z);
+        float layer_depth = 1.0 / layers;
+        vec2 delta = view_ts.xy / max(view_ts.z, 0.05) * material.height_scale / layers;
+
+        float ray_depth = 0.0;
+        float depth = sample_depth(material.height, uv, dx, dy);
+        for (int i = 0; i < int(PARALLAX_MAX_LAYERS) && ray_depth < depth; i++) {
+            uv -= delta;
+            ray_depth += layer_depth;
+            depth = sample_depth(material.height, uv, dx, dy);
+        }
+
+        for (int i = 0; i < PARALLAX_REFINEMENT_STEPS; i++) {
+            delta *= 0.5;
+            layer_depth *= 0.5;
+            if (ray_depth < sample_depth(material.height, uv, dx, dy)) {
+                uv -= delta;
+                ray_depth += layer_depth;
+            } else {
+                uv += delta;
+                ray_depth -= layer_depth;
+            }
+        }
+        return uv;
+    }
+
+    // Steps from the hit point up to the surface towards the light, relief above the ray shadows softly
+    float get_self_shadow(Surface surface, vec3 to_light) {
+        if (surface.material < 0) return 1.0;
+        Material material = get_material(surface.material);
+        vec3 light_ts = to_light * surface.tangent_space;
+        if (material.height < 0 || !material.parallax_shadows || light_ts.z <= 0.0) return 1.0;
+
+        float start_depth = sample_depth(material.height, surface.uv, vec2(0.0), vec2(0.0));
+        float layers = mix(PARALLAX_MAX_LAYERS, PARALLAX_MIN_LAYERS, light_ts.z);
+        float layer_depth = start_depth / layers;
+        vec2 delta = light_ts.xy / max(light_ts.z, 0.05) * material.height_scale * layer_depth;
+
+        float occlusion = 0.0;
+        for (int i = 1; i < int(layers); i++) {
+            float ray_depth = start_depth - float(i) * layer_depth;
+            float depth = sample_depth(material.height, surface.uv + delta * float(i), vec2(0.0), vec2(0.0));
+            occlusion = max(occlusion, (ray_depth - depth) * (1.0 - float(i) / layers));
+        }
+        return 1.0 - clamp(occlusion / PARALLAX_SHADOW_SOFTNESS, 0.0, 1.0);
+    }
+
+    // Surface of a material at a point, leaving the screen space ambient occlusion out.
+    // Maps are read at `surface.uv`, which parallax mapping moves away from `uv`.
+    // `tint` multiplies the diffuse or base color, it is the color of the instance.
+    Surface get_material_surface(int id, vec3 position, vec3 normal, vec4 tangent, vec2 uv, vec3 tint) {
+        Material material = get_material(id);
+        Surface surface;
+        surface.position = position;
+        surface.normal = normalize(normal);
+        surface.material = id;
+        surface.tangent_space = get_tangent_space(surface.normal, tangent);
+
+        // Derivatives are taken before any branch depending on the material
+        vec2 dx = dFdx(uv);
+        vec2 dy = dFdy(uv);
+        vec3 view_ts = normalize(v_view - position) * surface.tangent_space;
+        if (material.height >= 0 && view_ts.z > 0.0) {
+            uv = get_parallax_uv(material, uv, view_ts, dx, dy);
+        }
+        surface.uv = uv;
+
+        if (material.normal >= 0) surface.normal = apply_normal_map(material, surface.tangent_space, uv);
+        surface.shading = material.shading;
+        surface.occlusion = 1.0;
+        surface.metallic = 0.0;
+        surface.roughness = 1.0;
+
+        if (material.shading == SHADING_PBR) {
+            surface.diffuse = material.base_color_factor;
+            if (material.base_color >= 0) surface.diffuse *= sample_color(material.base_color, uv);
+            surface.specular = vec3(0.0);
+            surface.shininess = 1.0;
+            surface.metallic = material.metallic_factor;
+            if (material.metallic >= 0) surface.metallic *= sample_data(material.metallic, uv).b;
+            surface.roughness = material.roughness_factor;
+            if (material.roughness >= 0) surface.roughness *= sample_data(material.roughness, uv).g;
+            if (material.occlusion >= 0) surface.occlusion = mix(1.0, sample_data(material.occlusion, uv).r, material.occlusion_strength);
+        } else {
+            surface.diffuse = sample_color(material.diffuse, uv);
+            surface.specular = sample_color(material.specular, uv);
+            surface.shininess = material.shininess;
+        }
+        surface.diffuse *= tint;
+        return surface;
+    }
+
+    vec3 get_material_emissive(int id, vec2 uv) {
+        Material material = get_material(id);
+        vec3 emissive = material.emissive_factor;
+        if (material.emissive >= 0) emissive *= sample_color(material.emissive, uv);
+        return emissive;
+    }
+
+    // Opacity times the alpha of the diffuse or base color texture, opaque materials ignore both
+    float get_material_alpha(int id, vec2 uv) {
+        Material material = get_material(id);
+        if (material.alpha_mode == ALPHA_OPAQUE) return 1.0;
+        int color = material.shading == SHADING_PBR ? material.base_color : material.diffuse;
+        float alpha = material.opacity;
+        if (color >= 0) alpha *= texture(textures, vec3(uv, color)).a;
+        return alpha;
+    }
+
+    // Whether a fragment belongs to the opaque passes, blended materials are drawn in the transparent pass
+    // and masked ones are cut out below their cutoff
+    bool is_opaque_fragment(int id, vec2 uv) {
+        Material material = get_material(id);
+        return material.alpha_mode != ALPHA_BLEND && get_material_alpha(id, uv) >= material.alpha_cutoff;
+    }
+"#;
+
+/*
+Lighting of a surface by all lights for the forward fragment shaders, combined after `LIGHTING` and `MATERIALS`
+*/
+pub const FORWARD: &str = r#"
+    in vec3 v_normal;
+    in vec4 v_tangent;
+    in vec3 v_position;
+    in vec2 v_texture;
+    flat in int i_material;
+    flat in vec3 v_tint;
+
+    uniform vec3 u_light;
+
+    // Point light clusters, see `Cluster3D.rs`
+    uniform usamplerBuffer cluster_grid;
+    uniform usamplerBuffer cluster_lights;
+    uniform ivec3 cluster_dimensions;
+    uniform vec2 cluster_depth_range;
+
+    uniform mat4 view;
+    uniform mat4 perspective;
+
+    int get_cluster(vec3 position) {
+        vec4 view_position = view * vec4(position, 1.0);
+        vec4 clip_position = perspective * view_position;
+        vec2 ndc = clip_position.xy / clip_position.w;
+        ivec2 tile = clamp(ivec2((ndc * 0.5 + 0.5) * vec2(cluster_dimensions.xy)), ivec2(0), cluster_dimensions.xy - 1);
+
+        float depth = max(view_position.z, cluster_depth_range.x);
+        int slice = int(floor(log(depth / cluster_depth_range.x) / log(cluster_depth_range.y / cluster_depth_range.x) * float(cluster_dimensions.z)));
+        slice = clamp(slice, 0, cluster_dimensions.z - 1);
+
+        return (slice * cluster_dimensions.y + tile.y) * cluster_dimensions.x + tile.x;
+    }
+
+    vec3 get_lit_color(Surface surface) {
         // Define accumulator vector to "accumulate" resulting color
         vec3 res_color = vec3(0.0, 0.0, 0.0);
 
-        // Normalize normal and compute normalized view-direction vector
-        vec3 norm = normalize(v_normal);
-        vec3 view_dir = normalize(v_view - v_position);
+        // Compute normalized view-direction vector
+        vec3 view_dir = normalize(v_view - surface.position);
+
+        ivec3 light_counts = get_light_counts();
 
         // Compute directional lights impact
-        for (int i = 0; i < num_directional_lights; i++) {
-            res_color += calc_dir_light(directional_lights[i], norm, view_dir);
+        for (int i = 0; i < light_counts.x; i++) {
+            res_color += calc_dir_light(get_directional_light(i), surface, view_dir);
         }
 
-        // Compute point lights impact
-        for (int i = 0; i < num_point_lights; i++) {
-            res_color += calc_point_light(point_lights[i], norm, v_position, view_dir);
+        // Compute point lights impact, only for the lights reaching this fragment's cluster
+        uvec2 cluster = texelFetch(cluster_grid, get_cluster(surface.position)).xy;
+        for (int i = 0; i < int(cluster.y); i++) {
+            int light = int(texelFetch(cluster_lights, int(cluster.x) + i).x);
+            res_color += calc_point_light(get_point_light(light), surface, view_dir);
         }
 
         // Compute spot lights impact
-        for (int i = 0; i < num_spot_lights; i++) {
-            res_color += calc_spot_light(spot_lights[i], norm, v_position, view_dir);
+        for (int i = 0; i < light_counts.z; i++) {
+            res_color += calc_spot_light(get_spot_light(i), surface, view_dir);
         }
 
+        res_color += calc_environment(surface, view_dir);
+        res_color += get_material_emissive(surface.material, surface.uv);
+        return res_color;
+    }
+"#;
+
+/*
+Main fragment shader for opaque and masked materials, combined after `FORWARD`
+*/
+pub const FRAGMENT_SHADER: &str = r#"
+    out vec4 color;
+
+    void main() {
+        // Look up the material, fragments of the transparent pass and cut out ones are left out
+        Surface surface = get_material_surface(i_material, v_position, v_normal, v_tangent, v_texture, v_tint);
+        if (!is_opaque_fragment(i_material, surface.uv)) discard;
+        surface.occlusion *= get_ambient_occlusion();
+
         // Return resulting color
-        color = vec4(res_color, 1.0);
+        color = vec4(get_lit_color(surface), 1.0);
+    }
+"#;
+
+/*
+Fragment shader of blended materials, drawn back to front over the scene and combined after `FORWARD`.
+The ambient occlusion of the screen belongs to the opaque surfaces behind and is left out.
+*/
+pub const TRANSPARENT_FRAGMENT_SHADER: &str = r#"
+    out vec4 color;
+
+    void main() {
+        if (get_material(i_material).alpha_mode != ALPHA_BLEND) discard;
+        Surface surface = get_material_surface(i_material, v_position, v_normal, v_tangent, v_texture, v_tint);
+        color = vec4(get_lit_color(surface), get_material_alpha(i_material, surface.uv));
     }
+"#;
 
-    
+/*
+Weighted blended order independent transparency, see `Transparency3D.rs`. Combined after `FORWARD`.
+Every layer adds its premultiplied color and opacity with a weight falling off with the distance
+to the camera, so nearer layers dominate without sorting. The alpha channel of `accumulation`
+multiplies up the revealage, the fraction of the background still visible through all layers.
+*/
+pub const WEIGHTED_TRANSPARENT_FRAGMENT_SHADER: &str = r#"
+    out vec4 accumulation;
+    out vec4 weight;
+
+    void main() {
+        if (get_material(i_material).alpha_mode != ALPHA_BLEND) discard;
+        Surface surface = get_material_surface(i_material, v_position, v_normal, v_tangent, v_texture, v_tint);
+        float alpha = get_material_alpha(i_material, surface.uv);
+
+        // Weight function of McGuire and Bavoil, equation (7)
+        float depth = abs((view * vec4(v_position, 1.0)).z);
+        float w = alpha * clamp(10.0 / (1e-5 + pow(depth / 5.0, 2.0) + pow(depth / 200.0, 6.0)), 1e-2, 3e3);
+
+        accumulation = vec4(get_lit_color(surface) * alpha * w, alpha);
+        weight = vec4(alpha * w);
+    }
 "#;
 
 /*
-Fragment shader for rendering light cubes
+Resolves the weighted blended transparency over the scene, blended with the source alpha
 */
-pub const FRAGMENT_SHADER_LIGHT: &str = r#"
+pub const WEIGHTED_COMPOSITE_FRAGMENT_SHADER: &str = r#"
     #version 150
 
-    in vec3 v_normal;
-    in vec3 v_position;
+    out vec4 color;
+
+    uniform sampler2D accumulation;
+    uniform sampler2D weight;
+
+    void main() {
+        ivec2 pixel = ivec2(gl_FragCoord.xy);
+        vec4 accumulated = texelFetch(accumulation, pixel, 0);
+        float revealage = accumulated.a;
+        if (revealage >= 1.0) discard;
+        color = vec4(accumulated.rgb / max(texelFetch(weight, pixel, 0).r, 1e-5), 1.0 - revealage);
+    }
+"#;
+
+/*
+Fragment shader of unlit meshes, which only show the emissive color of their materials.
+Combined after `LIGHTING` and `MATERIALS`.
+*/
+pub const FRAGMENT_SHADER_LIGHT: &str = r#"
     in vec2 v_texture;
+    flat in int i_material;
+
+    out vec4 color;
+
+    void main() {
+        color = vec4(get_material_emissive(i_material, v_texture), 1.0);
+    }
+"#;
+
+/*
+Markers of lights, a cube per light instance in the color of its light, see `Gizmo3D.rs`
+*/
+pub const LIGHT_GIZMO_VERTEX_SHADER: &str = r#"
+    #version 150
+
+    in vec3 position;
+
+    in vec3 gizmo_position;
+    in vec3 gizmo_color;
+
+    flat out vec3 v_color;
+
+    uniform mat4 view;
+    uniform mat4 perspective;
+    uniform float size;
+
+    void main() {
+        v_color = gizmo_color;
+        gl_Position = perspective * view * vec4(gizmo_position + position * size, 1.0);
+    }
+"#;
+
+pub const LIGHT_GIZMO_FRAGMENT_SHADER: &str = r#"
+    #version 150
+
+    flat in vec3 v_color;
 
     out vec4 color;
 
-    const vec3 v_color = vec3(1.0, 1.0, 1.0);
+    // Brighter than the light color so light sources bloom after tone mapping
+    uniform float brightness;
+
+    void main() {
+        color = vec4(v_color * brightness, 1.0);
+    }
+"#;
+
+/*
+Depth only shaders for rendering shadow maps
+*/
+pub const SHADOW_VERTEX_SHADER: &str = r#"
+    #version 150
+
+    in vec3 position;
+    in mat4 instance_model;
+
+    uniform mat4 light_space;
+    uniform mat4 model;
+
+    void main() {
+        gl_Position = light_space * model * instance_model * vec4(position, 1.0);
+    }
+"#;
+
+pub const SHADOW_FRAGMENT_SHADER: &str = r#"
+    #version 150
 
     void main() {
-        color = vec4(v_color, 1.0);
     }
-"#;
\ No newline at end of file
+"#;
+
+/*
+Shaders for rendering point light shadow cube maps, storing linear distance to the light
+*/
+pub const POINT_SHADOW_VERTEX_SHADER: &str = r#"
+    #version 150
+
+    in vec3 position;
+    in mat4 instance_model;
+
+    out vec3 v_position;
+
+    uniform mat4 light_space;
+    uniform mat4 model;
+
+    void main() {
+        vec4 world_position = model * instance_model * vec4(position, 1.0);
+        v_position = world_position.xyz;
+        gl_Position = light_space * world_position;
+    }
+"#;
+
+pub const POINT_SHADOW_FRAGMENT_SHADER: &str = r#"
+    #version 150
+
+    in vec3 v_position;
+
+    uniform vec3 light_position;
+    uniform float far;
+
+    void main() {
+        gl_FragDepth = length(v_position - light_position) / far;
+    }
+"#;
+
+/*
+Vertex shader for full screen passes, drawn with the quad of `Screen3D.rs`
+*/
+pub const SCREEN_VERTEX_SHADER: &str = r#"
+    #version 150
+
+    in vec2 position;
+
+    out vec2 v_texture;
+
+    void main() {
+        v_texture = position * 0.5 + 0.5;
+        gl_Position = vec4(position, 0.0, 1.0);
+    }
+"#;
+
+/*
+Copies a texture, with linear filtering also used to halve textures
+*/
+pub const COPY_FRAGMENT_SHADER: &str = r#"
+    #version 150
+
+    in vec2 v_texture;
+
+    out vec4 color;
+
+    uniform sampler2D source;
+
+    void main() {
+        color = vec4(texture(source, v_texture).rgb, 1.0);
+    }
+"#;
+
+/*
+Deferred geometry pass, writes the surface of every pixel into the G-buffer, the layout is described in `Deferred3D.rs`.
+Combined with `LIGHTING` and `MATERIALS`.
+*/
+pub const GBUFFER_FRAGMENT_SHADER: &str = r#"
+    in vec3 v_normal;
+    in vec4 v_tangent;
+    in vec3 v_position;
+    in vec2 v_texture;
+    flat in int i_material;
+    flat in vec3 v_tint;
+
+    out vec4 g_position;
+    out vec4 g_normal;
+    out vec4 g_albedo;
+    out vec4 g_specular;
+    out vec4 g_emissive;
+
+    void main() {
+        // Blended materials are drawn forward after lighting
+        Surface surface = get_material_surface(i_material, v_position, v_normal, v_tangent, v_texture, v_tint);
+        if (!is_opaque_fragment(i_material, surface.uv)) discard;
+        g_position = vec4(v_position, 1.0);
+        g_normal = vec4(surface.normal, surface.shininess);
+        g_albedo = vec4(surface.diffuse, float(surface.shading));
+        if (surface.shading == SHADING_PBR) {
+            g_specular = vec4(surface.metallic, surface.roughness, surface.occlusion, float(i_material));
+        } else {
+            g_specular = vec4(surface.specular, float(i_material));
+        }
+        g_emissive = vec4(get_material_emissive(i_material, surface.uv), 1.0);
+    }
+"#;
+
+/*
+G-buffer lookup for the deferred lighting passes, combined after `LIGHTING`
+*/
+pub const GBUFFER: &str = r#"
+    uniform sampler2D g_position;
+    uniform sampler2D g_normal;
+    uniform sampler2D g_albedo;
+    uniform sampler2D g_specular;
+
+    out vec4 color;
+
+    // False for pixels no geometry was drawn to
+    bool get_surface(ivec2 pixel, out Surface surface) {
+        vec4 position = texelFetch(g_position, pixel, 0);
+        vec4 normal = texelFetch(g_normal, pixel, 0);
+        vec4 albedo = texelFetch(g_albedo, pixel, 0);
+        vec4 specular = texelFetch(g_specular, pixel, 0);
+        surface = Surface(
+            position.xyz,
+            normal.xyz,
+            albedo.rgb,
+            specular.rgb,
+            normal.w,
+            get_ambient_occlusion(),
+            int(albedo.a + 0.5),
+            0.0,
+            1.0,
+            -1,
+            vec2(0.0),
+            mat3(1.0)
+        );
+        if (surface.shading == SHADING_PBR) {
+            surface.specular = vec3(0.0);
+            surface.metallic = specular.r;
+            surface.roughness = specular.g;
+            surface.occlusion *= specular.b;
+        }
+        return position.w != 0.0;
+    }
+
+    // The G-buffer has no texture coordinates to trace parallax shadows with
+    float get_self_shadow(Surface surface, vec3 to_light) {
+        return 1.0;
+    }
+"#;
+
+/*
+Deferred lighting of the directional and spot lights, drawn over the whole screen
+*/
+pub const DEFERRED_FRAGMENT_SHADER: &str = r#"
+    void main() {
+        Surface surface;
+        if (!get_surface(ivec2(gl_FragCoord.xy), surface)) {
+            discard;
+        }
+        vec3 view_dir = normalize(v_view - surface.position);
+
+        vec3 res_color = vec3(0.0, 0.0, 0.0);
+        ivec3 light_counts = get_light_counts();
+        for (int i = 0; i < light_counts.x; i++) {
+            res_color += calc_dir_light(get_directional_light(i), surface, view_dir);
+        }
+        for (int i = 0; i < light_counts.z; i++) {
+            res_color += calc_spot_light(get_spot_light(i), surface, view_dir);
+        }
+        res_color += calc_environment(surface, view_dir);
+
+        color = vec4(res_color, 1.0);
+    }
+"#;
+
+/*
+Deferred point light volumes, a sphere around every point light scaled to its range and drawn instanced
+*/
+pub const LIGHT_VOLUME_VERTEX_SHADER: &str = r#"
+    #version 150
+
+    in vec3 position;
+
+    in vec3 light_position;
+    in float light_radius;
+    in int light_index;
+
+    flat out int v_light_index;
+
+    uniform mat4 view;
+    uniform mat4 perspective;
+
+    void main() {
+        v_light_index = light_index;
+        gl_Position = perspective * view * vec4(light_position + position * light_radius, 1.0);
+    }
+"#;
+
+pub const LIGHT_VOLUME_FRAGMENT_SHADER: &str = r#"
+    flat in int v_light_index;
+
+    void main() {
+        Surface surface;
+        if (!get_surface(ivec2(gl_FragCoord.xy), surface)) {
+            discard;
+        }
+        vec3 view_dir = normalize(v_view - surface.position);
+
+        color = vec4(calc_point_light(get_point_light(v_light_index), surface, view_dir), 1.0);
+    }
+"#;
+
+/*
+SSAO prepass of the forward path, writes the position and normal targets laid out like the G-buffer
+*/
+pub const SSAO_PREPASS_FRAGMENT_SHADER: &str = r#"
+    #version 150
+
+    in vec3 v_normal;
+    in vec3 v_position;
+
+    out vec4 g_position;
+    out vec4 g_normal;
+
+    void main() {
+        g_position = vec4(v_position, 1.0);
+        g_normal = vec4(normalize(v_normal), 0.0);
+    }
+"#;
+
+/*
+Screen space ambient occlusion, the fraction of a hemisphere of samples around every pixel that is not
+hidden behind the scene. Samples are rotated by a tiled noise texture, which the blur removes again.
+*/
+pub const SSAO_FRAGMENT_SHADER: &str = r#"
+    #version 150
+
+    out vec4 color;
+
+    uniform sampler2D g_position;
+    uniform sampler2D g_normal;
+    uniform sampler2D noise;
+
+    uniform vec3 samples[64];
+    uniform int sample_count;
+    uniform float radius;
+    uniform float bias;
+    uniform float strength;
+
+    uniform mat4 view;
+    uniform mat4 perspective;
+
+    void main() {
+        ivec2 pixel = ivec2(gl_FragCoord.xy);
+        vec4 world_position = texelFetch(g_position, pixel, 0);
+        if (world_position.w == 0.0) {
+            color = vec4(1.0);
+            return;
+        }
+
+        // Work in view space, normals point into the surface so the hemisphere is turned around
+        vec3 position = (view * vec4(world_position.xyz, 1.0)).xyz;
+        vec3 normal = -normalize(mat3(view) * texelFetch(g_normal, pixel, 0).xyz);
+
+        vec3 random = texelFetch(noise, pixel % textureSize(noise, 0), 0).xyz;
+        vec3 tangent = normalize(random - normal * dot(random, normal));
+        vec3 bitangent = cross(normal, tangent);
+        mat3 tbn = mat3(tangent, bitangent, normal);
+
+        vec2 size = vec2(textureSize(g_position, 0));
+        float occlusion = 0.0;
+        for (int i = 0; i < sample_count; i++) {
+            vec3 sample_position = position + tbn * samples[i] * radius;
+            vec4 clip_position = perspective * vec4(sample_position, 1.0);
+            vec2 uv = clip_position.xy / clip_position.w * 0.5 + 0.5;
+            if (any(lessThan(uv, vec2(0.0))) || any(greaterThanEqual(uv, vec2(1.0)))) {
+                continue;
+            }
+
+            vec4 scene_position = texelFetch(g_position, ivec2(uv * size), 0);
+            if (scene_position.w == 0.0) {
+                continue;
+            }
+            float scene_depth = (view * vec4(scene_position.xyz, 1.0)).z;
+
+            // Surfaces far in front of the pixel do not occlude it
+            float range = smoothstep(0.0, 1.0, radius / abs(position.z - scene_depth));
+            occlusion += (scene_depth <= sample_position.z - bias ? 1.0 : 0.0) * range;
+        }
+
+        float visibility = 1.0 - occlusion / float(max(sample_count, 1));
+        color = vec4(vec3(pow(visibility, strength)), 1.0);
+    }
+"#;
+
+/*
+Box blur over the size of the SSAO noise texture
+*/
+pub const SSAO_BLUR_FRAGMENT_SHADER: &str = r#"
+    #version 150
+
+    out vec4 color;
+
+    uniform sampler2D occlusion;
+
+    void main() {
+        ivec2 pixel = ivec2(gl_FragCoord.xy);
+        ivec2 size = textureSize(occlusion, 0);
+        float result = 0.0;
+        for (int x = -2; x < 2; x++) {
+            for (int y = -2; y < 2; y++) {
+                result += texelFetch(occlusion, clamp(pixel + ivec2(x, y), ivec2(0), size - 1), 0).r;
+            }
+        }
+        color = vec4(vec3(result / 16.0), 1.0);
+    }
+"#;
+
+/*
+Copies a face image into a cube map face. Cube map faces are stored top row first,
+images are uploaded bottom row first, so the image is flipped.
+*/
+pub const CUBEMAP_FACE_FRAGMENT_SHADER: &str = r#"
+    #version 150
+
+    in vec2 v_texture;
+
+    out vec4 color;
+
+    uniform sampler2D face;
+
+    void main() {
+        color = vec4(texture(face, vec2(v_texture.x, 1.0 - v_texture.y)).rgb, 1.0);
+    }
+"#;
+
+/*
+Sky cube around the camera, drawn with the rotation of the view only
+*/
+pub const SKYBOX_VERTEX_SHADER: &str = r#"
+    #version 150
+
+    in vec3 position;
+
+    out vec3 v_direction;
+
+    uniform mat4 perspective;
+    uniform mat4 view;
+
+    void main() {
+        v_direction = position;
+        // z = w puts the sky on the far plane, behind everything in the depth buffer
+        gl_Position = (perspective * view * vec4(position, 1.0)).xyww;
+    }
+"#;
+
+pub const SKYBOX_FRAGMENT_SHADER: &str = r#"
+    #version 150
+
+    in vec3 v_direction;
+
+    out vec4 color;
+
+    uniform samplerCube skybox;
+    uniform float intensity;
+
+    void main() {
+        color = vec4(texture(skybox, v_direction).rgb * intensity, 1.0);
+    }
+"#;
+
+/*
+Shared by the environment baking passes, drawn with the screen quad. Cube maps are drawn face by face,
+the face vectors map the face to directions, see `CUBE_FACES` in `Ibl3D.rs`.
+*/
+pub const ENVIRONMENT_SAMPLING: &str = r#"
+    in vec2 v_texture;
+
+    out vec4 color;
+
+    uniform vec3 face_forward;
+    uniform vec3 face_right;
+    uniform vec3 face_up;
+
+    const float PI = 3.14159265;
+
+    vec3 get_face_direction() {
+        vec2 st = v_texture * 2.0 - 1.0;
+        return normalize(face_forward + st.x * face_right + st.y * face_up);
+    }
+
+    // Low discrepancy sample points in the unit square
+    vec2 hammersley(uint i, uint count) {
+        uint bits = i;
+        bits = (bits << 16u) | (bits >> 16u);
+        bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+        bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+        bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+        bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+        return vec2(float(i) / float(count), float(bits) * 2.3283064365386963e-10);
+    }
+
+    // Halfway vector around `normal` distributed like the GGX normal distribution
+    vec3 importance_sample_ggx(vec2 xi, vec3 normal, float roughness) {
+        float a = roughness * roughness;
+        float phi = 2.0 * PI * xi.x;
+        float cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+        float sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+        vec3 halfway = vec3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+
+        vec3 up = abs(normal.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+        vec3 tangent = normalize(cross(up, normal));
+        vec3 bitangent = cross(normal, tangent);
+        return normalize(tangent * halfway.x + bitangent * halfway.y + normal * halfway.z);
+    }
+"#;
+
+/*
+Projects an equirectangular image onto a cube map face, +Z is the middle of the image
+*/
+pub const EQUIRECTANGULAR_FRAGMENT_SHADER: &str = r#"
+    uniform sampler2D equirectangular;
+    uniform float lod;
+
+    void main() {
+        vec3 direction = get_face_direction();
+        vec2 uv = vec2(atan(direction.x, direction.z) / (2.0 * PI) + 0.5, asin(clamp(direction.y, -1.0, 1.0)) / PI + 0.5);
+        color = vec4(textureLod(equirectangular, uv, lod).rgb, 1.0);
+    }
+"#;
+
+/*
+Cosine weighted average of the environment over the hemisphere around every direction
+*/
+pub const IRRADIANCE_FRAGMENT_SHADER: &str = r#"
+    uniform samplerCube environment;
+    uniform float sample_delta;
+
+    void main() {
+        vec3 normal = get_face_direction();
+        vec3 up = abs(normal.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(0.0, 0.0, 1.0);
+        vec3 right = normalize(cross(up, normal));
+        up = cross(normal, right);
+
+        vec3 irradiance = vec3(0.0);
+        float samples = 0.0;
+        for (float phi = 0.0; phi < 2.0 * PI; phi += sample_delta) {
+            for (float theta = 0.0; theta < 0.5 * PI; theta += sample_delta) {
+                vec3 tangent_sample = vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+                vec3 direction = tangent_sample.x * right + tangent_sample.y * up + tangent_sample.z * normal;
+                irradiance += textureLod(environment, direction, 2.0).rgb * cos(theta) * sin(theta);
+                samples += 1.0;
+            }
+        }
+        color = vec4(PI * irradiance / samples, 1.0);
+    }
+"#;
+
+/*
+Environment blurred by the GGX lobe of `roughness`, one mip level of the prefiltered cube map.
+Samples read lower environment mips where they cover many texels, which keeps bright spots from sparkling.
+*/
+pub const PREFILTER_FRAGMENT_SHADER: &str = r#"
+    uniform samplerCube environment;
+    uniform float environment_size;
+    uniform float roughness;
+
+    const uint SAMPLE_COUNT = 512u;
+
+    void main() {
+        vec3 normal = get_face_direction();
+        vec3 view_dir = normal;
+        float a2 = pow(roughness, 4.0);
+        float texel_solid_angle = 4.0 * PI / (6.0 * environment_size * environment_size);
+
+        vec3 result = vec3(0.0);
+        float total_weight = 0.0;
+        for (uint i = 0u; i < SAMPLE_COUNT; i++) {
+            vec3 halfway = importance_sample_ggx(hammersley(i, SAMPLE_COUNT), normal, roughness);
+            vec3 light_dir = normalize(2.0 * dot(view_dir, halfway) * halfway - view_dir);
+            float n_dot_l = dot(normal, light_dir);
+            if (n_dot_l > 0.0) {
+                float n_dot_h = max(dot(normal, halfway), 0.0);
+                float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+                float pdf = a2 / (PI * d * d) / 4.0 + 1e-4;
+                float sample_solid_angle = 1.0 / (float(SAMPLE_COUNT) * pdf);
+                float lod = roughness == 0.0 ? 0.0 : 0.5 * log2(sample_solid_angle / texel_solid_angle) + 1.0;
+
+                result += textureLod(environment, light_dir, lod).rgb * n_dot_l;
+                total_weight += n_dot_l;
+            }
+        }
+        color = vec4(result / total_weight, 1.0);
+    }
+"#;
+
+/*
+Scale and bias to F0 of the specular environment light by n·v and roughness, combined after `ENVIRONMENT_SAMPLING`
+*/
+pub const BRDF_LUT_FRAGMENT_SHADER: &str = r#"
+    const uint SAMPLE_COUNT = 512u;
+
+    // Smith geometry term with the remapping used for image based lighting
+    float geometry_smith_ibl(float n_dot_v, float n_dot_l, float roughness) {
+        float k = roughness * roughness / 2.0;
+        return n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+    }
+
+    void main() {
+        float n_dot_v = max(v_texture.x, 1e-3);
+        float roughness = v_texture.y;
+        vec3 normal = vec3(0.0, 0.0, 1.0);
+        vec3 view_dir = vec3(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+
+        float scale = 0.0;
+        float bias = 0.0;
+        for (uint i = 0u; i < SAMPLE_COUNT; i++) {
+            vec3 halfway = importance_sample_ggx(hammersley(i, SAMPLE_COUNT), normal, roughness);
+            vec3 light_dir = normalize(2.0 * dot(view_dir, halfway) * halfway - view_dir);
+
+            float n_dot_l = max(light_dir.z, 0.0);
+            float n_dot_h = max(halfway.z, 0.0);
+            float v_dot_h = max(dot(view_dir, halfway), 0.0);
+            if (n_dot_l > 0.0) {
+                float visibility = geometry_smith_ibl(n_dot_v, n_dot_l, roughness) * v_dot_h / (n_dot_h * n_dot_v);
+                float fresnel = pow(1.0 - v_dot_h, 5.0);
+                scale += (1.0 - fresnel) * visibility;
+                bias += fresnel * visibility;
+            }
+        }
+        color = vec4(scale / float(SAMPLE_COUNT), bias / float(SAMPLE_COUNT), 0.0, 1.0);
+    }
+"#;
+
+/*
+Log luminance of an HDR image, averaged by halving it down to one texel
+*/
+pub const LUMINANCE_FRAGMENT_SHADER: &str = r#"
+    #version 150
+
+    in vec2 v_texture;
+
+    out vec4 color;
+
+    uniform sampler2D hdr;
+
+    void main() {
+        float luminance = dot(texture(hdr, v_texture).rgb, vec3(0.2126, 0.7152, 0.0722));
+        color = vec4(log(max(luminance, 1e-4)));
+    }
+"#;
+
+/*
+Moves the adapted luminance towards the average luminance of the current frame
+*/
+pub const ADAPTATION_FRAGMENT_SHADER: &str = r#"
+    #version 150
+
+    out vec4 color;
+
+    uniform sampler2D average_luminance;
+    uniform sampler2D previous_luminance;
+    uniform float adaptation;
+    uniform vec2 luminance_range;
+
+    void main() {
+        float current = clamp(exp(texelFetch(average_luminance, ivec2(0), 0).r), luminance_range.x, luminance_range.y);
+        float previous = texelFetch(previous_luminance, ivec2(0), 0).r;
+        color = vec4(mix(previous, current, adaptation));
+    }
+"#;
+
+/*
+Maps HDR colors to the screen, the operators are listed in `Hdr3D.rs`
+*/
+pub const TONE_MAP_FRAGMENT_SHADER: &str = r#"
+    #version 150
+
+    in vec2 v_texture;
+
+    out vec4 color;
+
+    uniform sampler2D hdr;
+    uniform sampler2D adapted_luminance;
+    uniform int tone_map_operator;
+    uniform float exposure;
+    uniform bool auto_exposure;
+    uniform float middle_gray;
+
+    vec3 reinhard(vec3 x) {
+        return x / (1.0 + x);
+    }
+
+    // Krzysztof Narkowicz's fit of the ACES filmic curve
+    vec3 aces(vec3 x) {
+        return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+    }
+
+    void main() {
+        float scale = exposure;
+        if (auto_exposure) {
+            scale *= middle_gray / texelFetch(adapted_luminance, ivec2(0), 0).r;
+        }
+        vec3 hdr_color = texture(hdr, v_texture).rgb * scale;
+
+        vec3 mapped = clamp(hdr_color, 0.0, 1.0);
+        if (tone_map_operator == 1) {
+            mapped = reinhard(hdr_color);
+        } else if (tone_map_operator == 2) {
+            mapped = aces(hdr_color);
+        }
+
+        // Written linear, the default framebuffer converts to sRGB
+        color = vec4(mapped, 1.0);
+    }
+"#;
+
+/*
+Bloom downsampling with a 13 tap filter, the first level keeps only colors above the threshold
+*/
+pub const BLOOM_DOWNSAMPLE_FRAGMENT_SHADER: &str = r#"
+    #version 150
+
+    in vec2 v_texture;
+
+    out vec4 color;
+
+    uniform sampler2D source;
+    uniform bool prefilter;
+    uniform float threshold;
+    uniform float knee;
+
+    vec3 sample_source(vec2 offset) {
+        return texture(source, v_texture + offset / vec2(textureSize(source, 0))).rgb;
+    }
+
+    void main() {
+        vec3 result = sample_source(vec2(0.0, 0.0)) * 0.125;
+        result += (sample_source(vec2(-1.0, 1.0)) + sample_source(vec2(1.0, 1.0)) + sample_source(vec2(-1.0, -1.0)) + sample_source(vec2(1.0, -1.0))) * 0.125;
+        result += (sample_source(vec2(0.0, 2.0)) + sample_source(vec2(-2.0, 0.0)) + sample_source(vec2(2.0, 0.0)) + sample_source(vec2(0.0, -2.0))) * 0.0625;
+        result += (sample_source(vec2(-2.0, 2.0)) + sample_source(vec2(2.0, 2.0)) + sample_source(vec2(-2.0, -2.0)) + sample_source(vec2(2.0, -2.0))) * 0.03125;
+
+        if (prefilter) {
+            // Quadratic curve around the threshold so bloom fades in instead of cutting off
+            float brightness = max(result.r, max(result.g, result.b));
+            float soft = clamp(brightness - threshold + knee, 0.0, 2.0 * knee);
+            soft = soft * soft / (4.0 * knee);
+            result *= max(soft, brightness - threshold) / max(brightness, 1e-4);
+        }
+
+        color = vec4(result, 1.0);
+    }
+"#;
+
+/*
+Bloom upsampling with a 3x3 tent filter, added onto the next larger level
+*/
+pub const BLOOM_UPSAMPLE_FRAGMENT_SHADER: &str = r#"
+    #version 150
+
+    in vec2 v_texture;
+
+    out vec4 color;
+
+    uniform sampler2D source;
+    uniform float radius;
+    uniform float intensity;
+
+    vec3 sample_source(vec2 offset) {
+        return texture(source, v_texture + offset * radius / vec2(textureSize(source, 0))).rgb;
+    }
+
+    void main() {
+        vec3 result = sample_source(vec2(0.0, 0.0)) * 4.0;
+        result += (sample_source(vec2(0.0, 1.0)) + sample_source(vec2(-1.0, 0.0)) + sample_source(vec2(1.0, 0.0)) + sample_source(vec2(0.0, -1.0))) * 2.0;
+        result += sample_source(vec2(-1.0, 1.0)) + sample_source(vec2(1.0, 1.0)) + sample_source(vec2(-1.0, -1.0)) + sample_source(vec2(1.0, -1.0));
+
+        color = vec4(result / 16.0 * intensity, 1.0);
+    }
+"#;
+
+/*
+Built-in post-processing passes, see `PostProcess3D.rs` for the uniforms every pass gets.
+Colors between passes are linear, converted to sRGB where a pass works on perceived values.
+*/
+pub const FXAA_FRAGMENT_SHADER: &str = r#"
+    #version 150
+
+    in vec2 v_texture;
+
+    out vec4 color;
+
+    uniform sampler2D source;
+    uniform vec2 resolution;
+
+    const float SPAN_MAX = 8.0;
+    const float REDUCE_MUL = 1.0 / 8.0;
+    const float REDUCE_MIN = 1.0 / 128.0;
+
+    float luma(vec3 rgb) {
+        return dot(sqrt(rgb), vec3(0.299, 0.587, 0.114));
+    }
+
+    vec3 sample_source(vec2 position) {
+        return texture(source, position).rgb;
+    }
+
+    void main() {
+        vec2 texel = 1.0 / resolution;
+        vec3 rgb_m = sample_source(v_texture);
+        float luma_nw = luma(sample_source(v_texture + vec2(-1.0, -1.0) * texel));
+        float luma_ne = luma(sample_source(v_texture + vec2(1.0, -1.0) * texel));
+        float luma_sw = luma(sample_source(v_texture + vec2(-1.0, 1.0) * texel));
+        float luma_se = luma(sample_source(v_texture + vec2(1.0, 1.0) * texel));
+        float luma_m = luma(rgb_m);
+        float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+        float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+        // Blur along the edge, perpendicular to the luma gradient
+        vec2 direction = vec2(-((luma_nw + luma_ne) - (luma_sw + luma_se)), (luma_nw + luma_sw) - (luma_ne + luma_se));
+        float direction_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+        float inverse_direction_min = 1.0 / (min(abs(direction.x), abs(direction.y)) + direction_reduce);
+        direction = clamp(direction * inverse_direction_min, vec2(-SPAN_MAX), vec2(SPAN_MAX)) * texel;
+
+        vec3 rgb_a = 0.5 * (sample_source(v_texture + direction * (1.0 / 3.0 - 0.5)) + sample_source(v_texture + direction * (2.0 / 3.0 - 0.5)));
+        vec3 rgb_b = rgb_a * 0.5 + 0.25 * (sample_source(v_texture - direction * 0.5) + sample_source(v_texture + direction * 0.5));
+        float luma_b = luma(rgb_b);
+
+        // The wider blur crossed into another edge when its luma leaves the local range
+        color = vec4((luma_b < luma_min || luma_b > luma_max) ? rgb_a : rgb_b, 1.0);
+    }
+"#;
+
+pub const COLOR_GRADING_FRAGMENT_SHADER: &str = r#"
+    #version 150
+
+    in vec2 v_texture;
+
+    out vec4 color;
+
+    uniform sampler2D source;
+    uniform sampler3D lut;
+    uniform float intensity;
+
+    vec3 to_srgb(vec3 linear) {
+        return mix(linear * 12.92, 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055, step(vec3(0.0031308), linear));
+    }
+
+    vec3 to_linear(vec3 srgb) {
+        return mix(srgb / 12.92, pow((srgb + 0.055) / 1.055, vec3(2.4)), step(vec3(0.04045), srgb));
+    }
+
+    void main() {
+        vec3 linear = clamp(texture(source, v_texture).rgb, 0.0, 1.0);
+
+        // Tables map sRGB colors, look up at texel centers so the ends of the table are exact
+        float size = float(textureSize(lut, 0).x);
+        vec3 coordinates = to_srgb(linear) * (size - 1.0) / size + 0.5 / size;
+        vec3 graded = to_linear(texture(lut, coordinates).rgb);
+
+        color = vec4(mix(linear, graded, intensity), 1.0);
+    }
+"#;
+
+pub const GRAYSCALE_FRAGMENT_SHADER: &str = r#"
+    #version 150
+
+    in vec2 v_texture;
+
+    out vec4 color;
+
+    uniform sampler2D source;
+    uniform float amount;
+
+    void main() {
+        vec3 rgb = texture(source, v_texture).rgb;
+        float luminance = dot(rgb, vec3(0.2126, 0.7152, 0.0722));
+        color = vec4(mix(rgb, vec3(luminance), amount), 1.0);
+    }
+"#;
+
+pub const CHROMATIC_ABERRATION_FRAGMENT_SHADER: &str = r#"
+    #version 150
+
+    in vec2 v_texture;
+
+    out vec4 color;
+
+    uniform sampler2D source;
+    uniform float strength;
+
+    void main() {
+        // Red and blue are pushed apart towards the edges of the screen
+        vec2 offset = (v_texture - 0.5) * strength;
+        color = vec4(
+            texture(source, v_texture - offset).r,
+            texture(source, v_texture).g,
+            texture(source, v_texture + offset).b,
+            1.0
+        );
+    }
+"#;
+
+pub const VIGNETTE_FRAGMENT_SHADER: &str = r#"
+    #version 150
+
+    in vec2 v_texture;
+
+    out vec4 color;
+
+    uniform sampler2D source;
+    uniform vec2 resolution;
+    uniform float strength;
+    uniform float radius;
+    uniform float softness;
+
+    void main() {
+        // Distance from the center with the aspect ratio kept, 1 in the corners
+        vec2 centered = (v_texture - 0.5) * vec2(resolution.x / resolution.y, 1.0);
+        float distance = length(centered) / length(vec2(0.5 * resolution.x / resolution.y, 0.5));
+        float darkening = smoothstep(radius, radius + softness, distance) * strength;
+        color = vec4(texture(source, v_texture).rgb * (1.0 - darkening), 1.0);
+    }
+"#;
+
+pub const FILM_GRAIN_FRAGMENT_SHADER: &str = r#"
+    #version 150
+
+    in vec2 v_texture;
+
+    out vec4 color;
+
+    uniform sampler2D source;
+    uniform vec2 resolution;
+    uniform float time;
+    uniform float intensity;
+
+    float hash(vec2 p) {
+        vec3 p3 = fract(vec3(p.xyx) * 0.1031);
+        p3 += dot(p3, p3.yzx + 33.33);
+        return fract((p3.x + p3.y) * p3.z);
+    }
+
+    void main() {
+        vec3 rgb = texture(source, v_texture).rgb;
+        // New noise every frame, strongest in the mid tones like on film
+        float noise = hash(v_texture * resolution + fract(time * 7.31) * 1000.0) - 0.5;
+        float luminance = dot(rgb, vec3(0.2126, 0.7152, 0.0722));
+        float response = 4.0 * luminance * (1.0 - luminance);
+        color = vec4(max(rgb + noise * intensity * (0.25 + response), 0.0), 1.0);
+    }
+"#;
@@ -0,0 +1,426 @@
+pub mod Shadow {
+    use glium::Surface;
+    use glium::texture::{CubeLayer, DepthCubemap, DepthFormat, DepthTexture2d, MipmapsOption};
+    use glium::uniforms::{DepthTextureComparison, MagnifySamplerFilter, MinifySamplerFilter, SamplerBehavior, SamplerWrapFunction};
+
+    use crate::Lights3D::Lights::*;
+    use crate::Math3D::Math::*;
+    use crate::Mesh3D::Mesh::*;
+    use crate::Shaders;
+
+    /**
+     * Number of shadow maps available to each light type, must match the sampler arrays in `Shaders.rs`
+     */
+    pub const MAX_DIRECTIONAL_SHADOWS: i32 = 2;
+    pub const MAX_SPOT_SHADOWS: i32 = 2;
+    pub const MAX_POINT_SHADOWS: i32 = 4;
+
+    /*
+    How far behind the visible area directional shadow casters are still rendered
+    */
+    const CASTER_DISTANCE: f32 = 16.0;
+
+    const SPOT_SHADOW_ZNEAR: f32 = 0.01;
+
+    /**
+     * Sampler for shadow maps, linear filtering on a depth comparison sampler gives
+     * hardware 2x2 PCF on top of the filtering done in the shader
+     */
+    pub fn get_shadow_sampler() -> SamplerBehavior {
+        return SamplerBehavior {
+            wrap_function: (SamplerWrapFunction::Clamp, SamplerWrapFunction::Clamp, SamplerWrapFunction::Clamp),
+            minify_filter: MinifySamplerFilter::Linear,
+            magnify_filter: MagnifySamplerFilter::Linear,
+            depth_texture_comparison: Some(DepthTextureComparison::LessOrEqual),
+            .. Default::default()
+        };
+    }
+
+    /**
+     * Light space matrix of a directional light. The shadow map covers a sphere around
+     * the part of the view frustum closer than `max_distance`, snapped to whole texels
+     * so shadow edges do not shimmer while the camera moves.
+     */
+    pub fn get_directional_light_space(direction: [f32; 3], view: &[[f32; 4]; 4], perspective: &[[f32; 4]; 4], max_distance: f32, resolution: u32) -> [[f32; 4]; 4] {
+        // Unproject the corners of the frustum slice, the far plane is moved to `max_distance`
+        let inverse = mat4_inverse(&mat4_mul(perspective, view));
+        let far_ndc = perspective[2][2] + perspective[3][2] / max_distance;
+        let mut corners: Vec<[f32; 3]> = Vec::with_capacity(8);
+        for z in [-1.0, far_ndc.min(1.0)] {
+            for y in [-1.0, 1.0] {
+                for x in [-1.0, 1.0] {
+                    let corner = mat4_mul_vec4(&inverse, [x, y, z, 1.0]);
+                    corners.push([corner[0] / corner[3], corner[1] / corner[3], corner[2] / corner[3]]);
+                }
+            }
+        }
+
+        let center = vscale(corners.iter().fold([0.0; 3], |sum, corner| vadd(sum, *corner)), 1.0 / corners.len() as f32);
+        let radius = corners.iter().map(|corner| vlength(vsub(*corner, center))).fold(0.0f32, f32::max);
+        // Quantize the radius so the projection does not change size every frame
+        let radius = (radius * 16.0).ceil() / 16.0;
+
+        let light_view = look_at_matrix([0.0; 3], direction, [0.0, 1.0, 0.0]);
+        let mut light_center = transform_point(&light_view, center);
+        let texel_size = 2.0 * radius / resolution as f32;
+        light_center[0] = (light_center[0] / texel_size).floor() * texel_size;
+        light_center[1] = (light_center[1] / texel_size).floor() * texel_size;
+
+        let projection = orthographic_matrix(
+            light_center[0] - radius, light_center[0] + radius,
+            light_center[1] - radius, light_center[1] + radius,
+            light_center[2] - radius - CASTER_DISTANCE, light_center[2] + radius,
+        );
+        return mat4_mul(&projection, &light_view);
+    }
+
+    /**
+     * Light space matrix of a spot light, a perspective projection covering the outer cone
+     * out to the distance where the light fades out
+     */
+    pub fn get_spot_light_space(light: &SpotLight) -> [[f32; 4]; 4] {
+        let fov = (2.0 * light.outer_cutoff.clamp(-1.0, 1.0).acos()).clamp(0.1, 170f32.to_radians());
+        let zfar = light.get_range().clamp(SPOT_SHADOW_ZNEAR * 2.0, 1024.0);
+        let light_view = look_at_matrix(light.position, light.direction, [0.0, 1.0, 0.0]);
+        return mat4_mul(&perspective_matrix(fov, 1.0, SPOT_SHADOW_ZNEAR, zfar), &light_view);
+    }
+
+    /**
+     * Depth texture rendered from the point of view of a light, together with
+     * the matrix that takes world space positions into it
+     */
+    pub struct ShadowMap {
+        texture: DepthTexture2d,
+        light_space: [[f32; 4]; 4],
+    }
+
+    impl ShadowMap {
+
+        pub fn new(display: &glium::Display, resolution: u32) -> ShadowMap {
+            return ShadowMap {
+                texture: DepthTexture2d::empty_with_format(display, DepthFormat::F32, MipmapsOption::NoMipmap, resolution, resolution).unwrap(),
+                light_space: IDENTITY,
+            }
+        }
+
+        pub fn get_resolution(&self) -> u32 {
+            return self.texture.width();
+        }
+
+        pub fn get_texture(&self) -> &DepthTexture2d {
+            return &self.texture;
+        }
+
+        pub fn get_light_space(&self) -> [[f32; 4]; 4] {
+            return self.light_space;
+        }
+
+        /**
+         * Renders the depth of all draw calls as seen through `light_space`
+         */
+        pub fn render(&mut self, display: &glium::Display, program: &glium::Program, light_space: [[f32; 4]; 4], draw_calls: &[DrawCall]) {
+            self.light_space = light_space;
+
+            let draw_parameters = glium::DrawParameters {
+                depth: glium::Depth {
+                    test: glium::draw_parameters::DepthTest::IfLess,
+                    write: true,
+                    .. Default::default()
+                },
+                // Slope scaled offset against shadow acne, the per light bias is applied when sampling
+                polygon_offset: glium::draw_parameters::PolygonOffset {
+                    factor: 2.0,
+                    units: 4.0,
+                    fill: true,
+                    .. Default::default()
+                },
+                .. Default::default()
+            };
+
+            let mut framebuffer = glium::framebuffer::SimpleFrameBuffer::depth_only(display, &self.texture).unwrap();
+            framebuffer.clear_depth(1.0);
+            for draw_call in draw_calls.iter() {
+                let uniforms = uniform! {
+                    light_space: light_space,
+                    model: draw_call.model,
+                };
+                draw_call.draw(&mut framebuffer, program, &uniforms, &draw_parameters).unwrap();
+            }
+        }
+    }
+
+    /**
+     * Cube map holding the distance to the closest surface around a point light,
+     * divided by `far` so it fits into the depth range
+     */
+    pub struct PointShadowMap {
+        texture: DepthCubemap,
+        far: f32,
+    }
+
+    impl PointShadowMap {
+
+        pub fn new(display: &glium::Display, resolution: u32) -> PointShadowMap {
+            return PointShadowMap {
+                texture: DepthCubemap::empty_with_format(display, DepthFormat::F32, MipmapsOption::NoMipmap, resolution).unwrap(),
+                far: 1.0,
+            }
+        }
+
+        pub fn get_resolution(&self) -> u32 {
+            return self.texture.width();
+        }
+
+        pub fn get_texture(&self) -> &DepthCubemap {
+            return &self.texture;
+        }
+
+        pub fn get_far(&self) -> f32 {
+            return self.far;
+        }
+
+        /**
+         * Renders the six faces of the cube map around `position`, out to `far`
+         */
+        pub fn render(&mut self, display: &glium::Display, program: &glium::Program, position: [f32; 3], far: f32, draw_calls: &[DrawCall]) {
+            self.far = far;
+
+            let draw_parameters = glium::DrawParameters {
+                depth: glium::Depth {
+                    test: glium::draw_parameters::DepthTest::IfLess,
+                    write: true,
+                    .. Default::default()
+                },
+                .. Default::default()
+            };
+
+            // Cube map faces are addressed right handed, mirroring x turns the left handed view around
+            let mut projection = perspective_matrix(std::f32::consts::FRAC_PI_2, 1.0, POINT_SHADOW_ZNEAR, far);
+            projection[0][0] = -projection[0][0];
+
+            for (layer, direction, up) in CUBE_FACES.iter() {
+                let light_space = mat4_mul(&projection, &look_at_matrix(position, *direction, *up));
+                let mut framebuffer = glium::framebuffer::SimpleFrameBuffer::depth_only(display, self.texture.main_level().image(*layer)).unwrap();
+                framebuffer.clear_depth(1.0);
+                for draw_call in draw_calls.iter() {
+                    let uniforms = uniform! {
+                        light_space: light_space,
+                        model: draw_call.model,
+                        light_position: position,
+                        far: far,
+                    };
+                    draw_call.draw(&mut framebuffer, program, &uniforms, &draw_parameters).unwrap();
+                }
+            }
+        }
+    }
+
+    const POINT_SHADOW_ZNEAR: f32 = 0.01;
+
+    /*
+    Viewing direction and up vector of each cube map face, as defined by OpenGL
+    */
+    const CUBE_FACES: [(CubeLayer, [f32; 3], [f32; 3]); 6] = [
+        (CubeLayer::PositiveX, [1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+        (CubeLayer::NegativeX, [-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+        (CubeLayer::PositiveY, [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+        (CubeLayer::NegativeY, [0.0, -1.0, 0.0], [0.0, 0.0, -1.0]),
+        (CubeLayer::PositiveZ, [0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+        (CubeLayer::NegativeZ, [0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+    ];
+
+    /*
+    Common interface of the shadow map kinds, so slots can be handed out the same way for all light types
+    */
+    trait ShadowTarget {
+        fn create(display: &glium::Display, resolution: u32) -> Self;
+        fn resolution(&self) -> u32;
+    }
+
+    impl ShadowTarget for ShadowMap {
+        fn create(display: &glium::Display, resolution: u32) -> ShadowMap {
+            return ShadowMap::new(display, resolution);
+        }
+
+        fn resolution(&self) -> u32 {
+            return self.get_resolution();
+        }
+    }
+
+    impl ShadowTarget for PointShadowMap {
+        fn create(display: &glium::Display, resolution: u32) -> PointShadowMap {
+            return PointShadowMap::new(display, resolution);
+        }
+
+        fn resolution(&self) -> u32 {
+            return self.get_resolution();
+        }
+    }
+
+    /*
+    Shadow maps of one light type. Maps are kept between frames and only
+    reallocated when the resolution asked for by their light changes.
+    */
+    struct ShadowSlots<M: ShadowTarget> {
+        maps: Vec<Option<M>>,
+        light_slots: Vec<i32>,
+    }
+
+    impl<M: ShadowTarget> ShadowSlots<M> {
+        fn new(count: i32) -> ShadowSlots<M> {
+            return ShadowSlots {
+                maps: (0..count).map(|_| None).collect(),
+                light_slots: Vec::new(),
+            }
+        }
+
+        /*
+        Hands out slots to `candidates`, pairs of light index and resolution, in the order given
+        until the slots run out. Returns the map of every light that got one.
+        */
+        fn assign(&mut self, display: &glium::Display, light_count: usize, candidates: Vec<(usize, u32)>) -> Vec<(usize, &mut M)> {
+            let assigned: Vec<(usize, u32)> = candidates.into_iter().take(self.maps.len()).collect();
+
+            self.light_slots = vec![-1; light_count];
+            for (slot, (light, resolution)) in assigned.iter().enumerate() {
+                self.light_slots[*light] = slot as i32;
+                let reuse = match &self.maps[slot] {
+                    Some(map) => map.resolution() == *resolution,
+                    None => false,
+                };
+                if !reuse {
+                    self.maps[slot] = Some(M::create(display, *resolution));
+                }
+            }
+
+            return assigned.iter().map(|(light, _)| *light).zip(self.maps.iter_mut().map(|map| map.as_mut().unwrap())).collect();
+        }
+
+        fn get_slot(&self, light: usize) -> i32 {
+            return *self.light_slots.get(light).unwrap_or(&-1);
+        }
+
+        fn get_map(&self, slot: usize) -> Option<&M> {
+            if slot >= self.maps.len() || !self.light_slots.contains(&(slot as i32)) {
+                return None;
+            }
+            return self.maps[slot].as_ref();
+        }
+    }
+
+    /*
+    Lights asking for shadows, in light order
+    */
+    fn shadow_candidates<'a, I: Iterator<Item = &'a ShadowSettings>>(settings: I) -> Vec<(usize, u32)> {
+        return settings.enumerate().filter(|(_, shadow)| shadow.enabled).map(|(light, shadow)| (light, shadow.resolution)).collect();
+    }
+
+    /*
+    How much a point light contributes around the camera, its brightest channel attenuated by the distance to the camera
+    */
+    fn point_light_importance(light: &PointLight, camera_position: [f32; 3]) -> f32 {
+        let distance = vlength(vsub(light.position, camera_position));
+        let brightness = light.diffuse_color.iter().fold(0.0f32, |max, channel| max.max(*channel));
+        return brightness / (light.constant + light.linear * distance + light.quadratic * distance * distance);
+    }
+
+    /**
+     * Renders the shadow maps of all shadow casting lights every frame
+     */
+    pub struct ShadowRenderer {
+        program: glium::Program,
+        point_program: glium::Program,
+        empty_map: DepthTexture2d,
+        empty_cube_map: DepthCubemap,
+        directional: ShadowSlots<ShadowMap>,
+        spot: ShadowSlots<ShadowMap>,
+        point: ShadowSlots<PointShadowMap>,
+        pub max_distance: f32,
+        pub point_shadow_budget: usize,
+    }
+
+    impl ShadowRenderer {
+
+        pub fn new(display: &glium::Display) -> ShadowRenderer {
+            return ShadowRenderer {
+                program: glium::Program::from_source(display, Shaders::SHADOW_VERTEX_SHADER, Shaders::SHADOW_FRAGMENT_SHADER, None).unwrap(),
+                point_program: glium::Program::from_source(display, Shaders::POINT_SHADOW_VERTEX_SHADER, Shaders::POINT_SHADOW_FRAGMENT_SHADER, None).unwrap(),
+                empty_map: DepthTexture2d::empty_with_format(display, DepthFormat::F32, MipmapsOption::NoMipmap, 1, 1).unwrap(),
+                empty_cube_map: DepthCubemap::empty_with_format(display, DepthFormat::F32, MipmapsOption::NoMipmap, 1).unwrap(),
+                directional: ShadowSlots::new(MAX_DIRECTIONAL_SHADOWS),
+                spot: ShadowSlots::new(MAX_SPOT_SHADOWS),
+                point: ShadowSlots::new(MAX_POINT_SHADOWS),
+                max_distance: 8.0,
+                point_shadow_budget: 2,
+            }
+        }
+
+        /**
+         * Renders shadow maps for the given lights, directional shadows are fitted to the camera
+         * described by `view` and `perspective`. Lights asking for shadows once every slot is taken go without.
+         * Of the point lights only the `point_shadow_budget` most important ones around the camera get shadows.
+         */
+        pub fn update(&mut self, display: &glium::Display, lights: &LightList, view: &[[f32; 4]; 4], perspective: &[[f32; 4]; 4], draw_calls: &[DrawCall]) {
+            let directional_lights = &lights.directional_lights;
+            let point_lights = &lights.point_lights;
+            let spot_lights = &lights.spot_lights;
+
+            let max_distance = self.max_distance;
+            for (light, map) in self.directional.assign(display, directional_lights.len(), shadow_candidates(directional_lights.iter().map(|light| &light.shadow))) {
+                let light_space = get_directional_light_space(directional_lights[light].direction, view, perspective, max_distance, map.get_resolution());
+                map.render(display, &self.program, light_space, draw_calls);
+            }
+
+            for (light, map) in self.spot.assign(display, spot_lights.len(), shadow_candidates(spot_lights.iter().map(|light| &light.shadow))) {
+                map.render(display, &self.program, get_spot_light_space(&spot_lights[light]), draw_calls);
+            }
+
+            let camera_position = transform_point(&mat4_inverse(view), [0.0; 3]);
+            let mut candidates = shadow_candidates(point_lights.iter().map(|light| &light.shadow));
+            candidates.sort_by(|a, b| point_light_importance(&point_lights[b.0], camera_position).total_cmp(&point_light_importance(&point_lights[a.0], camera_position)));
+            candidates.truncate(self.point_shadow_budget);
+            for (light, map) in self.point.assign(display, point_lights.len(), candidates) {
+                let far = point_lights[light].get_range().clamp(POINT_SHADOW_ZNEAR * 2.0, 1024.0);
+                map.render(display, &self.point_program, point_lights[light].position, far, draw_calls);
+            }
+        }
+
+        /**
+         * Shadow map slot of the i'th directional light, -1 if it casts no shadow
+         */
+        pub fn get_directional_slot(&self, light: usize) -> i32 {
+            return self.directional.get_slot(light);
+        }
+
+        pub fn get_spot_slot(&self, light: usize) -> i32 {
+            return self.spot.get_slot(light);
+        }
+
+        pub fn get_point_slot(&self, light: usize) -> i32 {
+            return self.point.get_slot(light);
+        }
+
+        pub fn get_directional_map(&self, slot: usize) -> Option<&ShadowMap> {
+            return self.directional.get_map(slot);
+        }
+
+        pub fn get_spot_map(&self, slot: usize) -> Option<&ShadowMap> {
+            return self.spot.get_map(slot);
+        }
+
+        pub fn get_point_map(&self, slot: usize) -> Option<&PointShadowMap> {
+            return self.point.get_map(slot);
+        }
+
+        /**
+         * Bound to unused shadow samplers, so no sampler ends up on a unit holding another texture type
+         */
+        pub fn get_empty_map(&self) -> &DepthTexture2d {
+            return &self.empty_map;
+        }
+
+        pub fn get_empty_cube_map(&self) -> &DepthCubemap {
+            return &self.empty_cube_map;
+        }
+    }
+}
@@ -0,0 +1,72 @@
+pub mod Skybox {
+    use std::path::Path;
+
+    use glium::texture::Cubemap;
+
+    use crate::Base3D::General::*;
+    use crate::GraphicsLoader2D::GraphicsLoader;
+    use crate::Ibl3D::Ibl::*;
+    use crate::Mesh3D::Mesh::*;
+    use crate::Shaders;
+
+    /**
+     * Loads a sky cube map from a directory of six face images, see `GraphicsLoader::load_cubemap_from_directory`,
+     * or from a single equirectangular image
+     */
+    pub fn load_skybox(display: &glium::Display, path: &Path) -> Result<Cubemap, String> {
+        if path.is_dir() {
+            return GraphicsLoader::load_cubemap_from_directory(display, path);
+        }
+        let equirectangular = GraphicsLoader::load_equirectangular(display, path)?;
+        return Ok(equirectangular_to_cubemap(display, &equirectangular));
+    }
+
+    /**
+     * Draws a cube map around the camera behind everything drawn before it. The sky only turns with
+     * the camera and is projected onto the far plane, so it only covers pixels where the depth buffer
+     * is still clear. Colors are linear and scaled by `intensity` before tone mapping.
+     */
+    pub struct SkyboxRenderer {
+        program: glium::Program,
+        cube: GpuMesh,
+        pub intensity: f32,
+    }
+
+    impl SkyboxRenderer {
+
+        pub fn new(display: &glium::Display) -> SkyboxRenderer {
+            return SkyboxRenderer {
+                program: glium::Program::from_source(display, Shaders::SKYBOX_VERTEX_SHADER, Shaders::SKYBOX_FRAGMENT_SHADER, None).unwrap(),
+                cube: GpuMesh::new_static(display, &IndexedShape::from_shape(&Cube::new([-1.0, -1.0, -1.0], 2.0, 0))),
+                intensity: 1.0,
+            }
+        }
+
+        /**
+         * Draws `skybox` into a target with the depth buffer of the scene
+         */
+        pub fn render<S: glium::Surface>(&self, target: &mut S, skybox: &Cubemap, view: &[[f32; 4]; 4], perspective: &[[f32; 4]; 4]) {
+            // Rotation of the camera only, the sky is infinitely far away
+            let mut rotation = *view;
+            rotation[3] = [0.0, 0.0, 0.0, 1.0];
+
+            let uniforms = uniform! {
+                skybox: glium::uniforms::Sampler(skybox, get_cubemap_sampler()),
+                intensity: self.intensity,
+                view: rotation,
+                perspective: *perspective,
+            };
+            // The camera looks at the inside of the cube
+            let draw_parameters = glium::DrawParameters {
+                depth: glium::Depth {
+                    test: glium::draw_parameters::DepthTest::IfLessOrEqual,
+                    write: false,
+                    .. Default::default()
+                },
+                backface_culling: glium::BackfaceCullingMode::CullingDisabled,
+                .. Default::default()
+            };
+            self.cube.draw(target, &self.program, &uniforms, &draw_parameters).unwrap();
+        }
+    }
+}
@@ -0,0 +1,227 @@
+pub mod Ssao {
+    use glium::Surface;
+    use glium::framebuffer::{DepthRenderBuffer, MultiOutputFrameBuffer, SimpleFrameBuffer};
+    use glium::texture::{DepthFormat, MipmapsOption, Texture2d, UncompressedFloatFormat};
+    use glium::uniforms::UniformValue;
+
+    use crate::Math3D::Math::*;
+    use crate::Mesh3D::Mesh::*;
+    use crate::Screen3D::Screen::*;
+    use crate::Shaders;
+
+    /*
+    Most hemisphere samples per pixel, the size of the sample array in the shader
+    */
+    pub const MAX_SSAO_SAMPLES: usize = 64;
+
+    /*
+    Width and height of the tiled noise texture, the blur averages as many pixels so the pattern disappears
+    */
+    const NOISE_SIZE: u32 = 4;
+
+    /*
+    Deterministic xorshift random numbers in [0, 1), so the kernel and noise are the same every run
+    */
+    struct Random {
+        state: u32,
+    }
+
+    impl Random {
+        fn next(&mut self) -> f32 {
+            self.state ^= self.state << 13;
+            self.state ^= self.state >> 17;
+            self.state ^= self.state << 5;
+            return (self.state >> 8) as f32 / (1 << 24) as f32;
+        }
+    }
+
+    /*
+    Samples in the hemisphere around +Z, scaled so more of them end up close to the center
+    */
+    fn get_kernel(sample_count: usize) -> Vec<[f32; 3]> {
+        let mut random = Random { state: 0x9e3779b9 };
+        return (0..sample_count).map(|i| {
+            let direction = vnormalize([random.next() * 2.0 - 1.0, random.next() * 2.0 - 1.0, random.next().max(0.05)]);
+            let t = i as f32 / sample_count as f32;
+            return vscale(direction, random.next() * (0.1 + 0.9 * t * t));
+        }).collect();
+    }
+
+    /*
+    Position and normal targets drawn by the prepass, laid out like the ones of the G-buffer
+    */
+    struct PrepassTarget {
+        position: Texture2d,
+        normal: Texture2d,
+        depth: DepthRenderBuffer,
+    }
+
+    /*
+    Uniforms of the occlusion pass, a struct to hand over the sample array
+    */
+    struct SsaoUniform<'a> {
+        position: &'a Texture2d,
+        normal: &'a Texture2d,
+        noise: &'a Texture2d,
+        samples: &'a [[f32; 3]],
+        radius: f32,
+        bias: f32,
+        strength: f32,
+        view: [[f32; 4]; 4],
+        perspective: [[f32; 4]; 4],
+    }
+
+    impl glium::uniforms::Uniforms for SsaoUniform<'_> {
+        fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut f: F) {
+            f("g_position", UniformValue::Texture2d(self.position, None));
+            f("g_normal", UniformValue::Texture2d(self.normal, None));
+            f("noise", UniformValue::Texture2d(self.noise, None));
+            for (i, sample) in self.samples.iter().enumerate() {
+                f(&format!("samples[{}]", i), UniformValue::Vec3(*sample));
+            }
+            f("sample_count", UniformValue::SignedInt(self.samples.len() as i32));
+            f("radius", UniformValue::Float(self.radius));
+            f("bias", UniformValue::Float(self.bias));
+            f("strength", UniformValue::Float(self.strength));
+            f("view", UniformValue::Mat4(self.view));
+            f("perspective", UniformValue::Mat4(self.perspective));
+        }
+    }
+
+    /**
+     * Screen space ambient occlusion. Every pixel tests `sample_count` points in a hemisphere of `radius`
+     * world units over its surface against the depth of the scene, the visible fraction raised to
+     * `strength` scales the ambient light of the pixel. `bias` keeps flat surfaces from occluding themselves.
+     * The forward path has no G-buffer, a renderer created with `prepass` draws its own positions and normals.
+     *
+     * The passes take `&self` so the occlusion texture can stay bound in the uniforms of a frame,
+     * `resize` has to be called before them.
+     */
+    pub struct SsaoRenderer {
+        prepass_program: glium::Program,
+        ssao_program: glium::Program,
+        blur_program: glium::Program,
+        screen_quad: ScreenQuad,
+        noise: Texture2d,
+        prepass: Option<PrepassTarget>,
+        use_prepass: bool,
+        occlusion: Texture2d,
+        blurred: Texture2d,
+        pub enabled: bool,
+        pub radius: f32,
+        pub bias: f32,
+        pub strength: f32,
+        pub sample_count: usize,
+    }
+
+    impl SsaoRenderer {
+
+        pub fn new(display: &glium::Display, prepass: bool) -> SsaoRenderer {
+            let mut random = Random { state: 0x2545f491 };
+            let noise: Vec<Vec<(f32, f32, f32)>> = (0..NOISE_SIZE).map(|_| {
+                (0..NOISE_SIZE).map(|_| (random.next() * 2.0 - 1.0, random.next() * 2.0 - 1.0, 0.0)).collect()
+            }).collect();
+
+            return SsaoRenderer {
+                prepass_program: glium::Program::from_source(display, Shaders::VERTEX_SHADER, Shaders::SSAO_PREPASS_FRAGMENT_SHADER, None).unwrap(),
+                ssao_program: glium::Program::from_source(display, Shaders::SCREEN_VERTEX_SHADER, Shaders::SSAO_FRAGMENT_SHADER, None).unwrap(),
+                blur_program: glium::Program::from_source(display, Shaders::SCREEN_VERTEX_SHADER, Shaders::SSAO_BLUR_FRAGMENT_SHADER, None).unwrap(),
+                screen_quad: ScreenQuad::new(display),
+                noise: Texture2d::with_format(display, noise, UncompressedFloatFormat::F16F16F16, MipmapsOption::NoMipmap).unwrap(),
+                prepass: None,
+                use_prepass: prepass,
+                occlusion: occlusion_target(display, (1, 1)),
+                blurred: occlusion_target(display, (1, 1)),
+                enabled: true,
+                radius: 0.15,
+                bias: 0.01,
+                strength: 1.5,
+                sample_count: 16,
+            }
+        }
+
+        /**
+         * Sizes the targets to the frame
+         */
+        pub fn resize(&mut self, display: &glium::Display, dimensions: (u32, u32)) {
+            if (self.occlusion.width(), self.occlusion.height()) == dimensions {
+                return;
+            }
+            self.occlusion = occlusion_target(display, dimensions);
+            self.blurred = occlusion_target(display, dimensions);
+            if self.use_prepass {
+                self.prepass = Some(PrepassTarget {
+                    position: Texture2d::empty_with_format(display, UncompressedFloatFormat::F32F32F32F32, MipmapsOption::NoMipmap, dimensions.0, dimensions.1).unwrap(),
+                    normal: Texture2d::empty_with_format(display, UncompressedFloatFormat::F16F16F16F16, MipmapsOption::NoMipmap, dimensions.0, dimensions.1).unwrap(),
+                    depth: DepthRenderBuffer::new(display, DepthFormat::F32, dimensions.0, dimensions.1).unwrap(),
+                });
+            }
+        }
+
+        /**
+         * Draws the positions and normals of `draw_calls` with the prepass, then computes the occlusion from them
+         */
+        pub fn render_prepass(&self, display: &glium::Display, draw_calls: &[DrawCall], view: &[[f32; 4]; 4], perspective: &[[f32; 4]; 4], draw_parameters: &glium::DrawParameters) {
+            if !self.enabled {
+                self.clear(display);
+                return;
+            }
+            let prepass = self.prepass.as_ref().expect("SSAO renderer was created without a prepass or not resized");
+
+            let outputs = [("g_position", &prepass.position), ("g_normal", &prepass.normal)];
+            let mut framebuffer = MultiOutputFrameBuffer::with_depth_buffer(display, outputs.iter().cloned(), &prepass.depth).unwrap();
+            framebuffer.clear_color_and_depth((0.0, 0.0, 0.0, 0.0), 1.0);
+            for draw_call in draw_calls.iter() {
+                let uniforms = uniform! {model: draw_call.model, normal_matrix: draw_call.normal_matrix, view: *view, perspective: *perspective};
+                draw_call.draw(&mut framebuffer, &self.prepass_program, &uniforms, draw_parameters).unwrap();
+            }
+
+            self.render(display, &prepass.position, &prepass.normal, view, perspective);
+        }
+
+        /**
+         * Computes the occlusion from world space positions and normals, in the layout of the G-buffer
+         */
+        pub fn render(&self, display: &glium::Display, position: &Texture2d, normal: &Texture2d, view: &[[f32; 4]; 4], perspective: &[[f32; 4]; 4]) {
+            if !self.enabled {
+                self.clear(display);
+                return;
+            }
+
+            let samples = get_kernel(self.sample_count.clamp(1, MAX_SSAO_SAMPLES));
+            let uniforms = SsaoUniform {
+                position: position,
+                normal: normal,
+                noise: &self.noise,
+                samples: &samples,
+                radius: self.radius,
+                bias: self.bias,
+                strength: self.strength,
+                view: *view,
+                perspective: *perspective,
+            };
+            let mut framebuffer = SimpleFrameBuffer::new(display, &self.occlusion).unwrap();
+            self.screen_quad.draw(&mut framebuffer, &self.ssao_program, &uniforms, &Default::default()).unwrap();
+
+            let mut framebuffer = SimpleFrameBuffer::new(display, &self.blurred).unwrap();
+            self.screen_quad.draw(&mut framebuffer, &self.blur_program, &uniform! {occlusion: &self.occlusion}, &Default::default()).unwrap();
+        }
+
+        /**
+         * Ambient occlusion of every pixel, 1 where nothing is occluded
+         */
+        pub fn get_occlusion(&self) -> &Texture2d {
+            return &self.blurred;
+        }
+
+        fn clear(&self, display: &glium::Display) {
+            SimpleFrameBuffer::new(display, &self.blurred).unwrap().clear_color(1.0, 1.0, 1.0, 1.0);
+        }
+    }
+
+    fn occlusion_target(display: &glium::Display, dimensions: (u32, u32)) -> Texture2d {
+        let texture = Texture2d::empty_with_format(display, UncompressedFloatFormat::U8, MipmapsOption::NoMipmap, dimensions.0, dimensions.1).unwrap();
+        SimpleFrameBuffer::new(display, &texture).unwrap().clear_color(1.0, 1.0, 1.0, 1.0);
+        return texture;
+    }
+}
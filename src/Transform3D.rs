@@ -0,0 +1,133 @@
+pub mod Transform {
+    use crate::Math3D::Math::*;
+
+    /**
+     * Placement of an object: scaled first, then rotated, then translated
+     */
+    #[derive(Copy, Clone)]
+    pub struct Transform {
+        pub translation: [f32; 3],
+        pub rotation: Quaternion,
+        pub scale: [f32; 3],
+    }
+
+    impl Transform {
+
+        pub fn new(translation: [f32; 3], rotation: Quaternion, scale: [f32; 3]) -> Transform {
+            return Transform {
+                translation: translation,
+                rotation: rotation,
+                scale: scale,
+            }
+        }
+
+        pub fn identity() -> Transform {
+            return Transform::new([0.0; 3], Quaternion::identity(), [1.0; 3]);
+        }
+
+        pub fn from_translation(translation: [f32; 3]) -> Transform {
+            return Transform::new(translation, Quaternion::identity(), [1.0; 3]);
+        }
+
+        /**
+         * Decomposes an affine matrix into translation, rotation and scale.
+         * Shear can not be represented and is dropped.
+         */
+        pub fn from_matrix(m: &[[f32; 4]; 4]) -> Transform {
+            let axes = upper_3x3(m);
+            let mut scale = [vlength(axes[0]), vlength(axes[1]), vlength(axes[2])];
+            if determinant_3x3(&axes) < 0.0 {
+                scale[0] = -scale[0];
+            }
+            let mut rotation = [[0.0f32; 3]; 3];
+            for column in 0..3 {
+                rotation[column] = if scale[column] == 0.0 { [0.0; 3] } else { vscale(axes[column], 1.0/scale[column]) };
+            }
+            return Transform::new([m[3][0], m[3][1], m[3][2]], Quaternion::from_matrix(&rotation), scale);
+        }
+
+        /**
+         * Rotates around the object's own origin, applied after the current rotation
+         */
+        pub fn rotate(&mut self, rotation: Quaternion) {
+            self.rotation = rotation.mul(&self.rotation).normalize();
+        }
+
+        pub fn rotate_euler(&mut self, angle_XYZ: [f32; 3]) {
+            self.rotate(Quaternion::from_euler(angle_XYZ));
+        }
+
+        pub fn set_uniform_scale(&mut self, factor: f32) {
+            self.scale = [factor; 3];
+        }
+
+        pub fn get_model_matrix(&self) -> [[f32; 4]; 4] {
+            let r = self.rotation.to_matrix();
+            let s = self.scale;
+            let t = self.translation;
+            return [
+                [r[0][0]*s[0], r[0][1]*s[0], r[0][2]*s[0], 0.0],
+                [r[1][0]*s[1], r[1][1]*s[1], r[1][2]*s[1], 0.0],
+                [r[2][0]*s[2], r[2][1]*s[2], r[2][2]*s[2], 0.0],
+                [t[0], t[1], t[2], 1.0],
+            ];
+        }
+
+        /**
+         * Inverse transpose of the rotation and scale part, (R*S)^-T = R*S^-1
+         */
+        pub fn get_normal_matrix(&self) -> [[f32; 3]; 3] {
+            let r = self.rotation.to_matrix();
+            let mut m = r;
+            for column in 0..3 {
+                let inverse_scale = if self.scale[column] == 0.0 { 0.0 } else { 1.0 / self.scale[column] };
+                for row in 0..3 {
+                    m[column][row] = r[column][row] * inverse_scale;
+                }
+            }
+            return m;
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn assert_near(a: [f32; 3], b: [f32; 3]) {
+            assert!(vlength(vsub(a, b)) < 1e-5, "{:?} != {:?}", a, b);
+        }
+
+        fn apply(m: &[[f32; 3]; 3], v: [f32; 3]) -> [f32; 3] {
+            return vadd(vadd(vscale(m[0], v[0]), vscale(m[1], v[1])), vscale(m[2], v[2]));
+        }
+
+        #[test]
+        fn normal_matrix_is_inverse_transpose_under_non_uniform_scale() {
+            let transform = Transform::new([1.0, -2.0, 3.0], Quaternion::from_euler([0.3, -1.1, 0.7]), [2.0, 0.5, 3.0]);
+            let model = transform.get_model_matrix();
+            let normal = transform.get_normal_matrix();
+
+            // N^T * M = I for the upper 3x3 part of the model matrix
+            let m = upper_3x3(&model);
+            for column in 0..3 {
+                for row in 0..3 {
+                    let value = vdot(normal[row], m[column]);
+                    let expected = if row == column { 1.0 } else { 0.0 };
+                    assert!((value - expected).abs() < 1e-5, "N^T M [{}][{}] = {}", column, row, value);
+                }
+            }
+            for column in 0..3 {
+                assert_near(normal[column], normal_matrix(&model)[column]);
+            }
+
+            // Normals stay perpendicular to the surface, which the model matrix alone would not keep
+            let surface = [[1.0, 1.0, 0.0], [0.0, 1.0, 1.0]];
+            let surface_normal = vcross(surface[0], surface[1]);
+            let transformed_normal = apply(&normal, surface_normal);
+            for direction in surface.iter() {
+                assert!(vdot(transformed_normal, apply(&m, *direction)).abs() < 1e-5);
+            }
+            assert!(vdot(apply(&m, surface_normal), apply(&m, surface[0])).abs() > 0.1);
+        }
+    }
+}
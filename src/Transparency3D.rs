@@ -0,0 +1,168 @@
+pub mod Transparency {
+    use glium::Surface;
+    use glium::framebuffer::{MultiOutputFrameBuffer, SimpleFrameBuffer, ToDepthAttachment};
+    use glium::texture::{MipmapsOption, Texture2d, UncompressedFloatFormat};
+
+    use crate::Material3D::Material::*;
+    use crate::Math3D::Math::*;
+    use crate::Mesh3D::Mesh::*;
+    use crate::Screen3D::Screen::*;
+    use crate::Uniform3D::Uniforms::StdUniform;
+    use crate::Shaders;
+
+    /**
+     * How blended materials are composited over the scene
+     */
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum TransparencyMode {
+        // Objects are blended back to front, overlapping triangles of one object may blend out of order
+        Sorted,
+        // Weighted blended order independent transparency, for overlapping glass
+        WeightedBlended,
+    }
+
+    impl TransparencyMode {
+        pub fn next(&self) -> TransparencyMode {
+            return match self {
+                TransparencyMode::Sorted => TransparencyMode::WeightedBlended,
+                TransparencyMode::WeightedBlended => TransparencyMode::Sorted,
+            };
+        }
+    }
+
+    /**
+     * Draw calls with a blended material, sorted back to front from `eye` by the centers of their meshes
+     */
+    pub fn collect_transparent_draw_calls<'a>(draw_calls: &[DrawCall<'a>], materials: &[Material], eye: [f32; 3]) -> Vec<DrawCall<'a>> {
+        let mut transparent: Vec<(f32, DrawCall)> = draw_calls.iter()
+            .filter(|draw_call| draw_call.get_materials().iter().any(|id| materials.get(*id as usize).map_or(false, |material| material.is_blended())))
+            .map(|draw_call| (vlength(vsub(transform_point(&draw_call.model, draw_call.get_center()), eye)), *draw_call))
+            .collect();
+        transparent.sort_by(|a, b| b.0.total_cmp(&a.0));
+        return transparent.into_iter().map(|(_, draw_call)| draw_call).collect();
+    }
+
+    /*
+    Targets of weighted blended transparency, the sum of the weighted premultiplied colors with the revealage
+    in alpha, and the sum of the weights. Colors are summed in full floats as the weights reach thousands.
+    */
+    struct WeightedTargets {
+        accumulation: Texture2d,
+        weight: Texture2d,
+    }
+
+    /**
+     * Draws blended materials over an opaque scene, after everything else was drawn into its color and
+     * depth targets. Transparent surfaces are hidden by the scene but do not write depth themselves.
+     * Draw calls also draw their opaque parts again, which the transparent programs discard.
+     */
+    pub struct TransparencyRenderer {
+        sorted_program: glium::Program,
+        weighted_program: glium::Program,
+        composite_program: glium::Program,
+        screen_quad: ScreenQuad,
+        targets: Option<WeightedTargets>,
+        pub mode: TransparencyMode,
+    }
+
+    impl TransparencyRenderer {
+
+        pub fn new(display: &glium::Display) -> TransparencyRenderer {
+            let sorted_source = Shaders::combine(&[Shaders::SHADER_VERSION, Shaders::LIGHTING, Shaders::MATERIALS, Shaders::FORWARD, Shaders::TRANSPARENT_FRAGMENT_SHADER]);
+            let weighted_source = Shaders::combine(&[Shaders::SHADER_VERSION, Shaders::LIGHTING, Shaders::MATERIALS, Shaders::FORWARD, Shaders::WEIGHTED_TRANSPARENT_FRAGMENT_SHADER]);
+            return TransparencyRenderer {
+                sorted_program: glium::Program::from_source(display, Shaders::VERTEX_SHADER, &sorted_source, None).unwrap(),
+                weighted_program: glium::Program::from_source(display, Shaders::VERTEX_SHADER, &weighted_source, None).unwrap(),
+                composite_program: glium::Program::from_source(display, Shaders::SCREEN_VERTEX_SHADER, Shaders::WEIGHTED_COMPOSITE_FRAGMENT_SHADER, None).unwrap(),
+                screen_quad: ScreenQuad::new(display),
+                targets: None,
+                mode: TransparencyMode::Sorted,
+            }
+        }
+
+        /**
+         * Blends `draw_calls`, usually from `collect_transparent_draw_calls`, onto `color`. `uniform` has to be
+         * set up like for the forward pass, with the clusters of the point lights updated.
+         */
+        pub fn render<'a, D: ToDepthAttachment<'a> + Copy>(&'a mut self, display: &glium::Display, color: &'a Texture2d, depth: D,
+            uniform: &mut StdUniform, draw_calls: &[DrawCall], draw_parameters: &glium::DrawParameters) {
+
+            if draw_calls.is_empty() {
+                return;
+            }
+            let depth_test = glium::Depth {
+                write: false,
+                .. draw_parameters.depth
+            };
+
+            match self.mode {
+                TransparencyMode::Sorted => {
+                    let parameters = glium::DrawParameters {
+                        depth: depth_test,
+                        blend: glium::Blend {
+                            color: glium::BlendingFunction::Addition {
+                                source: glium::LinearBlendingFactor::SourceAlpha,
+                                destination: glium::LinearBlendingFactor::OneMinusSourceAlpha,
+                            },
+                            alpha: glium::BlendingFunction::Addition {
+                                source: glium::LinearBlendingFactor::One,
+                                destination: glium::LinearBlendingFactor::OneMinusSourceAlpha,
+                            },
+                            constant_value: (0.0, 0.0, 0.0, 0.0),
+                        },
+                        .. draw_parameters.clone()
+                    };
+                    let mut framebuffer = SimpleFrameBuffer::with_depth_buffer(display, color, depth).unwrap();
+                    for draw_call in draw_calls.iter() {
+                        uniform.model = draw_call.model;
+                        uniform.normal_matrix = draw_call.normal_matrix;
+                        draw_call.draw(&mut framebuffer, &self.sorted_program, uniform, &parameters).unwrap();
+                    }
+                },
+                TransparencyMode::WeightedBlended => {
+                    let dimensions = (color.width(), color.height());
+                    if self.targets.as_ref().map_or(true, |targets| (targets.weight.width(), targets.weight.height()) != dimensions) {
+                        self.targets = Some(WeightedTargets {
+                            accumulation: Texture2d::empty_with_format(display, UncompressedFloatFormat::F32F32F32F32, MipmapsOption::NoMipmap, dimensions.0, dimensions.1).unwrap(),
+                            weight: Texture2d::empty_with_format(display, UncompressedFloatFormat::F16, MipmapsOption::NoMipmap, dimensions.0, dimensions.1).unwrap(),
+                        });
+                    }
+                    let targets = self.targets.as_ref().unwrap();
+                    SimpleFrameBuffer::new(display, &targets.accumulation).unwrap().clear_color(0.0, 0.0, 0.0, 1.0);
+                    SimpleFrameBuffer::new(display, &targets.weight).unwrap().clear_color(0.0, 0.0, 0.0, 0.0);
+
+                    // Colors and weights add up, the revealage is multiplied by one minus every opacity
+                    let parameters = glium::DrawParameters {
+                        depth: depth_test,
+                        blend: glium::Blend {
+                            color: glium::BlendingFunction::Addition {
+                                source: glium::LinearBlendingFactor::One,
+                                destination: glium::LinearBlendingFactor::One,
+                            },
+                            alpha: glium::BlendingFunction::Addition {
+                                source: glium::LinearBlendingFactor::Zero,
+                                destination: glium::LinearBlendingFactor::OneMinusSourceAlpha,
+                            },
+                            constant_value: (0.0, 0.0, 0.0, 0.0),
+                        },
+                        .. draw_parameters.clone()
+                    };
+                    let outputs = [("accumulation", &targets.accumulation), ("weight", &targets.weight)];
+                    let mut framebuffer = MultiOutputFrameBuffer::with_depth_buffer(display, outputs.iter().cloned(), depth).unwrap();
+                    for draw_call in draw_calls.iter() {
+                        uniform.model = draw_call.model;
+                        uniform.normal_matrix = draw_call.normal_matrix;
+                        draw_call.draw(&mut framebuffer, &self.weighted_program, uniform, &parameters).unwrap();
+                    }
+
+                    let composite_parameters = glium::DrawParameters {
+                        blend: glium::Blend::alpha_blending(),
+                        .. Default::default()
+                    };
+                    let uniforms = uniform! {accumulation: &targets.accumulation, weight: &targets.weight};
+                    self.screen_quad.draw(&mut SimpleFrameBuffer::new(display, color).unwrap(), &self.composite_program, &uniforms, &composite_parameters).unwrap();
+                },
+            }
+        }
+    }
+}
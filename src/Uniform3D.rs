@@ -2,27 +2,31 @@
 mod Lights3D;
 
 pub mod Uniforms {
-    use crate::Lights3D::Lights::*;
-    use crate::Material3D::Material::*;
-    use glium::texture::SrgbTexture2dArray;
+    use crate::LightBuffer3D::LightBuffer::*;
+    use crate::Cluster3D::Cluster::*;
+    use crate::MaterialBuffer3D::MaterialBuffer::*;
+    use crate::Shadow3D::Shadow::*;
+    use crate::Ibl3D::Ibl::*;
+    use glium::texture::{SrgbTexture2dArray, Texture2d, Texture2dArray};
     use glium::uniforms::UniformValue;
 
     const MAX_SAMPLERS: i32 = 32;
 
     pub struct StdUniform<'a> {
         pub model: [[f32; 4]; 4],
+        pub normal_matrix: [[f32; 3]; 3],
         pub view: [[f32; 4]; 4],
         pub perspective: [[f32; 4]; 4],
         pub u_light: [f32; 3],
         pub v_view: [f32; 3],
         pub textures: &'a SrgbTexture2dArray,
-        pub materials: [Material; MAX_MATERIALS as usize],
-        pub num_directional_lights: i32,
-        pub directional_lights: [DirectionalLight; MAX_DIRECTIONAL_LIGHTS as usize],
-        pub num_point_lights: i32,
-        pub point_lights: [PointLight; MAX_POINT_LIGHTS as usize],
-        pub num_spot_lights: i32,
-        pub spot_lights: [SpotLight; MAX_SPOT_LIGHTS as usize],
+        pub data_textures: &'a Texture2dArray,
+        pub materials: &'a MaterialBuffer,
+        pub lights: &'a LightBuffer,
+        pub clusters: &'a ClusterGrid,
+        pub shadows: &'a ShadowRenderer,
+        pub ambient_occlusion: &'a Texture2d,
+        pub environment: &'a Environment,
     }
 
     impl glium::uniforms::Uniforms for StdUniform<'_> {
@@ -35,6 +39,7 @@ pub mod Uniforms {
                 [self.model[3][0], self.model[3][1], self.model[3][2], self.model[3][3]]
                 ]
             ));
+            f("normal_matrix", UniformValue::Mat3(self.normal_matrix));
             f("view", UniformValue::Mat4(
                 [
                 [self.view[0][0], self.view[0][1], self.view[0][2], self.view[0][3]],
@@ -59,49 +64,53 @@ pub mod Uniforms {
             ));
 
             f("textures", UniformValue::SrgbTexture2dArray(&self.textures, None));
+            f("data_textures", UniformValue::Texture2dArray(&self.data_textures, None));
 
-            for i in 0..MAX_MATERIALS {
-                f(&format!("materials[{}].diffuse",i), UniformValue::SignedInt(self.materials[i as usize].diffuse));
-                f(&format!("materials[{}].specular",i), UniformValue::SignedInt(self.materials[i as usize].specular));
-                f(&format!("materials[{}].shininess",i), UniformValue::Float(self.materials[i as usize].shininess));
-            }
+            f("materials", UniformValue::BufferTexture(self.materials.get_texture().as_buffer_texture_ref()));
 
-            f("num_directional_lights", UniformValue::SignedInt(self.num_directional_lights));
+            f("lights", UniformValue::BufferTexture(self.lights.get_texture().as_buffer_texture_ref()));
 
-            for i in 0..MAX_DIRECTIONAL_LIGHTS {
-                f(&format!("directional_lights[{}].direction",i), UniformValue::Vec3(self.directional_lights[i as usize].direction));
-                f(&format!("directional_lights[{}].ambient_color",i), UniformValue::Vec3(self.directional_lights[i as usize].ambient_color));
-                f(&format!("directional_lights[{}].diffuse_color",i), UniformValue::Vec3(self.directional_lights[i as usize].diffuse_color));
-                f(&format!("directional_lights[{}].specular_color",i), UniformValue::Vec3(self.directional_lights[i as usize].specular_color));
-            }
+            f("cluster_grid", UniformValue::BufferTexture(self.clusters.get_grid().as_buffer_texture_ref()));
+            f("cluster_lights", UniformValue::BufferTexture(self.clusters.get_light_indices().as_buffer_texture_ref()));
+            f("cluster_dimensions", UniformValue::IntVec3(self.clusters.get_dimensions()));
+            f("cluster_depth_range", UniformValue::Vec2(self.clusters.get_depth_range()));
 
-            f("num_point_lights", UniformValue::SignedInt(self.num_point_lights));
+            f("ambient_occlusion", UniformValue::Texture2d(self.ambient_occlusion, None));
 
-            for i in 0..MAX_POINT_LIGHTS {
-                f(&format!("point_lights[{}].position",i), UniformValue::Vec3(self.point_lights[i as usize].position));
-                f(&format!("point_lights[{}].constant",i), UniformValue::Float(self.point_lights[i as usize].constant));
-                f(&format!("point_lights[{}].linear",i), UniformValue::Float(self.point_lights[i as usize].linear));
-                f(&format!("point_lights[{}].quadratic",i), UniformValue::Float(self.point_lights[i as usize].quadratic));
-                f(&format!("point_lights[{}].ambient_color",i), UniformValue::Vec3(self.point_lights[i as usize].ambient_color));
-                f(&format!("point_lights[{}].diffuse_color",i), UniformValue::Vec3(self.point_lights[i as usize].diffuse_color));
-                f(&format!("point_lights[{}].specular_color",i), UniformValue::Vec3(self.point_lights[i as usize].specular_color));
+            f("environment_enabled", UniformValue::Bool(self.environment.is_active()));
+            f("environment_intensity", UniformValue::Float(self.environment.intensity));
+            f("irradiance_map", UniformValue::Cubemap(self.environment.get_irradiance(), Some(get_cubemap_sampler())));
+            f("prefiltered_map", UniformValue::Cubemap(self.environment.get_prefiltered(), Some(get_cubemap_sampler())));
+            f("prefiltered_max_level", UniformValue::Float(self.environment.get_prefiltered_max_level()));
+            f("brdf_lut", UniformValue::Texture2d(self.environment.get_brdf_lut(), Some(get_cubemap_sampler())));
+
+            // Every shadow sampler gets a depth texture, unused ones the empty map
+            for i in 0..MAX_DIRECTIONAL_SHADOWS {
+                let (texture, light_space) = match self.shadows.get_directional_map(i as usize) {
+                    Some(map) => (map.get_texture(), map.get_light_space()),
+                    None => (self.shadows.get_empty_map(), [[0.0; 4]; 4]),
+                };
+                f(&format!("directional_shadow_maps[{}]",i), UniformValue::DepthTexture2d(texture, Some(get_shadow_sampler())));
+                f(&format!("directional_shadow_matrices[{}]",i), UniformValue::Mat4(light_space));
             }
 
-            f("num_spot_lights", UniformValue::SignedInt(self.num_spot_lights));
+            for i in 0..MAX_SPOT_SHADOWS {
+                let (texture, light_space) = match self.shadows.get_spot_map(i as usize) {
+                    Some(map) => (map.get_texture(), map.get_light_space()),
+                    None => (self.shadows.get_empty_map(), [[0.0; 4]; 4]),
+                };
+                f(&format!("spot_shadow_maps[{}]",i), UniformValue::DepthTexture2d(texture, Some(get_shadow_sampler())));
+                f(&format!("spot_shadow_matrices[{}]",i), UniformValue::Mat4(light_space));
+            }
 
-            for i in 0..MAX_SPOT_LIGHTS {
-                f(&format!("spot_lights[{}].position",i), UniformValue::Vec3(self.spot_lights[i as usize].position));
-                f(&format!("spot_lights[{}].direction",i), UniformValue::Vec3(self.spot_lights[i as usize].direction));
-                f(&format!("spot_lights[{}].cutoff",i), UniformValue::Float(self.spot_lights[i as usize].cutoff));
-                f(&format!("spot_lights[{}].outer_cutoff",i), UniformValue::Float(self.spot_lights[i as usize].outer_cutoff));
-                f(&format!("spot_lights[{}].constant",i), UniformValue::Float(self.spot_lights[i as usize].constant));
-                f(&format!("spot_lights[{}].linear",i), UniformValue::Float(self.spot_lights[i as usize].linear));
-                f(&format!("spot_lights[{}].quadratic",i), UniformValue::Float(self.spot_lights[i as usize].quadratic));
-                f(&format!("spot_lights[{}].ambient_color",i), UniformValue::Vec3(self.spot_lights[i as usize].ambient_color));
-                f(&format!("spot_lights[{}].diffuse_color",i), UniformValue::Vec3(self.spot_lights[i as usize].diffuse_color));
-                f(&format!("spot_lights[{}].specular_color",i), UniformValue::Vec3(self.spot_lights[i as usize].specular_color));
+            for i in 0..MAX_POINT_SHADOWS {
+                let texture = match self.shadows.get_point_map(i as usize) {
+                    Some(map) => map.get_texture(),
+                    None => self.shadows.get_empty_cube_map(),
+                };
+                f(&format!("point_shadow_maps[{}]",i), UniformValue::DepthCubemap(texture, Some(get_shadow_sampler())));
             }
         }
     }
 
-}
\ No newline at end of file
+}
@@ -1,23 +1,68 @@
 mod Base3D;
+mod Bounds3D;
+mod Bvh3D;
 mod Camera3D;
 mod Lights3D;
 mod Uniform3D;  
 mod Material3D;
 mod GraphicsLoader2D;
+mod ObjLoader3D;
+mod GltfLoader3D;
+mod Mesh3D;
+mod Math3D;
+mod Transform3D;
+mod Scene3D;
+mod Shadow3D;
+mod LightBuffer3D;
+mod MaterialBuffer3D;
+mod Cluster3D;
+mod Screen3D;
+mod Deferred3D;
+mod Hdr3D;
+mod Bloom3D;
+mod PostProcess3D;
+mod Ssao3D;
+mod Ibl3D;
+mod Skybox3D;
+mod Transparency3D;
+mod Gizmo3D;
+mod Culling3D;
 mod Shaders;
 
 #[macro_use]
 extern crate glium;
 extern crate image;
 
+use std::rc::Rc;
 use glium::{glutin, Surface, Frame};
 
 use crate::Base3D::General::*;
+use crate::Bvh3D::Bvh::*;
 use crate::Camera3D::Camera;
 use crate::Lights3D::Lights::*;
 use crate::Uniform3D::Uniforms::StdUniform;
 use crate::Material3D::Material::*;
 use crate::GraphicsLoader2D::GraphicsLoader;
+use crate::ObjLoader3D::ObjLoader::*;
+use crate::GltfLoader3D::GltfLoader::*;
+use crate::Mesh3D::Mesh::*;
+use crate::Math3D::Math::*;
+use crate::Transform3D::Transform::*;
+use crate::Scene3D::Scene::*;
+use crate::Shadow3D::Shadow::*;
+use crate::LightBuffer3D::LightBuffer::*;
+use crate::MaterialBuffer3D::MaterialBuffer::*;
+use crate::Cluster3D::Cluster::*;
+use crate::Deferred3D::Deferred::*;
+use crate::Hdr3D::Hdr::*;
+use crate::Bloom3D::Bloom::*;
+use crate::PostProcess3D::PostProcess::*;
+use crate::Ssao3D::Ssao::*;
+use crate::Ibl3D::Ibl::*;
+use crate::Skybox3D::Skybox::*;
+use crate::Transparency3D::Transparency::*;
+use crate::Gizmo3D::Gizmo::*;
+use crate::Culling3D::Culling::*;
 
 
 enum Action {
@@ -32,19 +77,127 @@ enum Action {
 const CAMERA_MOVE_SPEED: f32 = 0.01;
 const CAMERA_ROTATE_SPEED: f32 = 0.1;
 
+/**
+ * How close the camera may get to meshes
+ */
+const CAMERA_RADIUS: f32 = 0.03;
+
+/**
+ * First material slot used by imported models
+ */
+const FIRST_MODEL_MATERIAL: i32 = 7;
+
 fn main() {
     // Building window and event loop
     let mut event_loop = glutin::event_loop::EventLoop::new();
     let display = get_display(&event_loop);
     let mut is_fullscreen: bool = false;
 
-    // Load textures
-    let textures = GraphicsLoader::load_all_textures(&display);
+    // Read command line options, the first argument that is neither an option nor its value is a model to load
+    let arguments: Vec<String> = std::env::args().skip(1).collect();
+    let light_demo = arguments.iter().any(|argument| argument == "--light-demo");
+    let deferred = arguments.iter().any(|argument| argument == "--deferred");
+    let lut_path = arguments.iter().position(|argument| argument == "--lut").and_then(|i| arguments.get(i + 1));
+    let environment_path = arguments.iter().position(|argument| argument == "--environment").and_then(|i| arguments.get(i + 1));
+    let skybox_path = arguments.iter().position(|argument| argument == "--skybox").and_then(|i| arguments.get(i + 1));
+    let grid_size: usize = arguments.iter().position(|argument| argument == "--cubes").and_then(|i| arguments.get(i + 1)).and_then(|size| size.parse().ok()).unwrap_or(16);
+    let model_path = arguments.iter().enumerate()
+        .find(|(i, argument)| !argument.starts_with("--") && (*i == 0 || !["--lut", "--environment", "--skybox", "--cubes"].contains(&arguments[i - 1].as_str())))
+        .map(|(_, argument)| argument);
+
+    // Load textures and an optional model given on the command line
+    let mut images = GraphicsLoader::load_default_images();
+    let mut data_images = GraphicsLoader::load_default_data_images();
+    let mut model_shape: Option<AShape> = None;
+    let mut model_scene: Option<GltfScene> = None;
+    let mut model_materials: Vec<Material> = Vec::new();
+    if let Some(path) = model_path {
+        let path = std::path::Path::new(path);
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gltf") | Some("glb") => match GltfLoader::load(path, FIRST_MODEL_MATERIAL, images.len() as i32, data_images.len() as i32) {
+                Ok(mut scene) => {
+                    images.extend(std::mem::take(&mut scene.images));
+                    data_images.extend(std::mem::take(&mut scene.data_images));
+                    model_materials = std::mem::take(&mut scene.materials);
+                    model_scene = Some(scene);
+                },
+                Err(error) => println!("Could not load scene: {}", error),
+            },
+            _ => match ObjLoader::load(path, FIRST_MODEL_MATERIAL, images.len() as i32) {
+                Ok(model) => {
+                    images.extend(model.images);
+                    model_materials = model.materials;
+                    model_shape = Some(model.shape);
+                },
+                Err(error) => println!("Could not load model: {}", error),
+            },
+        }
+    }
+    let textures = GraphicsLoader::create_texture_array(&display, images);
+    let data_textures = GraphicsLoader::create_data_texture_array(&display, data_images);
+
+    // Create materials, color maps index `textures` and normal, height and PBR data maps `data_textures`
+    let mut materials = [
+        Material::new(0, 0, 16.0); MAX_MATERIALS as usize
+    ];
+    materials[1] = Material::new(1, 1, 16.0);
+    materials[2] = Material::new(2, 2, 16.0);
+    materials[3] = Material::new(3, 4, 32.0);
+    materials[1].normal = 0;
+    materials[1].height = 2;
+    materials[1].height_scale = 0.02;
+    materials[1].parallax_shadows = true;
+    materials[2].normal = 1;
+    materials[4] = Material::new_pbr(NO_TEXTURE, [1.0, 0.78, 0.34], 1.0, 0.35);
+    materials[5] = Material::new_pbr(NO_TEXTURE, [0.3, 0.55, 1.0], 0.0, 0.05);
+    materials[5].alpha_mode = AlphaMode::Blend;
+    materials[5].opacity = 0.35;
+    materials[6] = Material::new_pbr(NO_TEXTURE, [1.0, 0.6, 0.15], 0.0, 0.05);
+    materials[6].alpha_mode = AlphaMode::Blend;
+    materials[6].opacity = 0.5;
+    for (i, material) in model_materials.iter().enumerate() {
+        materials[FIRST_MODEL_MATERIAL as usize + i] = *material;
+    }
+    let mut material_buffer = MaterialBuffer::new(&display);
+    material_buffer.update(&materials);
 
     // Prepare program and draw parameters
-    let program = glium::Program::from_source(&display, Shaders::VERTEX_SHADER, Shaders::FRAGMENT_SHADER, None).unwrap();
-    let program_lights = glium::Program::from_source(&display, Shaders::VERTEX_SHADER, Shaders::FRAGMENT_SHADER_LIGHT, None).unwrap();
+    let fragment_shader = Shaders::combine(&[Shaders::SHADER_VERSION, Shaders::LIGHTING, Shaders::MATERIALS, Shaders::FORWARD, Shaders::FRAGMENT_SHADER]);
+    let program = glium::Program::from_source(&display, Shaders::VERTEX_SHADER, &fragment_shader, None).unwrap();
+    let unlit_fragment_shader = Shaders::combine(&[Shaders::SHADER_VERSION, Shaders::LIGHTING, Shaders::MATERIALS, Shaders::FRAGMENT_SHADER_LIGHT]);
+    let program_lights = glium::Program::from_source(&display, Shaders::VERTEX_SHADER, &unlit_fragment_shader, None).unwrap();
     let draw_parameters = get_draw_parameters();
+    let mut shadow_renderer = ShadowRenderer::new(&display);
+    let mut light_buffer = LightBuffer::new(&display);
+    let mut cluster_grid = ClusterGrid::new(&display);
+    let mut deferred_renderer = if deferred { Some(DeferredRenderer::new(&display)) } else { None };
+    let mut hdr_target: Option<HdrTarget> = None;
+    let mut ssao_renderer = SsaoRenderer::new(&display, !deferred);
+    let mut tone_mapper = ToneMapper::new(&display);
+    let mut bloom_renderer = BloomRenderer::new(&display);
+    let mut post_process = PostProcessStack::with_default_passes(&display);
+    let skybox_renderer = SkyboxRenderer::new(&display);
+    let mut transparency_renderer = TransparencyRenderer::new(&display);
+    let mut light_gizmo_renderer = LightGizmoRenderer::new(&display);
+    let mut frustum_culler = FrustumCuller::new();
+    let mut skybox = skybox_path.and_then(|path| load_skybox(&display, std::path::Path::new(path)).map_err(|error| println!("{}", error)).ok());
+    let mut environment = match environment_path {
+        Some(path) => Environment::load(&display, std::path::Path::new(path)).unwrap_or_else(|error| {
+            println!("{}", error);
+            Environment::empty(&display)
+        }),
+        // Without an environment image the sky lights the scene, so reflective materials mirror it
+        None => match skybox.take() {
+            Some(cubemap) => Environment::from_cubemap(&display, cubemap),
+            None => Environment::empty(&display),
+        },
+    };
+    if let Some(path) = lut_path {
+        match load_cube_lut(&display, std::path::Path::new(path)) {
+            Ok(lut) => post_process.get_pass_mut(COLOR_GRADING).unwrap().set_value("lut", PassValue::Texture3d(Rc::new(lut))),
+            Err(error) => println!("Could not load LUT: {}", error),
+        }
+    }
 
     // Prepare fps camera
     let mut fps_camera = Camera::new([0.0, 0.0, -1.0], [0.0, 1.0, 0.0], 0.0, 90.0, CAMERA_MOVE_SPEED, CAMERA_ROTATE_SPEED);
@@ -53,28 +206,123 @@ fn main() {
     let scene: AShape = build_scene();
     let light_cube: AShape = Cube::new([-0.5, 0.6, -0.05], 0.1, 0);
 
-    // Prepare a rotating "dynamic" cube
-    let mut dynamic_cube: AShape = Cube::new([0.0, 0.4, 0.0], 0.2, 2);
-
-    // Prepare a sphere for testing
-    let mut sphere: AShape = Sphere::new([0.0, 0.3, 0.0], 0.1, 4, 0);
-
-    // Try out many cubes
-    let mut many_cubes: Vec<AShape> = Vec::new();
-    let x_count = 16;
-    let y_count = 16;
-    for i in 0..x_count {
-        for j in 0..y_count {
-            let mut cube = Cube::new([(i as f32 - x_count as f32/2f32)*0.1f32, 0.8f32, (j as f32 - y_count as f32/2f32)*0.1f32 ], 0.09f32, 1);
-            many_cubes.push(cube);
+    // Weld all static geometry into one indexed mesh and upload it once
+    let mut static_shapes: Vec<&AShape> = vec![&scene];
+    if let Some(model_shape) = &model_shape {
+        static_shapes.push(model_shape);
+    }
+    let static_scene: IndexedShape = IndexedShape::from_shape(&combine_shapes(static_shapes));
+    let static_mesh = Rc::new(GpuMesh::new_static(&display, &static_scene));
+
+    // Meshes built around the origin and placed by the transforms of their nodes
+    let dynamic_cube_mesh = Rc::new(GpuMesh::new_static(&display, &IndexedShape::from_shape(&Cube::new([-0.5, -0.5, -0.5], 1.0, 2))));
+    let small_cube_mesh = Rc::new(GpuMesh::new_static(&display, &IndexedShape::from_shape(&Cube::new([-0.5, -0.5, -0.5], 1.0, 1))));
+    let blue_glass_mesh = Rc::new(GpuMesh::new_static(&display, &IndexedShape::from_shape(&Cube::new([-0.5, -0.5, -0.5], 1.0, 5))));
+    let amber_glass_mesh = Rc::new(GpuMesh::new_static(&display, &IndexedShape::from_shape(&Cube::new([-0.5, -0.5, -0.5], 1.0, 6))));
+
+    /*
+    Build the scene graph
+    */
+    let mut scene_graph = SceneGraph::new();
+    scene_graph.add_mesh("static scene", &static_mesh, Transform::identity(), None);
+    let mut sun = DirectionalLight::new([-1.0, -0.6, 0.0], [1.0, 0.2, 0.2]);
+    sun.shadow = ShadowSettings::new(0.0001, 2048);
+    scene_graph.add_light("sun", SceneLight::Directional(sun), Transform::identity(), None);
+
+    // Point light where the light cube used to be, shown by its light gizmo
+    let mut point_light = PointLight::new([0.0; 3], [1.0, 1.0, 1.0]);
+    point_light.shadow = ShadowSettings::new(0.0002, 512);
+    scene_graph.add_light("light", SceneLight::Point(point_light), Transform::from_translation(light_cube.centroid()), None);
+
+    // Rotating "dynamic" cube
+    let dynamic_cube = scene_graph.add_mesh("dynamic cube", &dynamic_cube_mesh, Transform::from_translation([0.1, 0.5, 0.1]), None);
+
+    // Sphere shaded as metal with the PBR material, its surface ripples as its vertices are rewritten every frame.
+    // It stays unwelded, so the vertex count does not change when the flat normals of the rippled triangles split shared corners.
+    let sphere_shape = Sphere::new([0.0, 0.0, 0.0], 0.1, 4, 4);
+    let mut rippled_sphere_shape = sphere_shape.clone();
+    let rippling_sphere = scene_graph.add_mesh("rippling sphere", &Rc::new(GpuMesh::new_persistent(&display, &IndexedShape::from_shape_unwelded(&sphere_shape))), Transform::from_translation([0.0, 0.3, 0.0]), None);
+
+    // Try out many cubes, all drawn with one instanced call. The grid keeps the size of the room, `--cubes` sets its cells per side.
+    let cell_size = 1.6 / grid_size as f32;
+    let mut many_cubes: Vec<Transform> = Vec::new();
+    let mut cube_instances: Vec<Instance> = Vec::new();
+    for i in 0..grid_size {
+        for j in 0..grid_size {
+            let mut transform = Transform::from_translation([(i as f32 - grid_size as f32/2f32 + 0.5)*cell_size - 0.005, 0.0, (j as f32 - grid_size as f32/2f32 + 0.5)*cell_size - 0.005]);
+            transform.set_uniform_scale(0.9 * cell_size);
+            let mut instance = Instance::from_transform(&transform);
+            let u = i as f32 / grid_size as f32;
+            let v = j as f32 / grid_size as f32;
+            instance.instance_color = [0.6 + 0.4 * u, 0.6 + 0.4 * v, 1.0 - 0.4 * u];
+            // Every seventh cube is gold
+            if (i * grid_size + j) % 7 == 0 {
+                instance.instance_material = 4;
+            }
+            many_cubes.push(transform);
+            cube_instances.push(instance);
+        }
+    }
+    let cube_grid = scene_graph.add_instanced_mesh("cube grid", &small_cube_mesh, InstanceBuffer::new(&display, &cube_instances),
+        Transform::from_translation([0.0, 0.845, 0.0]), None);
+
+    // A lamp whose spot light follows its swinging arm
+    let lamp = scene_graph.add_node("lamp", Transform::from_translation([0.6, -0.2, 0.6]), None);
+    let mut lamp_base_transform = Transform::identity();
+    lamp_base_transform.scale = [0.12, 0.02, 0.12];
+    scene_graph.add_mesh("lamp base", &dynamic_cube_mesh, lamp_base_transform, Some(lamp));
+    let lamp_arm = scene_graph.add_node("lamp arm", Transform::identity(), Some(lamp));
+    let mut lamp_pole_transform = Transform::from_translation([0.0, 0.2, 0.0]);
+    lamp_pole_transform.scale = [0.02, 0.4, 0.02];
+    scene_graph.add_mesh("lamp pole", &dynamic_cube_mesh, lamp_pole_transform, Some(lamp_arm));
+    let mut lamp_head_transform = Transform::from_translation([0.0, 0.4, 0.0]);
+    lamp_head_transform.rotation = Quaternion::look_rotation([0.0, -1.0, 0.3], [0.0, 0.0, 1.0]);
+    let mut lamp_light = SpotLight::new([0.0; 3], [0.0, 0.0, 1.0], 20.0, [0.9, 0.8, 0.5]);
+    lamp_light.shadow = ShadowSettings::new(0.00005, 1024);
+    let lamp_head = scene_graph.add_light("lamp head", SceneLight::Spot(lamp_light), lamp_head_transform, Some(lamp_arm));
+    let mut lamp_shade_transform = Transform::from_translation([0.0, 0.0, -0.025]);
+    lamp_shade_transform.scale = [0.06, 0.06, 0.04];
+    scene_graph.add_mesh("lamp shade", &dynamic_cube_mesh, lamp_shade_transform, Some(lamp_head));
+
+    // Two overlapping glass panes in front of the cubes
+    let mut glass_transform = Transform::from_translation([-0.1, 0.0, -0.45]);
+    glass_transform.scale = [0.3, 0.3, 0.01];
+    scene_graph.add_mesh("blue glass", &blue_glass_mesh, glass_transform, None);
+    glass_transform.translation = [0.1, 0.05, -0.5];
+    scene_graph.add_mesh("amber glass", &amber_glass_mesh, glass_transform, None);
+
+    // Optionally fill the room with hundreds of small colored point lights circling in two rings
+    let mut light_rings: Vec<NodeId> = Vec::new();
+    if light_demo {
+        for ring in 0..2 {
+            let ring_node = scene_graph.add_node("light ring", Transform::identity(), None);
+            for i in 0..160 {
+                let angle = i as f32 / 160.0 * std::f32::consts::TAU;
+                let radius = 0.25 + 0.7 * ((i * 7 + ring * 3) % 11) as f32 / 10.0;
+                let height = -0.15 + 0.25 * ((i * 5 + ring) % 7) as f32 / 6.0;
+                let color = [
+                    0.5 + 0.5 * (angle).sin(),
+                    0.5 + 0.5 * (angle + 2.1).sin(),
+                    0.5 + 0.5 * (angle + 4.2).sin(),
+                ];
+                let light = PointLight::new_with_parameters([0.0; 3], color, 18.0, 400.0);
+                scene_graph.add_light("ring light", SceneLight::Point(light), Transform::from_translation([radius * angle.cos(), height, radius * angle.sin()]), Some(ring_node));
+            }
+            light_rings.push(ring_node);
         }
     }
 
+    // An imported glTF scene keeps its nodes, so they can be found by name
+    if let Some(model_scene) = &model_scene {
+        model_scene.add_to_scene_graph(&display, &mut scene_graph, None);
+    }
+
     // Describe global lighting
     let global_light: [f32; 3] = light_cube.centroid();
 
     // Run event loop
     let mut t: f32 = 0.0;
+    let mut selected_node: Option<NodeId> = None;
     let mut last_dt = std::time::Instant::now();
     start_loop(event_loop, move |events| {
 
@@ -87,56 +335,60 @@ fn main() {
         fps_camera.update_position();
         fps_camera.update_direction();
 
+        // Push the camera back out of meshes it moved into, as they were placed last frame
+        if let Some((_, hit)) = scene_graph.find_nearest(fps_camera.get_position(), CAMERA_RADIUS) {
+            if hit.distance > 0.0 {
+                fps_camera.set_position(vadd(hit.point, vscale(vsub(fps_camera.get_position(), hit.point), CAMERA_RADIUS / hit.distance)));
+            }
+        }
+
         /*
         Update all shapes / Game objects
         */
-        dynamic_cube.rotate_mut_O([0.01, 0.02, 0.03]); // This is mutable rotation
-        let scaled_dynamic_cube = dynamic_cube.scale_O((t*0.08).sin()*1.5f32); // This is immutable scaling
-
-        for cube in many_cubes.iter_mut() {
-            cube.rotate_mut_O([0.01, 0.01, 0.01]);
+        if let Some(transform) = scene_graph.get_transform_mut(dynamic_cube) {
+            transform.rotate_euler([0.01, 0.02, 0.03]);
+            transform.set_uniform_scale(0.2f32 * (t*0.08).sin()*1.5f32);
         }
 
-        /*
-        Combine all shapes (static scene and dynamic moving shapes) into one "package"
-        to later place into single vertex buffer
-        */
-        
-        let mut shapes: Vec<&AShape> = Vec::new();
-        shapes.push(&scene);
-        shapes.push(&scaled_dynamic_cube);
-        shapes.extend(many_cubes.iter());
-        shapes.push(&sphere);
-        
-        let shape = combine_shapes(shapes);
+        for (rippled, vertex) in rippled_sphere_shape.vertices.iter_mut().zip(sphere_shape.get_vertices().iter()) {
+            rippled.position = vscale(vertex.position, 1.0 + 0.08 * (vertex.position[1] * 60.0 + t * 0.3).sin());
+        }
+        rippled_sphere_shape.flat_normals_mut();
+        // The scene graph holds the only reference to the mesh, so it can be written in place
+        if let Some(mesh) = scene_graph.get_mut(rippling_sphere).and_then(|node| node.mesh.as_mut()).and_then(Rc::get_mut) {
+            if let Err(error) = mesh.update_vertices(&IndexedShape::from_shape_unwelded(&rippled_sphere_shape)) {
+                println!("Could not update the rippling sphere: {}", error);
+            }
+        }
 
-        /*
-        Create Directional and Point lights
-        */
-        let mut directional_lights = [    
-            DirectionalLight::new([0.0, 1.0, 0.0], [0.0, 0.0, 0.0]); MAX_DIRECTIONAL_LIGHTS as usize
-        ];
-        directional_lights[0] = DirectionalLight::new([-1.0, -0.6, 0.0], [1.0, 0.2, 0.2]);
+        let many_cubes_rotation = Quaternion::from_euler([0.01, 0.01, 0.01]);
+        for (cube, instance) in many_cubes.iter_mut().zip(cube_instances.iter_mut()) {
+            cube.rotate(many_cubes_rotation);
+            instance.instance_model = cube.get_model_matrix();
+            instance.instance_normal_matrix = cube.get_normal_matrix();
+        }
+        if let Some(instances) = scene_graph.get_mut(cube_grid).and_then(|node| node.instances.as_mut()) {
+            instances.update(&display, &cube_instances);
+        }
 
-        let mut point_lights = [
-            PointLight::new([0.0, 0.0, 0.0], [0.0, 0.0, 0.0]); MAX_POINT_LIGHTS as usize
-        ];
-        point_lights[0] = PointLight::new(light_cube.centroid(), [1.0, 1.0, 1.0]);
+        for (i, ring) in light_rings.iter().enumerate() {
+            let direction = if i % 2 == 0 { 1.0 } else { -1.0 };
+            if let Some(transform) = scene_graph.get_transform_mut(*ring) {
+                transform.rotate_euler([0.0, 0.004 * direction, 0.0]);
+            }
+        }
 
-        let mut spot_lights = [
-            SpotLight::new([0.0, 0.0, 0.0], [0.0, 0.0, 0.0], 0.0, [0.0, 1.0, 0.0]); MAX_SPOT_LIGHTS as usize
-        ];
-        spot_lights[0] = SpotLight::new(fps_camera.get_position(), fps_camera.get_direction(), 6.0f32, [0.6, 0.6, 0.6]);
+        scene_graph.set_transform(lamp_arm, Transform::new([0.0; 3], Quaternion::from_euler([0.0, t*0.05, 0.3*(t*0.03).sin()]), [1.0; 3]));
 
         /*
-        Create materials
+        Collect draw calls and lights, each draw call with its own model matrix
         */
-        let mut materials = [
-            Material::new(0, 0, 16.0); MAX_MATERIALS as usize
-        ];
-        materials[1] = Material::new(1, 1, 16.0);
-        materials[2] = Material::new(2, 2, 16.0);
-        materials[3] = Material::new(3, 4, 32.0);
+        scene_graph.update_world_transforms();
+        let draw_calls: Vec<DrawCall> = scene_graph.collect_draw_calls(false);
+        let mut lights = scene_graph.collect_lights();
+
+        // The camera carries a flash light
+        lights.spot_lights.insert(0, SpotLight::new(fps_camera.get_position(), fps_camera.get_direction(), 6.0f32, [0.6, 0.6, 0.6]));
 
         /*
         Beginning buffer and uniform building
@@ -146,32 +398,86 @@ fn main() {
         let mut target = display.draw(); 
 
         // Compute model, view and perspective matrices
-        let model = get_uniform(&[0.0, 0.0, 0.0], &[1.0, 1.0, 1.0], &[0.0, 0.0, 0.0]);
+        let model = IDENTITY;
         let view = fps_camera.get_view_matrix();
         let perspective = get_perspective_matrix(&target);
-        
-        // Create main vertex buffers
-        let vertex_buffer = glium::VertexBuffer::new(&display, &shape.get_vertices()).unwrap();
-        let normal_buffer = glium::VertexBuffer::new(&display, &shape.get_normals()).unwrap();
-        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
 
-        // Create vertex buffers for light cubes
-        let lights_vertex_buffer = glium::VertexBuffer::new(&display, &light_cube.get_vertices()).unwrap();
-        let lights_normal_buffer = glium::VertexBuffer::new(&display, &light_cube.get_normals()).unwrap();
+        // Render shadow maps of the lights that cast shadows, they need the objects outside of the view as well
+        shadow_renderer.update(&display, &lights, &view, &perspective, &draw_calls);
+
+        // Everything else only draws what the camera sees
+        frustum_culler.reset_stats();
+        let draw_calls = frustum_culler.cull(&scene_graph, false, &view, &perspective);
+        let unlit_draw_calls = frustum_culler.cull(&scene_graph, true, &view, &perspective);
+        let transparent_draw_calls = collect_transparent_draw_calls(&draw_calls, &materials, fps_camera.get_position());
+
+        // Upload the lights
+        light_buffer.update(&display, &lights, &shadow_renderer);
+        // The deferred path culls point lights by their light volumes, but lights blended materials forward
+        if deferred_renderer.is_none() || !transparent_draw_calls.is_empty() {
+            cluster_grid.update(&display, &lights.point_lights, &view, &perspective);
+        }
+
+        // Ambient occlusion, the deferred path computes it from its G-buffer
+        let dimensions = target.get_dimensions();
+        ssao_renderer.resize(&display, dimensions);
+        if deferred_renderer.is_none() {
+            ssao_renderer.render_prepass(&display, &draw_calls, &view, &perspective, &draw_parameters);
+        }
 
         // Build uniform
-        let uniform = StdUniform {
-            model: model, view: view, perspective: perspective, u_light: global_light, v_view: fps_camera.get_position(),
-            textures: &textures, materials: materials, num_directional_lights: 1,  directional_lights: directional_lights,
-            num_point_lights: 1, point_lights: point_lights, num_spot_lights: 1, spot_lights: spot_lights
+        let mut uniform = StdUniform {
+            model: model, normal_matrix: normal_matrix(&model), view: view, perspective: perspective, u_light: global_light, v_view: fps_camera.get_position(),
+            textures: &textures, data_textures: &data_textures, materials: &material_buffer, lights: &light_buffer, clusters: &cluster_grid, shadows: &shadow_renderer,
+            ambient_occlusion: ssao_renderer.get_occlusion(), environment: &environment
         };
 
         /*
-        Draw everything
+        Draw everything, the sky is drawn where nothing else was and blended materials last over it
         */
-        target.clear_color_and_depth((0.0, 0.0, 0.0, 1.0), 1.0); // Clear color and depth   
-        target.draw((&vertex_buffer, &normal_buffer), &indices, &program, &uniform, &draw_parameters).unwrap();
-        target.draw((&lights_vertex_buffer, &lights_normal_buffer), &indices, &program_lights, &uniform! {model: model, view: view, perspective: perspective}, &draw_parameters).unwrap();
+        let sky = skybox.as_ref().or(if environment.is_loaded() { Some(environment.get_cubemap()) } else { None });
+        let hdr_image = match &mut deferred_renderer {
+            Some(renderer) => {
+                renderer.render(&display, dimensions, &mut uniform, &lights.point_lights, &ssao_renderer, &draw_calls, &unlit_draw_calls, &program_lights, &draw_parameters);
+                let gbuffer = renderer.get_gbuffer().unwrap();
+                let mut framebuffer = glium::framebuffer::SimpleFrameBuffer::with_depth_buffer(&display, gbuffer.get_lit(), gbuffer.get_depth()).unwrap();
+                light_gizmo_renderer.render(&display, &mut framebuffer, &lights, &view, &perspective, &draw_parameters);
+                if let Some(sky) = sky {
+                    skybox_renderer.render(&mut framebuffer, sky, &view, &perspective);
+                }
+                transparency_renderer.render(&display, gbuffer.get_lit(), gbuffer.get_depth(), &mut uniform, &transparent_draw_calls, &draw_parameters);
+                gbuffer.get_lit()
+            },
+            None => {
+                // Light into a floating point target so bright lights do not clip before tone mapping
+                if hdr_target.as_ref().map_or(true, |hdr_target| hdr_target.get_dimensions() != dimensions) {
+                    hdr_target = Some(HdrTarget::new(&display, dimensions.0, dimensions.1));
+                }
+                let hdr_target = hdr_target.as_ref().unwrap();
+                let mut framebuffer = hdr_target.get_framebuffer(&display);
+
+                framebuffer.clear_color_and_depth((0.0, 0.0, 0.0, 1.0), 1.0); // Clear color and depth   
+                for draw_call in draw_calls.iter() {
+                    uniform.model = draw_call.model;
+                    uniform.normal_matrix = draw_call.normal_matrix;
+                    draw_call.draw(&mut framebuffer, &program, &uniform, &draw_parameters).unwrap();
+                }
+                for draw_call in unlit_draw_calls.iter() {
+                    uniform.model = draw_call.model;
+                    uniform.normal_matrix = draw_call.normal_matrix;
+                    draw_call.draw(&mut framebuffer, &program_lights, &uniform, &draw_parameters).unwrap();
+                }
+                light_gizmo_renderer.render(&display, &mut framebuffer, &lights, &view, &perspective, &draw_parameters);
+                if let Some(sky) = sky {
+                    skybox_renderer.render(&mut framebuffer, sky, &view, &perspective);
+                }
+                transparency_renderer.render(&display, hdr_target.get_color(), hdr_target.get_depth(), &mut uniform, &transparent_draw_calls, &draw_parameters);
+                hdr_target.get_color()
+            },
+        };
+        bloom_renderer.render(&display, hdr_image);
+        tone_mapper.render(&display, &mut post_process.get_framebuffer(&display, dimensions), hdr_image);
+        post_process.render(&display, &mut target);
         target.finish().unwrap();
 
         /*
@@ -179,13 +485,18 @@ fn main() {
         */
         let fps = 1f32 / (std::time::Instant::now() - last_dt).as_secs_f32();
         last_dt = std::time::Instant::now();
-        println!("{}", fps);
+        let culling_stats = frustum_culler.get_stats();
+        println!("{} fps, {} draw calls, {} culled", fps, culling_stats.visible, culling_stats.culled);
 
         /*
         Process events
         */
         let mut action = Action::Continue;
         let mut fullscreen_toggle_pressed: bool = false;
+        let mut tone_map_changed: bool = false;
+        let mut post_process_toggle: Option<&str> = None;
+        let mut pick_pressed: bool = false;
+        let mut delete_pressed: bool = false;
         for event in events {
             match event {
                 glutin::event::Event::DeviceEvent { event, .. } => {
@@ -195,12 +506,64 @@ fn main() {
                     fps_camera.process_input(&event);
                     match event {
                         glutin::event::WindowEvent::CloseRequested => action = Action::Stop,
+                        glutin::event::WindowEvent::MouseInput { state: glutin::event::ElementState::Pressed, button: glutin::event::MouseButton::Left, .. } => {
+                            pick_pressed = true;
+                        }
                         glutin::event::WindowEvent::KeyboardInput { input, .. } => match input.state {
                             glutin::event::ElementState::Pressed => match input.virtual_keycode {
                                 Some(glutin::event::VirtualKeyCode::Escape) => action = Action::Stop,
                                 Some(glutin::event::VirtualKeyCode::F1) => {
                                     fullscreen_toggle_pressed = true;
                                 }
+                                Some(glutin::event::VirtualKeyCode::F2) => {
+                                    tone_mapper.operator = tone_mapper.operator.next();
+                                    tone_map_changed = true;
+                                }
+                                Some(glutin::event::VirtualKeyCode::F3) => {
+                                    tone_mapper.auto_exposure = !tone_mapper.auto_exposure;
+                                    tone_map_changed = true;
+                                }
+                                Some(glutin::event::VirtualKeyCode::F4) => {
+                                    bloom_renderer.enabled = !bloom_renderer.enabled;
+                                    println!("Bloom: {}", bloom_renderer.enabled);
+                                }
+                                Some(glutin::event::VirtualKeyCode::F5) => post_process_toggle = Some(FXAA),
+                                Some(glutin::event::VirtualKeyCode::F6) => post_process_toggle = Some(COLOR_GRADING),
+                                Some(glutin::event::VirtualKeyCode::F7) => post_process_toggle = Some(GRAYSCALE),
+                                Some(glutin::event::VirtualKeyCode::F8) => post_process_toggle = Some(CHROMATIC_ABERRATION),
+                                Some(glutin::event::VirtualKeyCode::F9) => post_process_toggle = Some(VIGNETTE),
+                                Some(glutin::event::VirtualKeyCode::F10) => post_process_toggle = Some(FILM_GRAIN),
+                                Some(glutin::event::VirtualKeyCode::F11) => {
+                                    ssao_renderer.enabled = !ssao_renderer.enabled;
+                                    println!("SSAO: {}", ssao_renderer.enabled);
+                                }
+                                Some(glutin::event::VirtualKeyCode::F12) => {
+                                    environment.enabled = !environment.enabled;
+                                    println!("Environment lighting: {}", environment.is_active());
+                                }
+                                Some(glutin::event::VirtualKeyCode::G) => {
+                                    light_gizmo_renderer.enabled = !light_gizmo_renderer.enabled;
+                                    println!("Light gizmos: {}", light_gizmo_renderer.enabled);
+                                }
+                                Some(glutin::event::VirtualKeyCode::C) => {
+                                    frustum_culler.enabled = !frustum_culler.enabled;
+                                    println!("Frustum culling: {}", frustum_culler.enabled);
+                                }
+                                Some(glutin::event::VirtualKeyCode::T) => {
+                                    transparency_renderer.mode = transparency_renderer.mode.next();
+                                    println!("Transparency: {:?}", transparency_renderer.mode);
+                                }
+                                Some(glutin::event::VirtualKeyCode::Delete) => {
+                                    delete_pressed = true;
+                                }
+                                Some(glutin::event::VirtualKeyCode::PageUp) => {
+                                    tone_mapper.exposure *= 1.25;
+                                    tone_map_changed = true;
+                                }
+                                Some(glutin::event::VirtualKeyCode::PageDown) => {
+                                    tone_mapper.exposure /= 1.25;
+                                    tone_map_changed = true;
+                                }
                                 _ => (),
                             },
                             _ => (),
@@ -212,6 +575,32 @@ fn main() {
             }
         };
 
+        if tone_map_changed {
+            println!("Tone mapping: {:?}, exposure {}, automatic exposure {}", tone_mapper.operator, tone_mapper.exposure, tone_mapper.auto_exposure);
+        }
+
+        if let Some(name) = post_process_toggle {
+            if let Some(enabled) = post_process.toggle(name) {
+                println!("Post-processing {}: {}", name, enabled);
+            }
+        }
+
+        // The cursor is grabbed by the camera, so the crosshair in the middle of the screen picks
+        if pick_pressed {
+            let ray = Ray::from_screen(0.0, 0.0, &view, &perspective);
+            selected_node = scene_graph.cast_ray(&ray, 1024.0).map(|(id, distance)| {
+                println!("Selected {} at {:?}", get_node_path(&scene_graph, id), ray.get_point(distance));
+                return id;
+            });
+        }
+
+        if delete_pressed {
+            if let Some(id) = selected_node.take() {
+                println!("Removed {}", get_node_path(&scene_graph, id));
+                scene_graph.remove_node(id);
+            }
+        }
+
         if fullscreen_toggle_pressed {
             if is_fullscreen {
                 display.gl_window().window().set_fullscreen(None);
@@ -239,6 +628,20 @@ fn build_scene() -> AShape{
     return combine_shapes(scene);
 }
 
+/**
+ * Names of a node and its ancestors, separated by slashes
+ */
+fn get_node_path(scene_graph: &SceneGraph, id: NodeId) -> String {
+    let mut names: Vec<&str> = Vec::new();
+    let mut current = scene_graph.get(id);
+    while let Some(node) = current {
+        names.push(&node.name);
+        current = node.get_parent().and_then(|parent| scene_graph.get(parent));
+    }
+    names.reverse();
+    return names.join("/");
+}
+
 fn get_display(event_loop: &glutin::event_loop::EventLoop<()>) -> glium::Display {
     let wb = glutin::window::WindowBuilder::new()
     .with_title("3D Engine")
@@ -264,16 +667,6 @@ fn get_draw_parameters<'a>() -> glium::draw_parameters::DrawParameters<'a> {
     };
 }
 
-fn get_uniform(offset: &[f32; 3], scale: &[f32; 3], rotation: &[f32; 3]) -> [[f32; 4]; 4] {
-    let t = rotation[0];
-    return [
-        [1.0*scale[0]*t.cos(), 0.0, scale[0]*-t.sin(), 0.0],
-        [0.0, 1.0*scale[1], 0.0, 0.0],
-        [scale[2]*t.sin(), 0.0, 1.0*scale[2]*t.cos(), 0.0],
-        [offset[0], offset[1], offset[2], 1.0f32],
-    ]
-}
-
 fn get_perspective_matrix(target: &Frame) -> [[f32; 4]; 4] {
     let (width, height) = target.get_dimensions();
     let aspect_ratio = height as f32 / width as f32;
@@ -282,13 +675,7 @@ fn get_perspective_matrix(target: &Frame) -> [[f32; 4]; 4] {
     let zfar = 1024.0;
     let znear = 0.01;
 
-    let f = 1.0 / (fov / 2.0).tan();
-    return [
-        [f * aspect_ratio, 0.0, 0.0, 0.0],
-        [0.0, f, 0.0, 0.0],
-        [0.0, 0.0, (zfar + znear)/(zfar - znear), 1.0],
-        [0.0, 0.0, -(2.0 * zfar * znear)/(zfar - znear), 0.0],
-    ]
+    return perspective_matrix(fov, aspect_ratio, znear, zfar);
 }
 
 fn start_loop<F>(event_loop: glutin::event_loop::EventLoop<()>, mut callback: F)->! where F: 'static + FnMut(&Vec<glutin::event::Event<'_, ()>>) -> Action {